@@ -0,0 +1,44 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/*
+ * ----------------------------------------------
+ * |    CLOCKS                                  |
+ * ----------------------------------------------
+ *
+ * Surveys are open between the times in their descriptor. Whoever enforces that (a user about to
+ * submit, or the SA's submission store) reads the time from a Clock, so that tests and replays
+ * can fix the time instead of depending on the system clock.
+*/
+
+/// Source of the current time, in Unix seconds
+pub trait Clock {
+    fn now(&self) -> u64;
+}
+
+/// The system's wall clock
+#[derive(Copy, Clone, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> u64 {
+        // A system clock set before 1970 is treated as the epoch itself
+        SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+    }
+}
+
+/// Clock stopped at a given time
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct FixedClock(pub u64);
+
+impl Clock for FixedClock {
+    fn now(&self) -> u64 {
+        self.0
+    }
+}
+
+/// Any function returning the time, e.g. a clock shared with and advanced by a test
+impl<F: Fn() -> u64> Clock for F {
+    fn now(&self) -> u64 {
+        self()
+    }
+}
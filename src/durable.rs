@@ -0,0 +1,22 @@
+use std::fs::File;
+use std::path::Path;
+use crate::error::Result;
+
+/*
+ * ----------------------------------------------
+ * |    DURABLE FILES                           |
+ * ----------------------------------------------
+ *
+ * Files that must survive a crash (registry snapshots, key files) are written next to their final
+ * name, synced and renamed over it. The rename itself only lasts once the directory holding the
+ * file is synced too, which is only possible (and needed) on Unix.
+*/
+
+/// Make a rename within dir durable
+pub(crate) fn sync_dir(dir: &Path) -> Result<()> {
+    #[cfg(unix)]
+    File::open(dir)?.sync_all()?;
+    #[cfg(not(unix))]
+    let _ = dir;
+    Ok(())
+}
@@ -0,0 +1,810 @@
+extern crate tbn;
+
+use tbn::{Group, Fq, Fq2, Fr, G1, G2, Gt, AffineG1, AffineG2};
+use tbn::arith::U256;
+use crate::users::{VerificationKey, MasterCredential, Submission};
+use crate::zkp::{SurveyProof, KeySignature};
+use crate::survey::{ParticipantList, SurveyDescriptor, SignedDescriptor};
+use crate::schema::{Question, QuestionKind};
+use crate::params::Fingerprint;
+use crate::error::{Error, Result};
+
+/*
+ * ----------------------------------------------
+ * |    CANONICAL BINARY ENCODING               |
+ * ----------------------------------------------
+ *
+ * Every public protocol object is encoded as
+ *      version (u8) || type tag (u8) || body length (u32) || body
+ * with all integers big-endian. Inside the body:
+ *      - Fr and Fq elements are 32 bytes in normal (non-Montgomery) form, and must be reduced
+ *      - G1 points are compressed to 33 bytes: 0x02/0x03 (y even/odd) || x
+ *      - G2 points are compressed to 65 bytes: 0x0a/0x0b (smaller/larger y) || x.real || x.imaginary
+ *      - the point at infinity is a 0x00 flag followed by zeros
+ *      - Gt elements are their 12 Fq coefficients (384 bytes)
+ *      - strings and lists are prefixed by their length (u32)
+ * Decoding rejects anything that is not the unique canonical encoding of a valid value,
+ * including points that are not on the curve or not in the prime order subgroup.
+*/
+
+/// Version written at the start of every top-level encoding
+pub const ENCODING_VERSION: u8 = 1;
+
+const FIELD_BYTES: usize = 32;
+/// Size of a compressed G1 point
+pub const G1_BYTES: usize = 1 + FIELD_BYTES;
+/// Size of a compressed G2 point
+pub const G2_BYTES: usize = 1 + 2 * FIELD_BYTES;
+/// Size of an encoded Gt element
+pub const GT_BYTES: usize = 12 * FIELD_BYTES;
+
+/// Values that can be written as part of a canonical encoding
+pub trait Encode {
+    /// Append the canonical encoding of self (without any header)
+    fn encode_to(&self, out: &mut Vec<u8>);
+}
+
+/// Values that can be read back from their canonical encoding
+pub trait Decode: Sized {
+    /// Read a value, rejecting anything but a canonical encoding of a valid value
+    fn decode_from(reader: &mut Reader) -> Result<Self>;
+}
+
+/// Top-level protocol objects, encoded with a version and type header
+pub trait Canonical: Encode + Decode {
+    /// Type tag distinguishing this object from others with a similar layout
+    const TAG: u8;
+
+    /// Versioned, length-prefixed canonical encoding
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut body:Vec<u8> = Vec::new();
+        self.encode_to(&mut body);
+
+        let mut out:Vec<u8> = vec![ENCODING_VERSION, Self::TAG];
+        (body.len() as u32).encode_to(&mut out);
+        out.extend_from_slice(&body);
+        out
+    }
+
+    /// Decode an object produced by to_bytes
+    fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let mut reader = Reader::new(bytes);
+        if reader.read_u8()? != ENCODING_VERSION || reader.read_u8()? != Self::TAG {
+            return Err(Error::MalformedEncoding);
+        }
+        let len:usize = u32::decode_from(&mut reader)? as usize;
+        if len != reader.remaining() {
+            return Err(Error::MalformedEncoding);
+        }
+        let value = Self::decode_from(&mut reader)?;
+        reader.finish()?;
+        Ok(value)
+    }
+}
+
+/// Cursor over an encoding being decoded
+#[derive(Copy, Clone)]
+pub struct Reader<'a> {
+    bytes: &'a [u8]
+}
+
+impl<'a> Reader<'a> {
+
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Reader { bytes }
+    }
+
+    /// Next n bytes of the encoding
+    pub fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        if n > self.bytes.len() {
+            return Err(Error::MalformedEncoding);
+        }
+        let (head, tail) = self.bytes.split_at(n);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.bytes.len()
+    }
+
+    /// Fails if there are trailing bytes
+    pub fn finish(&self) -> Result<()> {
+        match self.bytes.is_empty() {
+            true    => Ok(()),
+            false   => Err(Error::MalformedEncoding)
+        }
+    }
+}
+
+
+/*
+ * Integers, strings and lists
+ */
+
+impl Encode for u8 {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        out.push(*self);
+    }
+}
+
+impl Decode for u8 {
+    fn decode_from(reader: &mut Reader) -> Result<Self> {
+        reader.read_u8()
+    }
+}
+
+// A single byte, 0 or 1
+impl Encode for bool {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        (*self as u8).encode_to(out);
+    }
+}
+
+impl Decode for bool {
+    fn decode_from(reader: &mut Reader) -> Result<Self> {
+        match reader.read_u8()? {
+            0   => Ok(false),
+            1   => Ok(true),
+            _   => Err(Error::MalformedEncoding)
+        }
+    }
+}
+
+impl Encode for u32 {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_be_bytes());
+    }
+}
+
+impl Decode for u32 {
+    fn decode_from(reader: &mut Reader) -> Result<Self> {
+        let mut bytes:[u8; 4] = [0; 4];
+        bytes.copy_from_slice(reader.take(4)?);
+        Ok(u32::from_be_bytes(bytes))
+    }
+}
+
+impl Encode for u64 {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_be_bytes());
+    }
+}
+
+impl Decode for u64 {
+    fn decode_from(reader: &mut Reader) -> Result<Self> {
+        let mut bytes:[u8; 8] = [0; 8];
+        bytes.copy_from_slice(reader.take(8)?);
+        Ok(u64::from_be_bytes(bytes))
+    }
+}
+
+// Two's complement
+impl Encode for i64 {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_be_bytes());
+    }
+}
+
+impl Decode for i64 {
+    fn decode_from(reader: &mut Reader) -> Result<Self> {
+        Ok(u64::decode_from(reader)? as i64)
+    }
+}
+
+impl Encode for [u8; 32] {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(self);
+    }
+}
+
+impl Decode for [u8; 32] {
+    fn decode_from(reader: &mut Reader) -> Result<Self> {
+        let mut bytes:[u8; 32] = [0; 32];
+        bytes.copy_from_slice(reader.take(32)?);
+        Ok(bytes)
+    }
+}
+
+impl Encode for str {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        (self.len() as u32).encode_to(out);
+        out.extend_from_slice(self.as_bytes());
+    }
+}
+
+impl Encode for String {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        self.as_str().encode_to(out);
+    }
+}
+
+impl Decode for String {
+    fn decode_from(reader: &mut Reader) -> Result<Self> {
+        let len:usize = u32::decode_from(reader)? as usize;
+        String::from_utf8(reader.take(len)?.to_vec()).map_err(|_| Error::MalformedEncoding)
+    }
+}
+
+impl<T: Encode> Encode for [T] {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        (self.len() as u32).encode_to(out);
+        for item in self {
+            item.encode_to(out);
+        }
+    }
+}
+
+impl<T: Encode> Encode for Vec<T> {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        self.as_slice().encode_to(out);
+    }
+}
+
+impl<T: Decode> Decode for Vec<T> {
+    fn decode_from(reader: &mut Reader) -> Result<Self> {
+        let len:usize = u32::decode_from(reader)? as usize;
+        // Don't trust the length for preallocation: every item takes at least one byte
+        let mut items:Vec<T> = Vec::with_capacity(len.min(reader.remaining()));
+        for _ in 0..len {
+            items.push(T::decode_from(reader)?);
+        }
+        Ok(items)
+    }
+}
+
+
+/*
+ * Field and group elements
+ */
+
+fn u256_bytes(n: U256) -> [u8; FIELD_BYTES] {
+    let mut bytes:[u8; FIELD_BYTES] = [0; FIELD_BYTES];
+    n.to_big_endian(&mut bytes).expect("U256 is 32 bytes");
+    bytes
+}
+
+impl Encode for Fr {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&u256_bytes(self.into_u256()));
+    }
+}
+
+impl Decode for Fr {
+    fn decode_from(reader: &mut Reader) -> Result<Self> {
+        let n:U256 = U256::from_slice(reader.take(FIELD_BYTES)?).map_err(|_| Error::MalformedEncoding)?;
+        Fr::new(n).ok_or(Error::MalformedEncoding)
+    }
+}
+
+impl Encode for Fq {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&u256_bytes(self.into_u256()));
+    }
+}
+
+impl Decode for Fq {
+    fn decode_from(reader: &mut Reader) -> Result<Self> {
+        Fq::from_slice(reader.take(FIELD_BYTES)?).map_err(|_| Error::MalformedEncoding)
+    }
+}
+
+// Checks that the flag is zero and the rest of the encoding is all zeros
+fn read_infinity(flag: u8, rest: &[u8]) -> Result<()> {
+    match flag == 0 && rest.iter().all(|b| *b == 0) {
+        true    => Ok(()),
+        false   => Err(Error::MalformedEncoding)
+    }
+}
+
+impl Encode for G1 {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        match AffineG1::from_jacobian(*self) {
+            Some(affine) => {
+                let odd:bool = affine.y().into_u256().get_bit(0).expect("bit 0 always exists");
+                out.push(if odd { 0x03 } else { 0x02 });
+                affine.x().encode_to(out);
+            },
+            None => out.extend_from_slice(&[0; G1_BYTES])
+        }
+    }
+}
+
+impl Decode for G1 {
+    fn decode_from(reader: &mut Reader) -> Result<Self> {
+        let bytes:&[u8] = reader.take(G1_BYTES)?;
+        match bytes[0] {
+            // G1 has cofactor 1, so being on the curve is enough
+            0x02 | 0x03 => G1::from_compressed(bytes).map_err(|_| Error::MalformedEncoding),
+            flag => read_infinity(flag, &bytes[1..]).map(|_| G1::zero())
+        }
+    }
+}
+
+// Whether y is the larger of the two square roots y and -y, ordered by (imaginary, real)
+fn is_larger_root(y: Fq2) -> bool {
+    let neg:Fq2 = -y;
+    (y.imaginary().into_u256(), y.real().into_u256()) > (neg.imaginary().into_u256(), neg.real().into_u256())
+}
+
+impl Encode for G2 {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        match AffineG2::from_jacobian(*self) {
+            Some(affine) => {
+                out.push(if is_larger_root(affine.y()) { 0x0b } else { 0x0a });
+                affine.x().real().encode_to(out);
+                affine.x().imaginary().encode_to(out);
+            },
+            None => out.extend_from_slice(&[0; G2_BYTES])
+        }
+    }
+}
+
+impl Decode for G2 {
+    fn decode_from(reader: &mut Reader) -> Result<Self> {
+        let flag:u8 = reader.read_u8()?;
+        let mut coords = Reader::new(reader.take(G2_BYTES - 1)?);
+        if flag != 0x0a && flag != 0x0b {
+            return read_infinity(flag, coords.take(G2_BYTES - 1)?).map(|_| G2::zero());
+        }
+        let x:Fq2 = Fq2::new(Fq::decode_from(&mut coords)?, Fq::decode_from(&mut coords)?);
+
+        let mut y:Fq2 = (x * x * x + G2::b()).sqrt().ok_or(Error::MalformedEncoding)?;
+        if is_larger_root(y) != (flag == 0x0b) {
+            y = -y;
+        }
+        // Checks that the point is on the twist and in the prime order subgroup
+        AffineG2::new(x, y).map(G2::from).map_err(|_| Error::MalformedEncoding)
+    }
+}
+
+// tbn does not expose the coefficients of Gt elements, so they are read straight out of (and
+// written back into) its representation.
+// SAFETY: Gt is a #[repr(C)] wrapper of Fq12, which is (through #[repr(C)] Fq6 and Fq2) laid
+// out as exactly 12 #[repr(C)] Fq coefficients, so both types have the same size and layout
+// and every array of valid Fq coefficients is a valid Fq12. This holds for the tbn release pinned
+// in Cargo.toml, and test_gt_layout checks it.
+fn gt_coefficients(x: Gt) -> [Fq; 12] {
+    unsafe { std::mem::transmute::<Gt, [Fq; 12]>(x) }
+}
+
+fn gt_from_coefficients(coeffs: [Fq; 12]) -> Gt {
+    unsafe { std::mem::transmute::<[Fq; 12], Gt>(coeffs) }
+}
+
+impl Encode for Gt {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        for coeff in gt_coefficients(*self).iter() {
+            coeff.encode_to(out);
+        }
+    }
+}
+
+impl Decode for Gt {
+    fn decode_from(reader: &mut Reader) -> Result<Self> {
+        let mut coeffs:[Fq; 12] = [Fq::zero(); 12];
+        for coeff in coeffs.iter_mut() {
+            *coeff = Fq::decode_from(reader)?;
+        }
+        let x:Gt = gt_from_coefficients(coeffs);
+
+        // Subgroup check: x^q = x^(q-1) * x = 1
+        match x.pow(-Fr::one()) * x == Gt::one() {
+            true    => Ok(x),
+            false   => Err(Error::MalformedEncoding)
+        }
+    }
+}
+
+
+/*
+ * Protocol objects
+ */
+
+impl Encode for VerificationKey {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        self.u.encode_to(out);
+        self.v.encode_to(out);
+        self.h.encode_to(out);
+        self.pk.encode_to(out);
+        self.params.encode_to(out);
+    }
+}
+
+impl Decode for VerificationKey {
+    fn decode_from(reader: &mut Reader) -> Result<Self> {
+        Ok(VerificationKey {
+            u: G1::decode_from(reader)?,
+            v: G1::decode_from(reader)?,
+            h: G1::decode_from(reader)?,
+            pk: Gt::decode_from(reader)?,
+            params: <[u8; 32]>::decode_from(reader)?
+        })
+    }
+}
+
+impl Canonical for VerificationKey {
+    const TAG: u8 = 0x10;
+}
+
+impl Encode for MasterCredential {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        self.sigma_1.encode_to(out);
+        self.sigma_2.encode_to(out);
+    }
+}
+
+impl Decode for MasterCredential {
+    fn decode_from(reader: &mut Reader) -> Result<Self> {
+        Ok(MasterCredential { sigma_1: G1::decode_from(reader)?, sigma_2: G2::decode_from(reader)? })
+    }
+}
+
+impl Canonical for MasterCredential {
+    const TAG: u8 = 0x11;
+}
+
+// Participant entry of a published survey list: (id, sigma_1, sigma_2)
+impl Encode for (Fr, G1, G2) {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        self.0.encode_to(out);
+        self.1.encode_to(out);
+        self.2.encode_to(out);
+    }
+}
+
+impl Decode for (Fr, G1, G2) {
+    fn decode_from(reader: &mut Reader) -> Result<Self> {
+        Ok((Fr::decode_from(reader)?, G1::decode_from(reader)?, G2::decode_from(reader)?))
+    }
+}
+
+impl Canonical for (Fr, G1, G2) {
+    const TAG: u8 = 0x12;
+}
+
+// Participant list: (version, epoch, entries), where entries must have distinct ids
+impl Encode for ParticipantList {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        self.version.encode_to(out);
+        self.epoch.encode_to(out);
+        self[..].encode_to(out);
+    }
+}
+
+impl Decode for ParticipantList {
+    fn decode_from(reader: &mut Reader) -> Result<Self> {
+        let (version, epoch):(u64, u64) = (u64::decode_from(reader)?, u64::decode_from(reader)?);
+        let entries:Vec<(Fr, G1, G2)> = Vec::decode_from(reader)?;
+        let count:usize = entries.len();
+        let mut list = ParticipantList::from(entries);
+        list.version = version;
+        list.epoch = epoch;
+        match list.len() == count {
+            true    => Ok(list),
+            false   => Err(Error::MalformedEncoding)
+        }
+    }
+}
+
+// Published survey list: (vid, participant entries)
+impl Encode for (Fr, ParticipantList) {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        self.0.encode_to(out);
+        self.1.encode_to(out);
+    }
+}
+
+impl Decode for (Fr, ParticipantList) {
+    fn decode_from(reader: &mut Reader) -> Result<Self> {
+        Ok((Fr::decode_from(reader)?, ParticipantList::decode_from(reader)?))
+    }
+}
+
+impl Canonical for (Fr, ParticipantList) {
+    const TAG: u8 = 0x13;
+}
+
+// Rotation of a registered id: (old id, new id)
+impl Encode for (Fr, Fr) {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        self.0.encode_to(out);
+        self.1.encode_to(out);
+    }
+}
+
+impl Decode for (Fr, Fr) {
+    fn decode_from(reader: &mut Reader) -> Result<Self> {
+        Ok((Fr::decode_from(reader)?, Fr::decode_from(reader)?))
+    }
+}
+
+// Question: (text, kind, required), where the kind is a tag byte followed by its parameters
+impl Encode for Question {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        self.text.encode_to(out);
+        match &self.kind {
+            QuestionKind::SingleChoice { options }      => { 0u8.encode_to(out); options.encode_to(out) },
+            QuestionKind::MultipleChoice { options }    => { 1u8.encode_to(out); options.encode_to(out) },
+            QuestionKind::Likert { points }             => { 2u8.encode_to(out); points.encode_to(out) },
+            QuestionKind::FreeText { max_len }          => { 3u8.encode_to(out); max_len.encode_to(out) },
+            QuestionKind::NumericRange { min, max }     => { 4u8.encode_to(out); min.encode_to(out); max.encode_to(out) }
+        }
+        self.required.encode_to(out);
+    }
+}
+
+impl Decode for Question {
+    fn decode_from(reader: &mut Reader) -> Result<Self> {
+        let text:String = String::decode_from(reader)?;
+        let kind:QuestionKind = match reader.read_u8()? {
+            0   => QuestionKind::SingleChoice { options: Vec::decode_from(reader)? },
+            1   => QuestionKind::MultipleChoice { options: Vec::decode_from(reader)? },
+            2   => QuestionKind::Likert { points: u8::decode_from(reader)? },
+            3   => QuestionKind::FreeText { max_len: u32::decode_from(reader)? },
+            4   => QuestionKind::NumericRange { min: i64::decode_from(reader)?, max: i64::decode_from(reader)? },
+            _   => return Err(Error::MalformedEncoding)
+        };
+        Ok(Question { text, kind, required: bool::decode_from(reader)? })
+    }
+}
+
+impl Encode for SurveyDescriptor {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        self.title.encode_to(out);
+        self.questions.encode_to(out);
+        self.opens_at.encode_to(out);
+        self.closes_at.encode_to(out);
+        self.min_responses.encode_to(out);
+        self.sa.encode_to(out);
+    }
+}
+
+impl Decode for SurveyDescriptor {
+    fn decode_from(reader: &mut Reader) -> Result<Self> {
+        Ok(SurveyDescriptor {
+            title: String::decode_from(reader)?,
+            questions: Vec::decode_from(reader)?,
+            opens_at: u64::decode_from(reader)?,
+            closes_at: u64::decode_from(reader)?,
+            min_responses: u32::decode_from(reader)?,
+            sa: Fingerprint::decode_from(reader)?
+        })
+    }
+}
+
+impl Canonical for SurveyDescriptor {
+    const TAG: u8 = 0x16;
+}
+
+impl Encode for KeySignature {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        self.c.encode_to(out);
+        self.z.encode_to(out);
+    }
+}
+
+impl Decode for KeySignature {
+    fn decode_from(reader: &mut Reader) -> Result<Self> {
+        Ok(KeySignature { c: Fr::decode_from(reader)?, z: Fr::decode_from(reader)? })
+    }
+}
+
+// Signed descriptor: (descriptor, SA's signature)
+impl Encode for SignedDescriptor {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        self.descriptor.encode_to(out);
+        self.signature.encode_to(out);
+    }
+}
+
+impl Decode for SignedDescriptor {
+    fn decode_from(reader: &mut Reader) -> Result<Self> {
+        Ok(SignedDescriptor { descriptor: SurveyDescriptor::decode_from(reader)?, signature: KeySignature::decode_from(reader)? })
+    }
+}
+
+impl Canonical for SignedDescriptor {
+    const TAG: u8 = 0x17;
+}
+
+impl Encode for SurveyProof {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        self.cred_1.encode_to(out);
+        self.cred_2.encode_to(out);
+        self.sig_1.encode_to(out);
+        self.sig_2.encode_to(out);
+        for z in &[self.c, self.z_id, self.z_seed, self.z_a, self.z_b] {
+            z.encode_to(out);
+        }
+    }
+}
+
+impl Decode for SurveyProof {
+    fn decode_from(reader: &mut Reader) -> Result<Self> {
+        Ok(SurveyProof {
+            cred_1: G1::decode_from(reader)?,
+            cred_2: G2::decode_from(reader)?,
+            sig_1: G1::decode_from(reader)?,
+            sig_2: G2::decode_from(reader)?,
+            c: Fr::decode_from(reader)?,
+            z_id: Fr::decode_from(reader)?,
+            z_seed: Fr::decode_from(reader)?,
+            z_a: Fr::decode_from(reader)?,
+            z_b: Fr::decode_from(reader)?
+        })
+    }
+}
+
+impl Canonical for SurveyProof {
+    const TAG: u8 = 0x14;
+}
+
+impl Encode for Submission {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        self.vid.encode_to(out);
+        self.epoch.encode_to(out);
+        self.token.encode_to(out);
+        self.response.encode_to(out);
+        self.proof.encode_to(out);
+    }
+}
+
+impl Decode for Submission {
+    fn decode_from(reader: &mut Reader) -> Result<Self> {
+        Ok(Submission {
+            vid: Fr::decode_from(reader)?,
+            epoch: u64::decode_from(reader)?,
+            token: G1::decode_from(reader)?,
+            response: String::decode_from(reader)?,
+            proof: SurveyProof::decode_from(reader)?
+        })
+    }
+}
+
+impl Canonical for Submission {
+    const TAG: u8 = 0x15;
+}
+
+
+/*
+ * Unit tests
+ */
+
+#[cfg(test)]
+use tbn::pairing;
+#[cfg(test)]
+use crate::users::SurveyAuthority;
+
+#[cfg(test)]
+// Encode a building block on its own and decode it back
+fn round_trip<T: Encode + Decode>(value: &T) -> Result<T> {
+    let mut bytes:Vec<u8> = Vec::new();
+    value.encode_to(&mut bytes);
+    let mut reader = Reader::new(&bytes);
+    let decoded = T::decode_from(&mut reader)?;
+    reader.finish()?;
+    Ok(decoded)
+}
+
+#[test]
+// Test that field and group elements survive a round trip, including the identity
+fn test_element_round_trip() {
+    let rng = &mut rand::thread_rng();
+    for _ in 0..10 {
+        let (x, p, q):(Fr, G1, G2) = (Fr::random(rng), G1::random(rng), G2::random(rng));
+        let e:Gt = pairing(p, q);
+        assert_eq!( round_trip(&x), Ok(x) );
+        assert_eq!( round_trip(&p), Ok(p) );
+        assert_eq!( round_trip(&q), Ok(q) );
+        assert!( round_trip(&e) == Ok(e) );
+    }
+    assert_eq!( round_trip(&G1::zero()), Ok(G1::zero()) );
+    assert_eq!( round_trip(&G2::zero()), Ok(G2::zero()) );
+    assert!( round_trip(&Gt::one()) == Ok(Gt::one()) );
+}
+
+#[test]
+// Test that Gt has the layout of 12 Fq coefficients, the identity's being 1 followed by zeros
+fn test_gt_layout() {
+    assert_eq!( std::mem::size_of::<Gt>(), std::mem::size_of::<[Fq; 12]>() );
+    assert_eq!( std::mem::align_of::<Gt>(), std::mem::align_of::<[Fq; 12]>() );
+    let mut one:[Fq; 12] = [Fq::zero(); 12];
+    one[0] = Fq::one();
+    assert!( gt_coefficients(Gt::one()) == one );
+    assert!( gt_from_coefficients(one) == Gt::one() );
+
+    // Pairings are unitary in Fq12 = Fq6[w], so inverting one negates its last 6 coefficients
+    let rng = &mut rand::thread_rng();
+    let e:Gt = pairing(G1::random(rng), G2::random(rng));
+    let mut conjugate:[Fq; 12] = gt_coefficients(e);
+    for coeff in conjugate[6..].iter_mut() {
+        *coeff = -*coeff;
+    }
+    assert!( e.inverse().map(gt_coefficients) == Some(conjugate) );
+}
+
+#[test]
+// Test that non-canonical or invalid elements are rejected
+fn test_element_rejects_invalid() {
+    let rng = &mut rand::thread_rng();
+
+    // Fr element not reduced modulo q
+    assert_eq!( Fr::decode_from(&mut Reader::new(&[0xff; FIELD_BYTES])), Err(Error::MalformedEncoding) );
+
+    // x coordinate with no point on the curve (x = 0 gives y^2 = 3, a non-residue for G1)
+    let mut bytes:Vec<u8> = vec![0x02];
+    bytes.extend_from_slice(&[0; FIELD_BYTES]);
+    assert_eq!( G1::decode_from(&mut Reader::new(&bytes)), Err(Error::MalformedEncoding) );
+
+    // Unknown flag byte
+    let mut bytes:Vec<u8> = Vec::new();
+    G1::random(rng).encode_to(&mut bytes);
+    bytes[0] = 0x04;
+    assert_eq!( G1::decode_from(&mut Reader::new(&bytes)), Err(Error::MalformedEncoding) );
+
+    // Point on the twist but outside the prime order subgroup: try x = 1, 2, ... until on curve
+    let mut x:Fq = Fq::one();
+    let y:Fq2 = loop {
+        let x2:Fq2 = Fq2::new(x, Fq::zero());
+        if let Some(y) = (x2 * x2 * x2 + G2::b()).sqrt() {
+            break y;
+        }
+        x = x + Fq::one();
+    };
+    let mut bytes:Vec<u8> = vec![if is_larger_root(y) { 0x0b } else { 0x0a }];
+    x.encode_to(&mut bytes);
+    Fq::zero().encode_to(&mut bytes);
+    assert_eq!( G2::decode_from(&mut Reader::new(&bytes)), Err(Error::MalformedEncoding) );
+
+    // Element of Fq12 outside of Gt
+    let mut bytes:Vec<u8> = Vec::new();
+    Fq::from_str("2").unwrap().encode_to(&mut bytes);
+    bytes.extend_from_slice(&[0; GT_BYTES - FIELD_BYTES]);
+    assert!( Gt::decode_from(&mut Reader::new(&bytes)) == Err(Error::MalformedEncoding) );
+}
+
+#[test]
+// Test that protocol objects survive a round trip and that headers and lengths are enforced
+fn test_object_round_trip() {
+    let (params, ra, sa, user, vid, signatures) = crate::zkp::setup_submission();
+    let survey:(Fr, ParticipantList) = (vid, signatures);
+    let submission:Submission = user.submit_survey(vid, "yes", &survey.1, &sa.vk, &ra.vk, &params).expect("Submission failed");
+    let cred:MasterCredential = user.credential.expect("No credential issued");
+
+    assert!( VerificationKey::from_bytes(&sa.vk.to_bytes()) == Ok(sa.vk) );
+    assert_eq!( MasterCredential::from_bytes(&cred.to_bytes()), Ok(cred) );
+    assert_eq!( <(Fr, G1, G2)>::from_bytes(&survey.1[0].to_bytes()), Ok(survey.1[0]) );
+    assert_eq!( <(Fr, ParticipantList)>::from_bytes(&survey.to_bytes()), Ok(survey.clone()) );
+    let descriptor = SurveyDescriptor::new("Lunch", crate::schema::sample_questions(), 0, 3600, &sa.vk);
+    assert_eq!( SurveyDescriptor::from_bytes(&descriptor.to_bytes()), Ok(descriptor.clone()) );
+    let signed:SignedDescriptor = sa.sign_descriptor(&descriptor, &params).expect("Signing failed");
+    assert_eq!( SignedDescriptor::from_bytes(&signed.to_bytes()), Ok(signed) );
+    // Booleans are a single 0 or 1 byte
+    assert_eq!( bool::decode_from(&mut Reader::new(&[2])), Err(Error::MalformedEncoding) );
+    // A participant listed twice is not a canonical list
+    let mut entries:Vec<(Fr, G1, G2)> = survey.1.to_vec();
+    entries.push(entries[0]);
+    let mut bytes:Vec<u8> = Vec::new();
+    survey.1.version.encode_to(&mut bytes);
+    survey.1.epoch.encode_to(&mut bytes);
+    entries.encode_to(&mut bytes);
+    assert_eq!( ParticipantList::decode_from(&mut Reader::new(&bytes)), Err(Error::MalformedEncoding) );
+    assert_eq!( SurveyProof::from_bytes(&submission.proof.to_bytes()), Ok(submission.proof) );
+    assert_eq!( Submission::from_bytes(&submission.to_bytes()), Ok(submission.clone()) );
+
+    let bytes:Vec<u8> = submission.to_bytes();
+    // Wrong version, wrong type, truncated and trailing bytes
+    let mut wrong_version:Vec<u8> = bytes.clone();
+    wrong_version[0] = ENCODING_VERSION + 1;
+    assert_eq!( Submission::from_bytes(&wrong_version), Err(Error::MalformedEncoding) );
+    assert_eq!( SurveyProof::from_bytes(&bytes), Err(Error::MalformedEncoding) );
+    assert_eq!( Submission::from_bytes(&bytes[..bytes.len() - 1]), Err(Error::MalformedEncoding) );
+    let mut trailing:Vec<u8> = bytes.clone();
+    trailing.push(0);
+    assert_eq!( Submission::from_bytes(&trailing), Err(Error::MalformedEncoding) );
+}
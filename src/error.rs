@@ -0,0 +1,103 @@
+extern crate hex;
+
+use std::fmt;
+use std::io;
+
+/// Reasons a protocol operation can fail
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The survey is not owned by this SA, or a submission names a different survey
+    UnknownSurvey,
+    /// The SA already owns a survey with this id
+    DuplicateSurvey,
+    /// A survey descriptor does not match the survey id or SA it is presented with
+    InvalidDescriptor,
+    /// The id was never registered with the RA (or has no registration in progress)
+    UnregisteredId,
+    /// The id is already registered with the RA (or has a registration in progress)
+    DuplicateId,
+    /// The RA has too many registrations in progress to start another
+    TooManyPending,
+    /// The user is not on the survey's list of participants
+    NotAuthorized,
+    /// Bytes could not be decoded into the expected value
+    MalformedEncoding,
+    /// A signature or credential does not verify under the expected key
+    InvalidSignature,
+    /// A zero-knowledge proof does not verify (or could not be produced)
+    InvalidProof,
+    /// Degenerate public parameters, or a key generated under different public parameters
+    InvalidParams,
+    /// Entries (by index) of a published survey list whose signatures do not verify
+    InvalidSurveyList(Vec<usize>),
+    /// A submission was made against a survey list the SA has since revoked participants from
+    StaleSurveyList,
+    /// A survey response does not answer the questions of the survey's descriptor
+    InvalidResponse,
+    /// The survey does not accept submissions yet
+    SurveyNotOpen,
+    /// The survey's closing time has passed, or the SA closed it
+    SurveyClosed,
+    /// Results are withheld until the survey is closed or its closing time has passed
+    SurveyStillOpen,
+    /// Results are withheld until the survey's minimum number of responses is reached
+    TooFewResponses { responses: usize, required: usize },
+    /// Reading or writing a file failed
+    Io(io::ErrorKind),
+    /// A key file could not be decrypted with the given passphrase (or was tampered with)
+    WrongPassphrase,
+    /// A key file can be accessed by users other than its owner
+    InsecureKeyFile,
+    /// Records (by index) of a submission log whose submissions do not verify
+    InvalidSubmissionLog(Vec<usize>),
+    /// The database failed, or holds a schema this version doesn't know
+    Database(String)
+}
+
+/// Result type used throughout the crate
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let msg = match self {
+            Error::InvalidSurveyList(bad) => return write!(f, "{} invalid signature(s) in survey list", bad.len()),
+            Error::InvalidSubmissionLog(bad) => return write!(f, "{} submission(s) in the log do not verify", bad.len()),
+            Error::TooFewResponses { responses, required } => return write!(f, "only {} of the {} responses needed to release results", responses, required),
+            Error::Io(kind)             => return write!(f, "i/o error: {:?}", kind),
+            Error::Database(msg)        => return write!(f, "database error: {}", msg),
+            Error::UnknownSurvey        => "unknown survey",
+            Error::DuplicateSurvey      => "survey id is already in use",
+            Error::InvalidDescriptor    => "survey descriptor does not match the survey",
+            Error::UnregisteredId       => "id is not registered with the RA",
+            Error::DuplicateId          => "id is already registered with the RA",
+            Error::TooManyPending       => "too many registrations in progress with the RA",
+            Error::NotAuthorized        => "user is not authorized to take the survey",
+            Error::MalformedEncoding    => "malformed encoding",
+            Error::InvalidSignature     => "invalid signature",
+            Error::InvalidProof         => "invalid zero-knowledge proof",
+            Error::InvalidParams        => "invalid or mismatched public parameters",
+            Error::StaleSurveyList      => "submission was made against an outdated survey list",
+            Error::InvalidResponse      => "response does not match the survey's questions",
+            Error::SurveyNotOpen        => "survey is not open for submissions yet",
+            Error::SurveyClosed         => "survey is closed",
+            Error::SurveyStillOpen      => "results are withheld until the survey closes",
+            Error::WrongPassphrase      => "wrong passphrase for key file",
+            Error::InsecureKeyFile      => "key file is accessible to other users"
+        };
+        write!(f, "{}", msg)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e.kind())
+    }
+}
+
+impl From<hex::FromHexError> for Error {
+    fn from(_: hex::FromHexError) -> Self {
+        Error::MalformedEncoding
+    }
+}
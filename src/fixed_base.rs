@@ -0,0 +1,159 @@
+extern crate tbn;
+
+use std::sync::Arc;
+use tbn::{Group, Fr, Fq, Fq2, G1, G2};
+use tbn::arith::U256;
+
+/*
+ * ----------------------------------------------
+ * |    FIXED-BASE MULTIPLICATION               |
+ * ----------------------------------------------
+ *
+ * Signing multiplies the same few bases (g, g2 and the bases of the keys) by a fresh scalar for
+ * every participant. A window table holds base * d * 16^i for every 4-bit digit d of every
+ * position i of a scalar, so a multiplication is one table lookup and addition per digit (64)
+ * instead of 256 doublings and additions. Tables are shared between clones.
+ *
+ * The scalars are secret (signing keys and signature randomness), so the lookup must not depend on
+ * them: every entry of a row is read and the digit's entry picked out with a mask, and an entry is
+ * added for every digit, including 0. To keep the additions away from the zero point, which tbn
+ * adds with a shortcut, every entry is offset by the curve's standard generator, and the 64 offsets
+ * are subtracted at the end. tbn's own field and group arithmetic is not constant-time, so this only
+ * keeps the table from adding a leak of its own.
+*/
+
+// Digits of 4 bits in a 256-bit scalar
+const WINDOWS: usize = 64;
+
+/// Group whose points can be read out as, and rebuilt from, a fixed number of limbs, so that a
+/// table entry can be picked without indexing by a secret digit
+pub trait Limbs: Group {
+    type Repr: Copy + Default + AsRef<[u128]> + AsMut<[u128]>;
+    fn to_limbs(&self) -> Self::Repr;
+    fn from_limbs(repr: &Self::Repr) -> Self;
+}
+
+impl Limbs for G1 {
+    type Repr = [u128; 6];
+
+    fn to_limbs(&self) -> [u128; 6] {
+        let mut repr:[u128; 6] = [0; 6];
+        for (limbs, coordinate) in repr.chunks_mut(2).zip([self.x(), self.y(), self.z()]) {
+            limbs.copy_from_slice(&coordinate.into_u256().0);
+        }
+        repr
+    }
+
+    fn from_limbs(repr: &[u128; 6]) -> Self {
+        G1::new(fq(&repr[0..2]), fq(&repr[2..4]), fq(&repr[4..6]))
+    }
+}
+
+impl Limbs for G2 {
+    type Repr = [u128; 12];
+
+    fn to_limbs(&self) -> [u128; 12] {
+        let mut repr:[u128; 12] = [0; 12];
+        let coordinates:[Fq; 6] = [self.x().real(), self.x().imaginary(), self.y().real(), self.y().imaginary(), self.z().real(), self.z().imaginary()];
+        for (limbs, coordinate) in repr.chunks_mut(2).zip(coordinates) {
+            limbs.copy_from_slice(&coordinate.into_u256().0);
+        }
+        repr
+    }
+
+    fn from_limbs(repr: &[u128; 12]) -> Self {
+        let fq2 = |limbs: &[u128]| Fq2::new(fq(&limbs[0..2]), fq(&limbs[2..4]));
+        G2::new(fq2(&repr[0..4]), fq2(&repr[4..8]), fq2(&repr[8..12]))
+    }
+}
+
+// Field element from the two limbs of a coordinate written by to_limbs
+fn fq(limbs: &[u128]) -> Fq {
+    Fq::from_u256(U256([limbs[0], limbs[1]])).expect("Limbs of a coordinate are reduced")
+}
+
+/// Precomputed window table for multiplying a fixed base by many scalars
+#[derive(Clone)]
+pub struct FixedBase<T: Limbs> {
+    base: T,
+    // windows[i][d] = base * d * 16^i + T::one()
+    windows: Arc<Vec<[T::Repr; 16]>>,
+    // T::one() * 64, the sum of the offsets of one entry per window
+    offset: T
+}
+
+impl<T: Limbs> FixedBase<T> {
+
+    /// Build the table for base (64 * 16 additions)
+    pub fn new(base: T) -> Self {
+        let mut windows:Vec<[T::Repr; 16]> = Vec::with_capacity(WINDOWS);
+        let mut offset:T = T::zero();
+        // base * 16^i
+        let mut power:T = base;
+        for _ in 0..WINDOWS {
+            let mut row:[T::Repr; 16] = [T::Repr::default(); 16];
+            let mut entry:T = T::one();
+            for limbs in row.iter_mut() {
+                *limbs = entry.to_limbs();
+                entry = entry + power;
+            }
+            // entry = base * 16^(i + 1) + T::one()
+            power = entry - T::one();
+            offset = offset + T::one();
+            windows.push(row);
+        }
+        FixedBase { base, windows: Arc::new(windows), offset }
+    }
+
+    pub fn base(&self) -> T {
+        self.base
+    }
+
+    /// base * x, reading every entry of the table whatever the digits of x
+    pub fn mul(&self, x: Fr) -> T {
+        let mut bytes:[u8; 32] = [0; 32];
+        x.into_u256().to_big_endian(&mut bytes).expect("Fr fits in 32 bytes");
+
+        let mut acc:T = T::zero();
+        // Least significant byte first: its low digit is window 0
+        for (i, byte) in bytes.iter().rev().enumerate() {
+            acc = acc + select(&self.windows[2 * i], byte & 0x0f);
+            acc = acc + select(&self.windows[2 * i + 1], byte >> 4);
+        }
+        acc - self.offset
+    }
+}
+
+// Entry digit of row, combining every entry under a mask rather than indexing by the digit
+fn select<T: Limbs>(row: &[T::Repr; 16], digit: u8) -> T {
+    let mut picked:T::Repr = T::Repr::default();
+    for (d, entry) in row.iter().enumerate() {
+        // All ones if d == digit, all zeros otherwise
+        let diff:u128 = std::hint::black_box((d as u8 ^ digit) as u128);
+        let mask:u128 = ((diff | diff.wrapping_neg()) >> 127).wrapping_sub(1);
+        for (limb, value) in picked.as_mut().iter_mut().zip(entry.as_ref()) {
+            *limb |= value & mask;
+        }
+    }
+    T::from_limbs(&picked)
+}
+
+
+/*
+ * Unit tests
+ */
+
+#[test]
+// Test that table multiplication agrees with ordinary scalar multiplication
+fn test_fixed_base() {
+    let rng = &mut rand::thread_rng();
+    let g:G1 = G1::random(rng);
+    let g2:G2 = G2::random(rng);
+    let (table, table2) = (FixedBase::new(g), FixedBase::new(g2));
+
+    for x in [Fr::zero(), Fr::one(), -Fr::one(), Fr::random(rng), Fr::random(rng)] {
+        assert!( table.mul(x) == g * x );
+        assert!( table2.mul(x) == g2 * x );
+    }
+    assert!( table.clone().base() == g );
+}
@@ -0,0 +1,119 @@
+extern crate tbn;
+extern crate sha2;
+
+use tbn::{Group, Fq, Fq2, G1, G2, AffineG1};
+use tbn::arith::U256;
+use sha2::{Sha512, Digest};
+
+/*
+ * ----------------------------------------------
+ * |    HASH TO CURVE                           |
+ * ----------------------------------------------
+ *
+ * Nothing-up-my-sleeve derivation of group elements from public strings, so that nobody
+ * (including whoever publishes them) knows a discrete logarithm relation between them.
+ * Uses try-and-increment: hash (domain, message, counter) to an x coordinate until x^3 + b is a
+ * square. G1 has cofactor 1, so any point on the curve will do; points on the G2 twist are
+ * multiplied by the twist's cofactor to land in the prime order subgroup.
+*/
+
+// Cofactor of the G2 twist, 2p - q
+const G2_COFACTOR: [u8; 32] = [
+    0x30, 0x64, 0x4e, 0x72, 0xe1, 0x31, 0xa0, 0x29, 0xb8, 0x50, 0x45, 0xb6, 0x81, 0x81, 0x58, 0x5e,
+    0x06, 0xce, 0xec, 0xda, 0x57, 0x2a, 0x24, 0x89, 0x34, 0x5f, 0x22, 0x99, 0xc0, 0xf9, 0xfa, 0x8d
+];
+
+// Element of Fq from SHA-512(domain || message || counter || index), reduced mod p
+fn hash_to_fq(domain: &[u8], msg: &[u8], counter: u32, index: u8) -> Fq {
+    let mut hasher = Sha512::new();
+    hasher.update((domain.len() as u32).to_be_bytes());
+    hasher.update(domain);
+    hasher.update((msg.len() as u32).to_be_bytes());
+    hasher.update(msg);
+    hasher.update(counter.to_be_bytes());
+    hasher.update([index]);
+
+    let mut digest:[u8; 64] = [0; 64];
+    digest.copy_from_slice(&hasher.finalize());
+    Fq::interpret(&digest)
+}
+
+/// Deterministically map a message to a point of G1 with unknown discrete logarithm
+pub fn hash_to_g1(domain: &[u8], msg: &[u8]) -> G1 {
+    let mut counter:u32 = 0;
+    loop {
+        let x:Fq = hash_to_fq(domain, msg, counter, 0);
+        if let Some(y) = (x * x * x + G1::b()).sqrt() {
+            if let Ok(point) = AffineG1::new(x, y) {
+                return point.into();
+            }
+        }
+        counter += 1;
+    }
+}
+
+/// Deterministically map a message to a point of G2 with unknown discrete logarithm
+pub fn hash_to_g2(domain: &[u8], msg: &[u8]) -> G2 {
+    let cofactor:U256 = U256::from_slice(&G2_COFACTOR).expect("Cofactor is 32 bytes");
+    let mut counter:u32 = 0;
+    loop {
+        let x:Fq2 = Fq2::new(hash_to_fq(domain, msg, counter, 0), hash_to_fq(domain, msg, counter, 1));
+        if let Some(y) = (x * x * x + G2::b()).sqrt() {
+            // On the twist, but not necessarily in the prime order subgroup yet
+            let point:G2 = mul_u256(G2::new(x, y, Fq2::one()), cofactor);
+            if !point.is_zero() {
+                return point;
+            }
+        }
+        counter += 1;
+    }
+}
+
+// Double-and-add multiplication by a scalar that may exceed the group order
+fn mul_u256(point: G2, n: U256) -> G2 {
+    let mut acc:G2 = G2::zero();
+    for bit in n.bits() {
+        acc = acc + acc;
+        if bit {
+            acc = acc + point;
+        }
+    }
+    acc
+}
+
+/// Public bases (u, v, h) of a verification key, derived from a seed instead of at random
+pub fn derive_bases(seed: &str) -> (G1, G1, G1) {
+    (
+        hash_to_g1(b"anon_survey/base/u", seed.as_bytes()),
+        hash_to_g1(b"anon_survey/base/v", seed.as_bytes()),
+        hash_to_g1(b"anon_survey/base/h", seed.as_bytes())
+    )
+}
+
+
+/*
+ * Unit tests
+ */
+
+#[cfg(test)]
+use tbn::{Fr, AffineG2};
+
+#[test]
+// Test that hashing is deterministic, domain separated and lands in the right groups
+fn test_hash_to_curve() {
+    let p:G1 = hash_to_g1(b"test", b"seed");
+    assert_eq!( p, hash_to_g1(b"test", b"seed") );
+    assert!( !p.is_zero() );
+    assert!( p != hash_to_g1(b"test", b"seed2") && p != hash_to_g1(b"test2", b"seed") );
+
+    let q:G2 = hash_to_g2(b"test", b"seed");
+    assert_eq!( q, hash_to_g2(b"test", b"seed") );
+    assert!( q != hash_to_g2(b"test2", b"seed") );
+    // In the prime order subgroup: q * (order - 1) + q = 0, and accepted by the checked constructor
+    assert!( (q * (-Fr::one()) + q).is_zero() );
+    let affine = AffineG2::from_jacobian(q).expect("Point at infinity");
+    assert!( AffineG2::new(affine.x(), affine.y()).is_ok() );
+
+    let (u, v, h) = derive_bases("seed");
+    assert!( u != v && v != h && u != h );
+}
@@ -0,0 +1,450 @@
+extern crate serde;
+extern crate hex;
+
+use std::convert::TryFrom;
+use std::fmt;
+use serde::{Serialize, Serializer, Deserialize, Deserializer};
+use serde::de::{self, Visitor};
+use tbn::{Fr, G1, G2, Gt};
+use crate::encoding::{Encode, Decode, Reader};
+use crate::users::{VerificationKey, MasterCredential, Submission};
+use crate::zkp::{SurveyProof, KeySignature};
+use crate::survey::{ParticipantList, SurveyDescriptor, SignedDescriptor};
+use crate::schema::{Question, QuestionKind};
+use crate::params::{PublicParams, Fingerprint};
+use crate::error::Error;
+
+/*
+ * ----------------------------------------------
+ * |    JSON                                    |
+ * ----------------------------------------------
+ *
+ * With the serde feature, the public protocol objects have a JSON representation. Every field or
+ * group element is written as a lowercase hex string of its canonical encoding (see encoding.rs):
+ * 32 bytes for Fr, a 33 byte compressed point for G1, a 65 byte compressed point for G2 and 384
+ * bytes for Gt. Fingerprints are 32 byte hashes. The schema is:
+ *      PublicParams      { "g": G1, "g2": G2, "fingerprint": Fingerprint }
+ *      VerificationKey   { "u": G1, "v": G1, "h": G1, "pk": Gt, "params": Fingerprint }
+ *      MasterCredential  { "sigma_1": G1, "sigma_2": G2 }
+ *      SurveyDescriptor  { "title": string, "questions": [ Question, ... ], "opens_at": integer,
+ *                          "closes_at": integer, "min_responses": integer, "sa": Fingerprint }
+ *      Question          { "text": string, "kind": QuestionKind, "required": bool }
+ *      QuestionKind      { "type": "single_choice" | "multiple_choice", "options": [ string, ... ] }
+ *                        | { "type": "likert", "points": integer }
+ *                        | { "type": "free_text", "max_len": integer }
+ *                        | { "type": "numeric_range", "min": integer, "max": integer }
+ *      SignedDescriptor  { "descriptor": SurveyDescriptor, "signature": KeySignature }
+ *      KeySignature      { "c": Fr, "z": Fr }
+ *      PublishedSurvey   { "vid": Fr, "version": integer, "epoch": integer,
+ *                          "participants": [ { "id": Fr, "sigma_1": G1, "sigma_2": G2 }, ... ] }
+ *      SurveyProof       { "cred_1": G1, "cred_2": G2, "sig_1": G1, "sig_2": G2,
+ *                          "c": Fr, "z_id": Fr, "z_seed": Fr, "z_a": Fr, "z_b": Fr }
+ *      Submission        { "vid": Fr, "epoch": integer, "token": G1, "response": string,
+ *                          "proof": SurveyProof }
+ *
+ * Survey results (tally::TallyReport and tally::CrossTab) are plain numbers and strings, and
+ * serialize field for field, with each question's result tagged by its "type".
+ *
+ * Deserializing applies the same validity checks as the binary decoder, so a malformed or
+ * off-curve element fails to deserialize.
+*/
+
+/// Hex string of the canonical encoding of a field or group element
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Hex<T>(pub T);
+
+impl<T: Encode> Serialize for Hex<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut bytes:Vec<u8> = Vec::new();
+        self.0.encode_to(&mut bytes);
+        serializer.serialize_str(&hex::encode(bytes))
+    }
+}
+
+struct HexVisitor<T>(std::marker::PhantomData<T>);
+
+impl<'de, T: Decode> Visitor<'de> for HexVisitor<T> {
+    type Value = Hex<T>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a hex encoded element")
+    }
+
+    fn visit_str<E: de::Error>(self, s: &str) -> Result<Hex<T>, E> {
+        let bytes:Vec<u8> = hex::decode(s).map_err(E::custom)?;
+        let mut reader = Reader::new(&bytes);
+        T::decode_from(&mut reader)
+            .and_then(|value| reader.finish().map(|_| Hex(value)))
+            .map_err(E::custom)
+    }
+}
+
+impl<'de, T: Decode> Deserialize<'de> for Hex<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_str(HexVisitor(std::marker::PhantomData))
+    }
+}
+
+
+/*
+ * Protocol objects, (de)serialized through mirror structs with hex fields
+ */
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PublicParamsJson {
+    g: Hex<G1>,
+    g2: Hex<G2>,
+    fingerprint: Hex<Fingerprint>
+}
+
+impl Serialize for PublicParams {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        PublicParamsJson { g: Hex(self.g), g2: Hex(self.g2), fingerprint: Hex(self.fingerprint) }.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for PublicParams {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let json = PublicParamsJson::deserialize(deserializer)?;
+        let params = PublicParams::new(json.g.0, json.g2.0).map_err(de::Error::custom)?;
+        match params.fingerprint == json.fingerprint.0 {
+            true    => Ok(params),
+            false   => Err(de::Error::custom(Error::InvalidParams))
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct VerificationKeyJson {
+    u: Hex<G1>,
+    v: Hex<G1>,
+    h: Hex<G1>,
+    pk: Hex<Gt>,
+    params: Hex<Fingerprint>
+}
+
+impl Serialize for VerificationKey {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        VerificationKeyJson { u: Hex(self.u), v: Hex(self.v), h: Hex(self.h), pk: Hex(self.pk), params: Hex(self.params) }.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for VerificationKey {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let json = VerificationKeyJson::deserialize(deserializer)?;
+        Ok(VerificationKey { u: json.u.0, v: json.v.0, h: json.h.0, pk: json.pk.0, params: json.params.0 })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MasterCredentialJson {
+    sigma_1: Hex<G1>,
+    sigma_2: Hex<G2>
+}
+
+impl Serialize for MasterCredential {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        MasterCredentialJson { sigma_1: Hex(self.sigma_1), sigma_2: Hex(self.sigma_2) }.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for MasterCredential {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let json = MasterCredentialJson::deserialize(deserializer)?;
+        Ok(MasterCredential { sigma_1: json.sigma_1.0, sigma_2: json.sigma_2.0 })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum QuestionKindJson {
+    SingleChoice { options: Vec<String> },
+    MultipleChoice { options: Vec<String> },
+    Likert { points: u8 },
+    FreeText { max_len: u32 },
+    NumericRange { min: i64, max: i64 }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct QuestionJson {
+    text: String,
+    kind: QuestionKindJson,
+    required: bool
+}
+
+impl Serialize for Question {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let kind = match self.kind.clone() {
+            QuestionKind::SingleChoice { options }      => QuestionKindJson::SingleChoice { options },
+            QuestionKind::MultipleChoice { options }    => QuestionKindJson::MultipleChoice { options },
+            QuestionKind::Likert { points }             => QuestionKindJson::Likert { points },
+            QuestionKind::FreeText { max_len }          => QuestionKindJson::FreeText { max_len },
+            QuestionKind::NumericRange { min, max }     => QuestionKindJson::NumericRange { min, max }
+        };
+        QuestionJson { text: self.text.clone(), kind, required: self.required }.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Question {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let json = QuestionJson::deserialize(deserializer)?;
+        let kind = match json.kind {
+            QuestionKindJson::SingleChoice { options }      => QuestionKind::SingleChoice { options },
+            QuestionKindJson::MultipleChoice { options }    => QuestionKind::MultipleChoice { options },
+            QuestionKindJson::Likert { points }             => QuestionKind::Likert { points },
+            QuestionKindJson::FreeText { max_len }          => QuestionKind::FreeText { max_len },
+            QuestionKindJson::NumericRange { min, max }     => QuestionKind::NumericRange { min, max }
+        };
+        Ok(Question { text: json.text, kind, required: json.required })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SurveyDescriptorJson {
+    title: String,
+    questions: Vec<Question>,
+    opens_at: u64,
+    closes_at: u64,
+    min_responses: u32,
+    sa: Hex<Fingerprint>
+}
+
+impl Serialize for SurveyDescriptor {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        SurveyDescriptorJson {
+            title: self.title.clone(),
+            questions: self.questions.clone(),
+            opens_at: self.opens_at,
+            closes_at: self.closes_at,
+            min_responses: self.min_responses,
+            sa: Hex(self.sa)
+        }.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for SurveyDescriptor {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let json = SurveyDescriptorJson::deserialize(deserializer)?;
+        Ok(SurveyDescriptor {
+            title: json.title,
+            questions: json.questions,
+            opens_at: json.opens_at,
+            closes_at: json.closes_at,
+            min_responses: json.min_responses,
+            sa: json.sa.0
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct KeySignatureJson {
+    c: Hex<Fr>,
+    z: Hex<Fr>
+}
+
+impl Serialize for KeySignature {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        KeySignatureJson { c: Hex(self.c), z: Hex(self.z) }.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for KeySignature {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let json = KeySignatureJson::deserialize(deserializer)?;
+        Ok(KeySignature { c: json.c.0, z: json.z.0 })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SignedDescriptorJson {
+    descriptor: SurveyDescriptor,
+    signature: KeySignature
+}
+
+impl Serialize for SignedDescriptor {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        SignedDescriptorJson { descriptor: self.descriptor.clone(), signature: self.signature }.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for SignedDescriptor {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let json = SignedDescriptorJson::deserialize(deserializer)?;
+        Ok(SignedDescriptor { descriptor: json.descriptor, signature: json.signature })
+    }
+}
+
+/// Participant entry of a published survey: the SA's signature (sigma_1, sigma_2) on (signed_vid(vid, epoch), id)
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Participant {
+    pub id: Hex<Fr>,
+    pub sigma_1: Hex<G1>,
+    pub sigma_2: Hex<G2>
+}
+
+/// JSON form of the `(vid, Lvid)` pair returned by `gen_survey`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PublishedSurvey {
+    pub vid: Hex<Fr>,
+    pub version: u64,
+    pub epoch: u64,
+    pub participants: Vec<Participant>
+}
+
+impl From<(Fr, ParticipantList)> for PublishedSurvey {
+    fn from(survey: (Fr, ParticipantList)) -> Self {
+        PublishedSurvey {
+            vid: Hex(survey.0),
+            version: survey.1.version,
+            epoch: survey.1.epoch,
+            participants: survey.1.iter()
+                .map(|(id, sigma_1, sigma_2)| Participant { id: Hex(*id), sigma_1: Hex(*sigma_1), sigma_2: Hex(*sigma_2) })
+                .collect()
+        }
+    }
+}
+
+// Like the binary decoder, rejects a list that names an id twice
+impl TryFrom<PublishedSurvey> for (Fr, ParticipantList) {
+    type Error = Error;
+
+    fn try_from(survey: PublishedSurvey) -> Result<Self, Error> {
+        let mut list:ParticipantList = survey.participants.iter().map(|part| (part.id.0, part.sigma_1.0, part.sigma_2.0)).collect();
+        list.version = survey.version;
+        list.epoch = survey.epoch;
+        match list.len() == survey.participants.len() {
+            true    => Ok((survey.vid.0, list)),
+            false   => Err(Error::MalformedEncoding)
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SurveyProofJson {
+    cred_1: Hex<G1>,
+    cred_2: Hex<G2>,
+    sig_1: Hex<G1>,
+    sig_2: Hex<G2>,
+    c: Hex<Fr>,
+    z_id: Hex<Fr>,
+    z_seed: Hex<Fr>,
+    z_a: Hex<Fr>,
+    z_b: Hex<Fr>
+}
+
+impl Serialize for SurveyProof {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        SurveyProofJson {
+            cred_1: Hex(self.cred_1), cred_2: Hex(self.cred_2),
+            sig_1: Hex(self.sig_1), sig_2: Hex(self.sig_2),
+            c: Hex(self.c), z_id: Hex(self.z_id), z_seed: Hex(self.z_seed), z_a: Hex(self.z_a), z_b: Hex(self.z_b)
+        }.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for SurveyProof {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let json = SurveyProofJson::deserialize(deserializer)?;
+        Ok(SurveyProof {
+            cred_1: json.cred_1.0, cred_2: json.cred_2.0,
+            sig_1: json.sig_1.0, sig_2: json.sig_2.0,
+            c: json.c.0, z_id: json.z_id.0, z_seed: json.z_seed.0, z_a: json.z_a.0, z_b: json.z_b.0
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SubmissionJson {
+    vid: Hex<Fr>,
+    epoch: u64,
+    token: Hex<G1>,
+    response: String,
+    proof: SurveyProof
+}
+
+impl Serialize for Submission {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        SubmissionJson { vid: Hex(self.vid), epoch: self.epoch, token: Hex(self.token), response: self.response.clone(), proof: self.proof }.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Submission {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let json = SubmissionJson::deserialize(deserializer)?;
+        Ok(Submission { vid: json.vid.0, epoch: json.epoch, token: json.token.0, response: json.response, proof: json.proof })
+    }
+}
+
+
+/*
+ * Unit tests
+ */
+
+#[cfg(test)]
+use crate::users::SurveyAuthority;
+
+#[test]
+// Test that published artifacts survive a JSON round trip and invalid elements are rejected
+fn test_json_round_trip() {
+    let (params, ra, sa, user, vid, signatures) = crate::zkp::setup_submission();
+    let submission:Submission = user.submit_survey(vid, "yes", &signatures, &sa.vk, &ra.vk, &params).expect("Submission failed");
+
+    let json:String = serde_json::to_string(&params).unwrap();
+    assert!( serde_json::from_str::<PublicParams>(&json).unwrap() == params );
+
+    let json:String = serde_json::to_string(&sa.vk).unwrap();
+    assert!( serde_json::from_str::<VerificationKey>(&json).unwrap() == sa.vk );
+
+    let cred:MasterCredential = user.credential.expect("No credential issued");
+    let json:String = serde_json::to_string(&cred).unwrap();
+    assert_eq!( serde_json::from_str::<MasterCredential>(&json).unwrap(), cred );
+
+    let survey = PublishedSurvey::from((vid, signatures.clone()));
+    let json:String = serde_json::to_string(&survey).unwrap();
+    let decoded = <(Fr, ParticipantList)>::try_from(serde_json::from_str::<PublishedSurvey>(&json).unwrap());
+    assert_eq!( decoded, Ok((vid, signatures)) );
+
+    let descriptor = SurveyDescriptor::new("Lunch", crate::schema::sample_questions(), 0, 3600, &sa.vk);
+    let signed:SignedDescriptor = sa.sign_descriptor(&descriptor, &params).expect("Signing failed");
+    let json:String = serde_json::to_string(&signed).unwrap();
+    assert_eq!( serde_json::from_str::<SignedDescriptor>(&json).unwrap(), signed );
+    let value:serde_json::Value = serde_json::to_value(&descriptor).unwrap();
+    assert_eq!( value["questions"][2]["kind"], serde_json::json!({ "type": "likert", "points": 5 }) );
+
+    let json:String = serde_json::to_string(&submission).unwrap();
+    assert_eq!( serde_json::from_str::<Submission>(&json).unwrap(), submission );
+
+    // Elements are hex strings of their canonical encoding
+    let value:serde_json::Value = serde_json::to_value(&submission).unwrap();
+    assert_eq!( value["token"].as_str().map(|s| s.len()), Some(2 * crate::encoding::G1_BYTES) );
+    assert_eq!( value["response"], "yes" );
+
+    // Token with an invalid point flag, and a token that is not hex
+    let mut bad:serde_json::Value = value.clone();
+    let token:&str = value["token"].as_str().unwrap();
+    bad["token"] = serde_json::Value::String(format!("05{}", &token[2..]));
+    assert!( serde_json::from_value::<Submission>(bad.clone()).is_err() );
+    bad["token"] = serde_json::Value::String(String::from("zz"));
+    assert!( serde_json::from_value::<Submission>(bad).is_err() );
+}
+
+#[test]
+// Test that a published survey listing an id twice is rejected rather than merged
+fn test_published_survey_rejects_duplicates() {
+    let (_, _, _, _, vid, signatures) = crate::zkp::setup_submission();
+    let mut survey = PublishedSurvey::from((vid, signatures));
+    let first:Participant = survey.participants[0];
+    survey.participants.push(first);
+    assert_eq!( <(Fr, ParticipantList)>::try_from(survey), Err(Error::MalformedEncoding) );
+}
@@ -0,0 +1,299 @@
+extern crate argon2;
+extern crate chacha20poly1305;
+extern crate zeroize;
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::Path;
+use argon2::{Argon2, Algorithm, Version, Params};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce, KeyInit};
+use chacha20poly1305::aead::{Aead, Payload};
+use zeroize::Zeroizing;
+use rand::{RngCore, CryptoRng};
+use tbn::Fr;
+use crate::users::VerificationKey;
+use crate::params::PublicParams;
+use crate::encoding::{Encode, Decode, Canonical, Reader};
+use crate::durable::sync_dir;
+use crate::error::{Error, Result};
+
+/*
+ * ----------------------------------------------
+ * |    KEY STORE                               |
+ * ----------------------------------------------
+ *
+ * With the keystore feature, Registration and Survey Authorities can keep their key pair in a key
+ * file. It holds the authority's verification key in the clear and its secret key encrypted with
+ * ChaCha20-Poly1305, under a key derived from a passphrase with Argon2id. The verification key,
+ * KDF parameters and salt are authenticated along with the secret key, so none of them can be
+ * swapped without the file failing to open. Files are written with owner-only (0o600) permissions,
+ * and refused on load if anyone else can read them or they are reached through a symbolic link.
+*/
+
+const SALT_BYTES: usize = 16;
+const NONCE_BYTES: usize = 12;
+// Upper bounds on the KDF costs a key file may ask for (1 GiB, 16 iterations, 16 lanes), so that
+// a tampered file can't make opening it exhaust memory or time before the tag is checked
+const MAX_M_COST: u32 = 1 << 20;
+const MAX_T_COST: u32 = 16;
+const MAX_P_COST: u32 = 16;
+
+/// Argon2id cost parameters: memory in KiB, iterations and lanes
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct KdfParams {
+    pub m_cost: u32,
+    pub t_cost: u32,
+    pub p_cost: u32
+}
+
+impl KdfParams {
+
+    /// Fails with MalformedEncoding unless Argon2 accepts the costs and none exceeds the bounds
+    /// key files are held to
+    pub fn check(&self) -> Result<()> {
+        Params::new(self.m_cost, self.t_cost, self.p_cost, Some(32)).map_err(|_| Error::MalformedEncoding)?;
+        match self.m_cost <= MAX_M_COST && self.t_cost <= MAX_T_COST && self.p_cost <= MAX_P_COST {
+            true    => Ok(()),
+            false   => Err(Error::MalformedEncoding)
+        }
+    }
+}
+
+// Argon2's recommended defaults (19 MiB, 2 iterations, 1 lane)
+impl Default for KdfParams {
+    fn default() -> Self {
+        KdfParams { m_cost: Params::DEFAULT_M_COST, t_cost: Params::DEFAULT_T_COST, p_cost: Params::DEFAULT_P_COST }
+    }
+}
+
+/// Authority key pair with the secret key encrypted under a passphrase
+#[derive(Clone, PartialEq)]
+pub struct KeyFile {
+    pub vk: VerificationKey,
+    pub kdf: KdfParams,
+    salt: Vec<u8>,
+    nonce: Vec<u8>,
+    // Encrypted secret key and authentication tag
+    ciphertext: Vec<u8>
+}
+
+impl KeyFile {
+
+    /// Encrypt the secret key sk of the authority with key vk under passphrase. Fails with
+    /// MalformedEncoding if the KDF costs are out of bounds (see KdfParams::check).
+    pub fn seal<R: RngCore + CryptoRng>(vk: &VerificationKey, sk: Fr, passphrase: &str, kdf: KdfParams, rng: &mut R) -> Result<Self> {
+        kdf.check()?;
+        let mut salt:Vec<u8> = vec![0; SALT_BYTES];
+        let mut nonce:Vec<u8> = vec![0; NONCE_BYTES];
+        rng.fill_bytes(&mut salt);
+        rng.fill_bytes(&mut nonce);
+
+        let mut file = KeyFile { vk: *vk, kdf, salt, nonce, ciphertext: Vec::new() };
+        let mut plaintext:Zeroizing<Vec<u8>> = Zeroizing::new(Vec::new());
+        sk.encode_to(&mut plaintext);
+        file.ciphertext = file.cipher(passphrase)?
+            .encrypt(Nonce::from_slice(&file.nonce), Payload { msg: &plaintext, aad: &file.associated_data() })
+            .map_err(|_| Error::MalformedEncoding)?;
+        Ok(file)
+    }
+
+    /// Decrypt the secret key. Fails with WrongPassphrase if the passphrase is wrong or the file was
+    /// tampered with, InvalidParams if the key belongs to other public parameters, and
+    /// MalformedEncoding if the secret key does not match the verification key.
+    pub fn open(&self, passphrase: &str, params: &PublicParams) -> Result<Fr> {
+        params.check_key(&self.vk)?;
+        let plaintext:Zeroizing<Vec<u8>> = Zeroizing::new(self.cipher(passphrase)?
+            .decrypt(Nonce::from_slice(&self.nonce), Payload { msg: &self.ciphertext, aad: &self.associated_data() })
+            .map_err(|_| Error::WrongPassphrase)?);
+
+        let mut reader = Reader::new(&plaintext);
+        let sk:Fr = Fr::decode_from(&mut reader)?;
+        reader.finish()?;
+        match params.gt.pow(sk) == self.vk.pk {
+            true    => Ok(sk),
+            false   => Err(Error::MalformedEncoding)
+        }
+    }
+
+    /// Write the key file to path, readable and writable by its owner only. The file is replaced
+    /// atomically, so a crash never leaves a truncated key behind.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path:&Path = path.as_ref();
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+
+        let mut options = OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = options.open(&tmp)?;
+        // An existing file keeps its permissions when opened
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            file.set_permissions(fs::Permissions::from_mode(0o600))?;
+        }
+        file.write_all(&self.to_bytes())?;
+        file.sync_all()?;
+        fs::rename(&tmp, path)?;
+        match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => sync_dir(dir),
+            _ => sync_dir(Path::new("."))
+        }
+    }
+
+    /// Read a key file written by save. Fails with InsecureKeyFile if anyone but its owner can
+    /// access it, or if path is a symbolic link.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path:&Path = path.as_ref();
+        // Permissions are checked on the file that was opened, which is then read
+        let mut file:File = File::open(path)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::{MetadataExt, PermissionsExt};
+            let (link, opened) = (fs::symlink_metadata(path)?, file.metadata()?);
+            // The opened file is the one at path, not one a link there points to
+            if link.file_type().is_symlink() || (link.dev(), link.ino()) != (opened.dev(), opened.ino()) {
+                return Err(Error::InsecureKeyFile);
+            }
+            if opened.permissions().mode() & 0o077 != 0 {
+                return Err(Error::InsecureKeyFile);
+            }
+        }
+        let mut bytes:Vec<u8> = Vec::new();
+        file.read_to_end(&mut bytes)?;
+        KeyFile::from_bytes(&bytes)
+    }
+
+    // Cipher keyed by the passphrase, stretched with Argon2id over the file's salt
+    fn cipher(&self, passphrase: &str) -> Result<ChaCha20Poly1305> {
+        let params = Params::new(self.kdf.m_cost, self.kdf.t_cost, self.kdf.p_cost, Some(32)).map_err(|_| Error::MalformedEncoding)?;
+        let mut key:Zeroizing<[u8; 32]> = Zeroizing::new([0; 32]);
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password_into(passphrase.as_bytes(), &self.salt, &mut *key)
+            .map_err(|_| Error::MalformedEncoding)?;
+        Ok(ChaCha20Poly1305::new(Key::from_slice(&*key)))
+    }
+
+    // Everything in the file besides the ciphertext
+    fn associated_data(&self) -> Vec<u8> {
+        let mut aad:Vec<u8> = b"anon_survey/keystore".to_vec();
+        self.vk.encode_to(&mut aad);
+        self.kdf.encode_to(&mut aad);
+        self.salt.encode_to(&mut aad);
+        aad
+    }
+}
+
+impl Encode for KdfParams {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        self.m_cost.encode_to(out);
+        self.t_cost.encode_to(out);
+        self.p_cost.encode_to(out);
+    }
+}
+
+// Costs are checked on decode, before a file's parameters are ever used
+impl Decode for KdfParams {
+    fn decode_from(reader: &mut Reader) -> Result<Self> {
+        let kdf = KdfParams { m_cost: u32::decode_from(reader)?, t_cost: u32::decode_from(reader)?, p_cost: u32::decode_from(reader)? };
+        kdf.check()?;
+        Ok(kdf)
+    }
+}
+
+// Key file: (vk, KDF parameters, salt, nonce, ciphertext)
+impl Encode for KeyFile {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        self.vk.encode_to(out);
+        self.kdf.encode_to(out);
+        self.salt.encode_to(out);
+        self.nonce.encode_to(out);
+        self.ciphertext.encode_to(out);
+    }
+}
+
+impl Decode for KeyFile {
+    fn decode_from(reader: &mut Reader) -> Result<Self> {
+        let file = KeyFile {
+            vk: VerificationKey::decode_from(reader)?,
+            kdf: KdfParams::decode_from(reader)?,
+            salt: Vec::decode_from(reader)?,
+            nonce: Vec::decode_from(reader)?,
+            ciphertext: Vec::decode_from(reader)?
+        };
+        match file.salt.len() == SALT_BYTES && file.nonce.len() == NONCE_BYTES {
+            true    => Ok(file),
+            false   => Err(Error::MalformedEncoding)
+        }
+    }
+}
+
+impl Canonical for KeyFile {
+    const TAG: u8 = 0x18;
+}
+
+
+/*
+ * Unit tests
+ */
+
+#[cfg(test)]
+use crate::users::{User, SurveyAuthority, RegistrationAuthority};
+
+#[test]
+// Test that RA and SA keys survive a restart, and only open with the right passphrase
+fn test_keystore() {
+    let params = PublicParams::generate();
+    let ra = RegistrationAuthority::new(&params);
+    let sa:User = SurveyAuthority::new(&params);
+    // Removed when dropped, even if an assertion fails
+    let dir = tempfile::tempdir().expect("Creating temporary directory failed");
+    let (ra_path, sa_path) = (dir.path().join("ra"), dir.path().join("sa"));
+
+    ra.save_keys(&ra_path, "correct horse").expect("Saving RA keys failed");
+    sa.save_keys(&sa_path, "battery staple").expect("Saving SA keys failed");
+
+    // Loading checks the secret key against the verification key, so the reloaded RA keeps
+    // issuing credentials under the same key
+    let mut reloaded = RegistrationAuthority::load_keys(&params, &ra_path, "correct horse").expect("Loading RA keys failed");
+    assert!( reloaded.vk == ra.vk );
+    let mut user = User::new();
+    assert!( user.reg_user(&mut reloaded).is_ok() );
+    let sa_reloaded:User = SurveyAuthority::load_keys(&params, &sa_path, "battery staple").expect("Loading SA keys failed");
+    assert!( sa_reloaded.vk == sa.vk );
+
+    assert_eq!( RegistrationAuthority::load_keys(&params, &ra_path, "wrong horse").map(|ra| ra.vk.u), Err(Error::WrongPassphrase) );
+    assert_eq!( RegistrationAuthority::load_keys(&PublicParams::generate(), &ra_path, "correct horse").map(|ra| ra.vk.u), Err(Error::InvalidParams) );
+
+    // Swapping in another authority's public key breaks the authentication
+    let mut swapped:KeyFile = KeyFile::load(&ra_path).expect("Loading key file failed");
+    swapped.vk = sa.vk;
+    assert_eq!( swapped.open("correct horse", &params), Err(Error::WrongPassphrase) );
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        assert_eq!( fs::metadata(&ra_path).unwrap().permissions().mode() & 0o777, 0o600 );
+        fs::set_permissions(&ra_path, fs::Permissions::from_mode(0o644)).unwrap();
+        assert!( KeyFile::load(&ra_path) == Err(Error::InsecureKeyFile) );
+        // A link to a private key file is refused too
+        let link = dir.path().join("link");
+        std::os::unix::fs::symlink(&sa_path, &link).unwrap();
+        assert!( KeyFile::load(&sa_path).is_ok() && KeyFile::load(&link) == Err(Error::InsecureKeyFile) );
+    }
+    assert!( KeyFile::load(dir.path().join("missing")) == Err(Error::Io(std::io::ErrorKind::NotFound)) );
+
+    // Out of range KDF costs are refused when sealing, and when reading a file that asks for them
+    let costly = KdfParams { m_cost: MAX_M_COST + 1, ..KdfParams::default() };
+    assert!( KeyFile::seal(&sa.vk, Fr::one(), "battery staple", costly, &mut rand::thread_rng()) == Err(Error::MalformedEncoding) );
+    let (mut tampered, mut vk_bytes):(Vec<u8>, Vec<u8>) = (Vec::new(), Vec::new());
+    swapped.encode_to(&mut tampered);
+    swapped.vk.encode_to(&mut vk_bytes);
+    // m_cost directly follows the verification key
+    tampered[vk_bytes.len()..vk_bytes.len() + 4].copy_from_slice(&u32::MAX.to_be_bytes());
+    assert!( KeyFile::decode_from(&mut Reader::new(&tampered)) == Err(Error::MalformedEncoding) );
+}
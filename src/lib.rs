@@ -0,0 +1,415 @@
+//! A (partial) implementation of the ANONIZE anonymous survey system.
+//!
+//! The protocol is split between three roles:
+//!  - the [`RegistrationAuthority`] (GenRA) registers users and issues each a master credential
+//!    through the interactive RegUser protocol ([`User::reg_user`]), keeping their ids in a
+//!    [`UserRegistry`] (in memory, or on disk with a [`FileRegistry`]),
+//!  - any [`User`] can act as a [`SurveyAuthority`] (GenSA) and authorize a list of user ids to
+//!    take a survey ([`SurveyAuthority::gen_survey`]), which anyone can verify with
+//!    [`authorized`], and later amend the list ([`SurveyAuthority::add_participants`],
+//!    [`SurveyAuthority::revoke_participants`]), optionally describing its questions in a
+//!    [`SurveyDescriptor`] that it signs ([`SurveyAuthority::sign_descriptor`]),
+//!  - registered, authorized users anonymously respond with [`User::submit_survey`], and the
+//!    SA validates responses with [`check`] and a [`SubmissionStore`], which keeps them in a
+//!    [`SubmissionBackend`] (in memory, or in a log with a [`FileStore`]), until the
+//!    survey's descriptor says it closes or the SA closes it ([`SurveyAuthority::close_survey`]),
+//!  - the stored responses are summarised with [`tally::tally`] and [`tally::cross_tab`].
+//!
+//! All keys are defined over the same [`PublicParams`]: a pair of generators (g, g2), generated
+//! once (at random, or auditably from a public seed with [`PublicParams::from_seed`]) and
+//! identified by a fingerprint that every verification key records.
+//!
+//! This code was written for educational purposes only and should not be used in production.
+
+extern crate tbn;
+extern crate rand;
+extern crate hex;
+
+pub mod error;
+pub mod params;
+pub mod clock;
+pub mod encoding;
+pub mod hash;
+pub mod fixed_base;
+mod durable;
+#[cfg(feature = "serde")]
+pub mod json;
+#[cfg(feature = "keystore")]
+pub mod keystore;
+pub mod registry;
+pub mod schema;
+pub mod survey;
+pub mod users;
+pub mod zkp;
+pub mod verifier;
+pub mod store;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+pub mod tally;
+
+pub use error::{Error, Result};
+pub use params::PublicParams;
+pub use clock::{Clock, SystemClock, FixedClock};
+pub use encoding::{Encode, Decode, Canonical};
+pub use registry::{UserRegistry, MemoryRegistry, FileRegistry};
+pub use schema::{Question, QuestionKind, Answer, Response};
+pub use survey::{FrKey, ParticipantList, SurveyList, SurveyDescriptor, SignedDescriptor};
+pub use users::{User, SurveyAuthority, RegistrationAuthority, VerificationKey, MasterCredential, RegistrationRequest, Submission};
+pub use verifier::{check, CheckOutcome, SubmissionStore};
+pub use store::{SubmissionBackend, MemoryStore, FileStore};
+
+use tbn::{Group, G1, G2, Gt, Fr, pairing, pairing_batch};
+use rand::{RngCore, CryptoRng};
+use tbn::arith::U256;
+
+/// Returns random generators (g, g2) in (G1, G2).
+///
+/// Because G1 and G2 are additive cyclic groups of prime order by construction of BN curves, it
+/// is sufficient to randomly choose non-zero elements in G1 and G2 to get g and g2.
+pub fn get_generator_pair() -> (G1, G2) {
+    get_generator_pair_with_rng(&mut rand::thread_rng())
+}
+
+/// get_generator_pair, drawing the generators from the given rng
+pub fn get_generator_pair_with_rng<R: RngCore + CryptoRng>(rng: &mut R) -> (G1, G2) {
+
+    // Generate random elements in G1 and G2
+    let (mut g, mut g2):(G1, G2) = (G1::random(rng), G2::random(rng));
+    // Ensure that g,g2 are both generators (i.e. non-zero in additive cyclic group of prime
+    // order)
+    while g.is_zero() {
+        g = G1::random(rng);
+    }
+    while g2.is_zero() {
+        g2 = G2::random(rng);
+    }
+
+    // Return generator pair
+    (g, g2)
+}
+
+/// Converts a U256 into its hex string encoding (excluding 0x).
+pub fn to_hex_string(n:U256) -> String {
+
+    let bytes = to_bytes(n);
+
+    // Return hex encoding of byte vector
+    hex::encode(bytes)
+}
+
+
+/// Iterates through the bits of a U256 and returns the byte vector in MSB order.
+pub fn to_bytes(n:U256) -> Vec<u8> {
+
+    let mut byte:u8 = 0;
+    let mut bytes:Vec<u8> = vec![];
+    for (iter, b) in n.bits().enumerate() {
+        let bit = b as u8;
+        // Finished whole byte -- save byte to vector and reset first
+        if iter % 8 == 0 {
+            bytes.push(byte);
+            byte = 0;
+        }
+        byte += bit * u8::pow(2, 7 - (iter % 8) as u32);
+    }
+    bytes.push(byte);
+
+    bytes
+}
+
+
+/// Authorized: anyone can test if a user is authorized to take a survey.
+///
+/// Looks up `id` in the survey's published list `Lvid` and checks the SA's signature on
+/// (`vid`, `id`) for the list's epoch under the SA's and RA's verification keys. Fails with NotAuthorized if `id` is
+/// not on the list, InvalidSignature if its signature does not verify and InvalidParams if either
+/// key was generated under other public parameters.
+#[allow(non_snake_case)]
+pub fn authorized(id:Fr, vid:Fr, Lvid:&ParticipantList, vk_sa:&VerificationKey, vk_ra:&VerificationKey, params:&PublicParams) -> Result<()> {
+
+    params.check_key(vk_sa)?;
+    params.check_key(vk_ra)?;
+    
+    // Find the participant signature corresponding to id
+    let (_, sigma_1, sigma_2) = Lvid.get(id).ok_or(Error::NotAuthorized)?;
+    let m:Fr = survey::signed_vid(vid, Lvid.epoch);
+    match pairing(*sigma_1, params.g2) == ( vk_sa.pk * pairing(vk_sa.u * m + vk_sa.v * id + vk_ra.h, *sigma_2) ) {
+        true    => Ok(()),
+        false   => Err(Error::InvalidSignature)
+    }
+}
+
+
+/// Verify every signature of a published survey list at once.
+///
+/// Rather than checking e(sigma_1, g2) = pk_SA * e(u^m * v^id * h_RA, sigma_2) for each entry,
+/// where m is signed_vid(vid, epoch) for the list's epoch
+/// (two pairings each), checks a random linear combination of all of them with a single
+/// multi-pairing. If the batch fails, it is bisected to find the bad entries, which are reported
+/// by index as InvalidSurveyList. Fails with InvalidParams if either key was generated under other
+/// public parameters.
+#[allow(non_snake_case)]
+pub fn verify_survey_list(vid:Fr, Lvid:&ParticipantList, vk_sa:&VerificationKey, vk_ra:&VerificationKey, params:&PublicParams) -> Result<()> {
+    verify_survey_list_with_rng(vid, Lvid, vk_sa, vk_ra, params, &mut rand::thread_rng())
+}
+
+/// verify_survey_list, drawing the random linear combination from the given rng
+#[allow(non_snake_case)]
+pub fn verify_survey_list_with_rng<R: RngCore + CryptoRng>(vid:Fr, Lvid:&ParticipantList, vk_sa:&VerificationKey, vk_ra:&VerificationKey,
+                                                           params:&PublicParams, rng:&mut R) -> Result<()> {
+
+    params.check_key(vk_sa)?;
+    params.check_key(vk_ra)?;
+
+    // Hoist the part of the signed message shared by all participants
+    let vid_val:G1 = vk_sa.u * survey::signed_vid(vid, Lvid.epoch) + vk_ra.h;
+    // Each entry, weighted by a random delta: (delta * sigma_1, -delta * message, sigma_2, delta)
+    let weighted:Vec<(G1, G1, G2, Fr)> = Lvid.iter().map(|(id, sigma_1, sigma_2)| {
+        let delta:Fr = Fr::random(rng);
+        (*sigma_1 * delta, (vid_val + vk_sa.v * *id) * (-delta), *sigma_2, delta)
+    }).collect();
+
+    let mut bad:Vec<usize> = Vec::new();
+    find_bad_entries(&weighted, 0, vk_sa.pk, params.g2, &mut bad);
+    match bad.is_empty() {
+        true    => Ok(()),
+        false   => Err(Error::InvalidSurveyList(bad))
+    }
+}
+
+// Check the batch of entries starting at index offset, bisecting it if it fails:
+//      e(sum delta_i * sigma_1_i, g2) * prod e(-delta_i * M_i, sigma_2_i) = pk^(sum delta_i)
+fn find_bad_entries(batch:&[(G1, G1, G2, Fr)], offset:usize, pk:Gt, g2:G2, bad:&mut Vec<usize>) {
+
+    if batch.is_empty() {
+        return;
+    }
+    let mut pairs:Vec<(G1, G2)> = Vec::with_capacity(batch.len() + 1);
+    let mut sigma_sum:G1 = G1::zero();
+    let mut delta_sum:Fr = Fr::zero();
+    for (sigma_1, message, sigma_2, delta) in batch {
+        sigma_sum = sigma_sum + *sigma_1;
+        delta_sum = delta_sum + *delta;
+        pairs.push((*message, *sigma_2));
+    }
+    pairs.push((sigma_sum, g2));
+
+    if pairing_batch(&pairs) == pk.pow(delta_sum) {
+        return;
+    }
+    if batch.len() == 1 {
+        bad.push(offset);
+        return;
+    }
+    let mid:usize = batch.len() / 2;
+    find_bad_entries(&batch[..mid], offset, pk, g2, bad);
+    find_bad_entries(&batch[mid..], offset + mid, pk, g2, bad);
+}
+
+
+
+/*
+ * Unit tests
+ */
+
+// Fuzzy test for if we have a good generator for pairing-based crypto
+#[test]
+fn test_generators() {
+
+    let (g, g2):(G1, G2) = get_generator_pair();
+    
+    // Try 5 different random values to see if assertion holds each time
+    // For random a and b, asserts that e(g^a, g_2^b) = e(g,g_2)^{ab} (RHS is generator for Gt)
+    let rng = &mut rand::thread_rng();
+    for _ in 0..5 {
+        let a = Fr::random(rng);
+        let b = Fr::random(rng);
+        assert!( pairing(g * a, g2 * b) == pairing(g, g2).pow(a * b) );
+    }
+}
+
+// TODO: Test U256 -> hex conversions
+
+#[test]
+// Test that Authorized tells apart missing participants and bad signatures
+fn test_authorized_errors() {
+
+    let params = PublicParams::generate();
+    let rng = &mut rand::thread_rng();
+    let ra = RegistrationAuthority::new(&params);
+    let mut sa:User = SurveyAuthority::new(&params);
+    let id = Fr::random(rng);
+    let (vid, mut signatures) = sa.gen_survey(&[id], &params, &ra.vk).expect("SA survey creation failed!");
+
+    assert_eq!( authorized(id, vid, &signatures, &sa.vk, &ra.vk, &params), Ok(()) );
+    assert_eq!( authorized(Fr::random(rng), vid, &signatures, &sa.vk, &ra.vk, &params), Err(Error::NotAuthorized) );
+    let (_, sigma_1, sigma_2) = signatures[0];
+    signatures.insert((id, sigma_1 + params.g, sigma_2));
+    assert_eq!( authorized(id, vid, &signatures, &sa.vk, &ra.vk, &params), Err(Error::InvalidSignature) );
+}
+
+#[test]
+// Test that batch verification accepts an honest list and pinpoints tampered entries
+fn test_verify_survey_list() {
+
+    let params = PublicParams::generate();
+    let rng = &mut rand::thread_rng();
+    let ra = RegistrationAuthority::new(&params);
+    let mut sa:User = SurveyAuthority::new(&params);
+    let ids:Vec<Fr> = (0..20).map(|_| Fr::random(rng)).collect();
+    let (vid, signatures) = sa.gen_survey(&ids, &params, &ra.vk).expect("SA survey creation failed!");
+
+    assert_eq!( verify_survey_list(vid, &signatures, &sa.vk, &ra.vk, &params), Ok(()) );
+    assert_eq!( verify_survey_list(vid, &ParticipantList::new(), &sa.vk, &ra.vk, &params), Ok(()) );
+    // Signatures are bound to the survey and the epoch
+    let first_two = ParticipantList::from(signatures[..2].to_vec());
+    assert_eq!( verify_survey_list(vid + Fr::one(), &first_two, &sa.vk, &ra.vk, &params), Err(Error::InvalidSurveyList(vec![0, 1])) );
+    let mut next_epoch = first_two.clone();
+    next_epoch.epoch += 1;
+    assert_eq!( verify_survey_list(vid, &next_epoch, &sa.vk, &ra.vk, &params), Err(Error::InvalidSurveyList(vec![0, 1])) );
+
+    let mut entries:Vec<(Fr, G1, G2)> = signatures.to_vec();
+    entries[3].1 = entries[3].1 + params.g;
+    entries[17].0 = Fr::random(rng);
+    assert_eq!( verify_survey_list(vid, &ParticipantList::from(entries), &sa.vk, &ra.vk, &params), Err(Error::InvalidSurveyList(vec![3, 17])) );
+}
+
+
+/*
+ * Integration tests
+ */
+
+
+/*
+ * Benchmark tests
+ */
+
+#[cfg(test)]
+// Time run on trials 0..n, printing the time of each (as "{label} i") and their mean, standard
+// deviation and total
+pub(crate) fn bench_trials<F: FnMut(usize)>(label: &str, n: usize, mut run: F) {
+
+    use std::time::{Duration, Instant};
+
+    assert!(n > 1);
+    let mut durs:Vec<Duration> = Vec::with_capacity(n);
+    for i in 0..n {
+        let start = Instant::now();
+        run(i);
+        durs.push(start.elapsed());
+        println!("{} {}:\t{:?}", label, i+1, durs[i]);
+    }
+    println!();
+    // Calculate mean
+    let sum:Duration = durs.iter().sum();
+    let mean:Duration = sum / (n as u32);
+    // Calculate standard deviation
+    let sum_of_diff:f64 = durs.iter().map(|dur| (dur.as_secs_f64() - mean.as_secs_f64()).powi(2)).sum();
+    let sd:f64 = (sum_of_diff / ((n as f64) - 1.0)).sqrt();
+
+    println!("Mean:\t\t{:?}", mean);
+    println!("Std Dev:\t{:?}s", sd);
+    println!("Total:\t\t{:?}", sum);
+}
+
+#[cfg(test)]
+// GenSurvey for num_users users, one at a time
+fn bench_user_gen_survey(num_users: usize) {
+
+    // Setup
+    let rng = &mut rand::thread_rng();
+    let params = PublicParams::generate();
+
+    let ra = RegistrationAuthority::new(&params);
+    let mut sa:User = SurveyAuthority::new(&params);
+    // Skip registering users -- we only care about user ids for generating survey
+    let userids:Vec<Fr> = (0..num_users).map(|_| Fr::random(rng)).collect();
+
+    println!("GenSurvey Benchmark Test ({} users)", num_users);
+    bench_trials("User", num_users, |i| {
+        sa.gen_survey(&[userids[i]], &params, &ra.vk).expect("SA survey creation failed!");
+    });
+}
+
+#[cfg(test)]
+// Authorized for each user of a num_users-participant survey
+fn bench_user_authorized(num_users: usize) {
+
+    // Setup
+    let rng = &mut rand::thread_rng();
+    let params = PublicParams::generate();
+
+    let ra = RegistrationAuthority::new(&params);
+    let mut sa:User = SurveyAuthority::new(&params);
+    // Skip registering users -- we only care about user ids for generating survey
+    let userids:Vec<Fr> = (0..num_users).map(|_| Fr::random(rng)).collect();
+
+    println!("Generating {} survey signatures...", userids.len());
+    let (vid, signatures):(Fr, ParticipantList) = sa.gen_survey(&userids, &params, &ra.vk).expect("SA survey creation failed!");
+
+    println!("User Authorized Benchmark Test ({} users)", num_users);
+    bench_trials("User", num_users, |i| {
+        let _ = authorized(userids[i], vid, &signatures, &sa.vk, &ra.vk, &params);
+    });
+}
+
+#[test]
+#[ignore]
+// Test GenSurvey for 30 users to get mean and standard deviation
+fn bench_30_user_gen_survey() {
+    bench_user_gen_survey(30);
+}
+
+#[test]
+#[ignore]
+// Test GenSurvey for 300 users to get mean and standard deviation
+fn bench_300_user_gen_survey() {
+    bench_user_gen_survey(300);
+}
+
+#[test]
+#[ignore]
+// Test Authorized for 30 users to get mean and standard deviation
+fn bench_30_user_authorized() {
+    bench_user_authorized(30);
+}
+
+#[test]
+#[ignore]
+// Test Authorized for 300 users to get mean and standard deviation
+fn bench_300_user_authorized() {
+    bench_user_authorized(300);
+}
+
+
+#[test]
+#[ignore]
+#[allow(non_snake_case, clippy::needless_range_loop)]
+// Test batch verification of a 300 user survey list against Authorized on every entry
+fn bench_300_user_verify_survey_list() {
+
+    use std::time::{Duration, Instant};
+
+    // Setup 
+    let rng = &mut rand::thread_rng();
+    let params = PublicParams::generate();
+
+    let ra = RegistrationAuthority::new(&params);
+    let mut sa:User = SurveyAuthority::new(&params);
+    const NUM_USERS:usize = 300;
+    let userids:Vec<Fr> = (0..NUM_USERS).map(|_| Fr::random(rng)).collect();
+    let (vid, signatures):(Fr, ParticipantList) = sa.gen_survey(&userids, &params, &ra.vk).expect("SA survey creation failed!");
+
+    println!("Survey List Verification Benchmark Test ({} users)", NUM_USERS);
+    let start = Instant::now();
+    for i in 0..NUM_USERS {
+        assert!( authorized(userids[i], vid, &signatures, &sa.vk, &ra.vk, &params).is_ok() );
+    }
+    let individual:Duration = start.elapsed();
+    println!("Authorized on every entry:\t{:?}", individual);
+
+    let start = Instant::now();
+    assert!( verify_survey_list(vid, &signatures, &sa.vk, &ra.vk, &params).is_ok() );
+    let batch:Duration = start.elapsed();
+    println!("Batch verification:\t\t{:?}", batch);
+}
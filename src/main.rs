@@ -1,71 +1,18 @@
 
+extern crate anon_survey;
 extern crate tbn;
 extern crate rand;
 extern crate hex;
 
-mod users;
-use users::{User, SurveyAuthority, RegistrationAuthority, VerificationKey};
+use anon_survey::{User, SurveyAuthority, RegistrationAuthority, Submission, SubmissionStore, PublicParams, ParticipantList, SurveyDescriptor, SignedDescriptor, Canonical, Error, Result};
+use anon_survey::{Question, QuestionKind, Answer, Response, Clock, SystemClock};
+use anon_survey::{authorized, verify_survey_list, to_hex_string};
+use anon_survey::tally::{tally, TallyReport, QuestionResult};
 
-use tbn::{Group, Fq, G1, Fq2, G2, Fr, pairing};
+use tbn::{Group, Fq, G1, Fq2, G2, Fr};
 use tbn::arith::U256;
 
-use hex::FromHex;
-
-// Returns generators (g, g2) in (G1, G2)
-// Because G1 and G2 are additive cyclic groups of prime order by construction of BN curves
-// It is sufficient to randomly choose elements in G1 and G2 to get g and g2
-fn get_generator_pair() -> (G1, G2) {
-    
-    // Crytpographiclaly secure thread-local rng
-    let rng = &mut rand::thread_rng();
-
-    // Generate random elements in G1 and G2
-    let (mut g, mut g2):(G1, G2) = (G1::random(rng), G2::random(rng));
-    // Ensure that g,g2 are both generators (i.e. non-zero in additive cyclic group of prime
-    // order)
-    while g.is_zero() {
-        g = G1::random(rng);
-    }
-    while g2.is_zero() {
-        g2 = G2::random(rng);
-    }
-
-    // Return generator pair
-    (g, g2)
-}
-
-// Convert U256 into hex string encoding (excluding 0x)
-fn to_hex_string(n:U256) -> String {
-
-    let bytes = to_bytes(n);
-
-    // Return hex encoding of byte vector
-    return hex::encode(bytes);
-}
-
-
-// Iterate through bits of U256 and return byte vector in MSB order
-fn to_bytes(n:U256) -> Vec<u8> {
-
-    let mut iter = 0;
-    let mut byte:u8 = 0;
-    let mut bytes:Vec<u8> = vec![];
-    for b in n.bits() {
-        let bit = b as u8;
-        // Finished whole byte -- save byte to vector and reset first
-        if iter % 8 == 0 {
-            bytes.push(byte);
-            byte = 0;
-        }
-        byte += bit * u8::pow(2, 7 - (iter % 8));
-        iter += 1;
-    }
-    bytes.push(byte);
-
-    return bytes;
-}
-
-fn main() {
+fn main() -> Result<()> {
     
     /* ------------------------------------------------------------------------------
      *                          Barreto-Naehrig (BN) Curves                         
@@ -90,8 +37,6 @@ fn main() {
 
     // Known q parameter (prime order of G1) for 256-bit BN curve (Kasamatsu et al., 2014)
     let q_hex = String::from("fffffffffffcf0cd46e5f25eee71a49e0cdc65fb1299921af62d536cd10b500d");
-    let q_slice = <[u8; BN_BYTES]>::from_hex(q_hex.clone()).expect("Could not decode q");
-    let q = U256::from_slice(&q_slice).expect("Could not convert q to U256"); 
     println!("\tq (prime order of G1, G2, and Gt) = 0x{}", q_hex);
 
     // TODO: Figure out what z does in G1 and G2
@@ -109,7 +54,7 @@ fn main() {
     println!("\tG2 = E'/𝔽_q2 is an additive cyclic subgroup of E(𝔽_{{p^k}}), where E' : y^2 = x^3 + b/xi\tmod p  is a twisted elliptic curve with:");
     let mut k_slice:[u8;BN_BYTES] = [0;BN_BYTES];
     k_slice[BN_BYTES-1] = 12;
-    let k:U256 = U256::from_slice(&k_slice).expect("Could not convert k to U256");
+    let k:U256 = U256::from_slice(&k_slice).map_err(|_| Error::MalformedEncoding)?;
         println!("\t\tk (embedding degree of G2) = {}", to_hex_string(k));
 
     println!("\t\t(x,y) ∈ E(𝔽_{{p^k}}), (base point):");
@@ -129,9 +74,14 @@ fn main() {
     println!("With these parameters, e returns a element in the multiplicative group Gt with the same order as G2");
     println!();
 
-    let (g, g2):(G1, G2) = get_generator_pair();
-    println!("g ∈ G1 (generator) = {:?}", g);
-    println!("g2 ∈ G2 (generator) = {:?}", g2);
+    // Generated once and published: everyone reloads the same parameters from their encoding, and
+    // can audit that they were hashed from the public seed rather than chosen
+    const PARAMS_SEED:&str = "anon_survey demo parameters";
+    let params:PublicParams = PublicParams::from_bytes(&PublicParams::from_seed(PARAMS_SEED).to_bytes())?;
+    println!("Generators derived from seed \"{}\": {}", PARAMS_SEED, params.is_derived_from(PARAMS_SEED));
+    println!("g ∈ G1 (generator) = {:?}", params.g);
+    println!("g2 ∈ G2 (generator) = {:?}", params.g2);
+    println!("Public parameter fingerprint = {}", hex::encode(params.fingerprint));
 
     // TODO: Figure out how to print elements of type Gt
 //    println!("\te(g, g2) ∈ Gt (generator) = {:?}", pairing(g, g2));
@@ -148,7 +98,7 @@ fn main() {
 
     // Instantiate new Registration Authority
     println!("Generating signature-verification key pair (x, vk_RA) for Registration Authority (RA)...");
-    let mut ra:RegistrationAuthority = RegistrationAuthority::new(g, g2);
+    let mut ra:RegistrationAuthority = RegistrationAuthority::new(&params);
     println!("sk_RA = x ∈ ℤ_q = (secret signature key)");
     println!("vk_RA.u ∈ G1 = {:?}", ra.vk.u);
     println!("vk_RA.v ∈ G1 = {:?}", ra.vk.v);
@@ -164,7 +114,7 @@ fn main() {
 
     // Instantiate new Survey Authority
     println!("Generating signature-verification key pair (y, vk_SA) for Survey Authority (SA)...");
-    let mut sa:User = SurveyAuthority::new(g, g2); 
+    let mut sa:User = SurveyAuthority::new(&params);
     println!("sk_SA = y ∈ ℤ_q = (secret signature key)");
     println!("vk_SA.u ∈ G1 = {:?}", sa.vk.u);
     println!("vk_SA.v ∈ G1 = {:?}", sa.vk.v);
@@ -187,16 +137,19 @@ fn main() {
     let mut userbase:Vec<User> = Vec::new();
     for _ in 0..5 {
         let mut new_user = User::new();
-        new_user.reg_user(&mut ra);
+        if let Err(e) = new_user.reg_user(&mut ra) {
+            println!("RA refused to register user: {}", e);
+        }
         userbase.push(new_user);
     }
     // Just for fun, some users will change their identities
-    userbase[0].re_identify(&mut ra);
-    userbase[3].re_identify(&mut ra);
+    userbase[0].re_identify(&mut ra)?;
+    userbase[3].re_identify(&mut ra)?;
+    println!("RA recorded {} id rotations", ra.rotations().len());
 
     println!("List of registered users:");
-    for id in &ra.userid_list { 
-        println!("User id ∈ ℤ_q : {:?}", *id);
+    for id in ra.registry.iter() { 
+        println!("User id ∈ ℤ_q : {:?}", id);
     }
     println!();
 
@@ -208,289 +161,123 @@ fn main() {
     // the RA.
     let rng = &mut rand::thread_rng();
     let unregistered_userid = Fr::random(rng);
-    let mut part_list:Vec<Fr> = ra.userid_list.clone();
+    let mut part_list:Vec<Fr> = ra.registry.ids();
     println!("Unregistered user with id ∈ ℤ_q : {:?}", unregistered_userid);
     part_list.push(unregistered_userid);
     println!();
 
+    // The survey id is derived from a descriptor of the survey, so it commits to its content
+    let now:u64 = SystemClock.now();
+    let questions:Vec<Question> = vec![
+        Question::new("Should we keep meeting on Fridays?", QuestionKind::SingleChoice { options: vec![String::from("Yes"), String::from("No")] }, true),
+        Question::new("Any other comments?", QuestionKind::FreeText { max_len: 500 }, false)
+    ];
+    // Results are only released once 3 participants responded, and per question once 3 answered it
+    let descriptor = SurveyDescriptor::new("Demo survey", questions, now, now + 7 * 24 * 3600, &sa.vk).with_min_responses(3);
+    let signed_descriptor:SignedDescriptor = sa.sign_descriptor(&descriptor, &params)?;
+
     println!("SA: Generating survey signatures for {} potential users...", part_list.len());
-    let (vid, signatures):(Fr, Vec<(Fr, G1, G2)>) = sa.gen_survey(&part_list, g, g2, &ra.vk).expect("SA survey creation failed!");
+    let (vid, signatures):(Fr, ParticipantList) = sa.gen_survey_from_descriptor(&descriptor, &part_list, &params, &ra.vk)?;
     println!("Ad-hoc survey generated:");
+    println!("\tTitle: {}", descriptor.title);
     println!("\tvid ∈ ℤ_q (survey ID) = {:?}", vid);
+    for question in &descriptor.questions {
+        println!("\tQuestion: {}", question.text);
+    }
+    print!("\tDescriptor signed by this SA, and vid derived from it... ");
+    match signed_descriptor.verify(vid, &sa.vk, &params) {
+        Ok(())  => println!("\u{2713}"),
+        Err(e)  => println!("\u{2717} ({})", e)
+    }
     println!("\tList of authorized users:");
     for (id, sigma_1, sigma_2) in &signatures {
         println!("\t\tParticipant id:\t{:?}", *id);
         println!();
         println!("\t\t\t(σ1, σ2) ∈ G1 × G2 (SA signature for participant) = ({:?}, {:?})", *sigma_1, *sigma_2);
         print!("\t\t\tAuthorized... ");
-        match authorized(*id, vid, &signatures, &sa.vk, &ra.vk, g2) {
-            true    => println!("\u{2713}"),    // Checkmark    (yes!)
-            false   => println!("\u{2717}")     // X mark       (no!)
+        match authorized(*id, vid, &signatures, &sa.vk, &ra.vk, &params) {
+            Ok(())  => println!("\u{2713}"),    // Checkmark    (yes!)
+            Err(e)  => println!("\u{2717} ({})", e)     // X mark       (no!)
         }
         println!();
     }
+    // Auditors can check the whole list at once
+    print!("\tWhole survey list verified... ");
+    match verify_survey_list(vid, &signatures, &sa.vk, &ra.vk, &params) {
+        Ok(())  => println!("\u{2713}"),
+        Err(e)  => println!("\u{2717} ({})", e)
+    }
     println!();
 
 
-    // TODO: Have all users run on separate threads for efficiency
-
-    println!();
-}
-
-
-// Anyone can test if a user is authorized to take a survey
-fn authorized(id:Fr, vid:Fr, Lvid:&Vec<(Fr, G1, G2)>, vk_sa:&VerificationKey, vk_ra:&VerificationKey, g2:G2) -> bool {
-    
-    // Search through list of participant signature to find the one corresponding to id
-    for (part_id, sigma_1, sigma_2) in Lvid {
-        if *part_id == id {
-            return pairing(*sigma_1, g2) == ( (*vk_sa).pk * pairing((*vk_sa).u * vid + (*vk_sa).v * id + (*vk_ra).h, *sigma_2) );
+    /* ------------------------------------------------------------------------------
+     *                                  SubmitSurvey                                       
+     * ------------------------------------------------------------------------------
+     */
+    // Every registered user anonymously responds to the survey
+    let mut submissions:Vec<Submission> = Vec::new();
+    for (i, user) in userbase.iter().enumerate() {
+        let response = Response::new(vec![Some(Answer::Choice((i % 2) as u32)), None]);
+        match user.submit_survey(vid, &response.to_string(), &signatures, &sa.vk, &ra.vk, &params) {
+            Ok(submission) => submissions.push(submission),
+            Err(e) => println!("User could not submit a response: {}", e)
         }
     }
-    false
-}
-
-
+    // Someone changes their mind and submits again with the same token
+    let changed = Response::new(vec![Some(Answer::Choice(0)), Some(Answer::Text(String::from("Thursdays work too")))]);
+    let resubmission = userbase[1].submit_survey(vid, &changed.to_string(), &signatures, &sa.vk, &ra.vk, &params)?;
+    let resubmitted_token:G1 = resubmission.token;
+    submissions.push(resubmission);
 
-/*
- * Unit tests
- */
-
-// Fuzzy test for if we have a good generator for pairing-based crypto
-#[test]
-fn test_generators() {
-
-    let (g, g2):(G1, G2) = get_generator_pair();
-    
-    // Try 5 different random values to see if assertion holds each time
-    // For random a and b, asserts that e(g^a, g_2^b) = e(g,g_2)^{ab} (RHS is generator for Gt)
-    let rng = &mut rand::thread_rng();
-    for _ in 0..5 {
-        let a = Fr::random(rng);
-        let b = Fr::random(rng);
-        assert!( pairing(g * a, g2 * b) == pairing(g, g2).pow(a * b) );
+    /* ------------------------------------------------------------------------------
+     *                                  Check
+     * ------------------------------------------------------------------------------
+     */
+    println!("Anonymous submissions received by SA:");
+    let mut store = SubmissionStore::for_descriptor(descriptor.clone());
+    for submission in submissions {
+        println!("\tSurvey token ∈ G1 = {:?}", submission.token);
+        println!("\t\tResponse: {:?}", submission.response);
+        println!("\t\tCheck: {:?}", store.check(submission, &sa.vk, &ra.vk, &params));
     }
-}
-
-// TODO: Test U256 -> hex conversions
-
-
-/*
- * Integration tests
- */
-
-
-/*
- * Benchmark tests
- */
-
-#[test]
-#[allow(non_snake_case)]
-// Test GenSurvey for 30 users to get mean and standard deviation
-fn bench_30_user_gen_survey() {
-
-    use std::time::{Duration, Instant};
-
-    // Setup 
-    let rng = &mut rand::thread_rng();
-    let (g, g2):(G1, G2) = get_generator_pair();
-
-    let mut ra = RegistrationAuthority::new(g, g2);
-    let mut sa:User = SurveyAuthority::new(g, g2);
-    const NUM_USERS:usize = 30;
-    assert!(NUM_USERS > 1);
-    let mut userids:Vec<Fr> = Vec::new();
-    for _ in 0..NUM_USERS {
-        // Skip registering user -- we only care about user ids for generating survey
-        userids.push(Fr::random(rng));
+    println!("SA stored {} responses", store.count());
+    // Responses are only read out once the survey has ended
+    if let Err(e) = store.get(resubmitted_token, &signatures) {
+        println!("Stored responses can't be read yet: {}", e);
     }
 
-    // 30-participant survey for GenSurvey
-    println!("GenSurvey Benchmark Test ({} users)", NUM_USERS);
-    let mut sum:Duration = Duration::new(0,0);
-    let mut durs:[Duration;NUM_USERS] = [Duration::new(0,0);NUM_USERS];
-    for i in 0..NUM_USERS {
-        let start = Instant::now();
-        // One user at a time
-        let _ = sa.gen_survey(&vec![userids[i]], g, g2, &ra.vk).expect("SA survey creation failed!");
-        durs[i] = start.elapsed();
-        sum += durs[i];
-        println!("User {}: {:?}", i+1, durs[i]);
+    // The SA closes the survey before its closing time: the stored responses are the final results
+    sa.close_survey(&mut store)?;
+    let late = userbase[2].submit_survey(vid, &Response::new(vec![Some(Answer::Choice(1)), None]).to_string(), &signatures, &sa.vk, &ra.vk, &params)?;
+    println!("SA closed the survey; late submission check: {:?}", store.check(late, &sa.vk, &ra.vk, &params));
+    if let Some(stored) = store.get(resubmitted_token, &signatures)? {
+        println!("Stored response for resubmitted token: {:?}", stored.response);
     }
     println!();
-    // Calculate mean
-    let mean = sum / (NUM_USERS as u32);
-    // Calculate standard deviation
-    let mut sum_of_diff:f32 = 0.0;
-    for i in 0..NUM_USERS {
-        sum_of_diff += f32::powf((((durs[i].as_millis() as i128) - (mean.as_millis() as i128)) as f32)/1000.0, 2.0);
-    }
-    let sd = ( sum_of_diff / ((NUM_USERS as f32)- 1.0)).sqrt();
- 
-    println!("Mean:\t\t{:?}", mean);
-    println!("Std Dev:\t{:?}s", sd);
-    println!("Total:\t\t{:?}", sum);
-}
-
-
-#[test]
-#[ignore]
-#[allow(non_snake_case)]
-// Test GenSurvey for 300 users to get mean and standard deviation
-fn bench_300_user_gen_survey() {
-
-    use std::time::{Duration, Instant};
-
-    // Setup 
-    let rng = &mut rand::thread_rng();
-    let (g, g2):(G1, G2) = get_generator_pair();
 
-    let mut ra = RegistrationAuthority::new(g, g2);
-    let mut sa:User = SurveyAuthority::new(g, g2);
-    const NUM_USERS:usize = 300;
-    assert!(NUM_USERS > 1);
-    let mut userids:Vec<Fr> = Vec::new();
-    for _ in 0..NUM_USERS {
-        // Skip registering user -- we only care about user ids for generating survey
-        userids.push(Fr::random(rng));
-    }
- 
-    // 300-participant survey for GenSurvey
-    println!("GenSurvey Benchmark Test ({} users)", NUM_USERS);
-    let mut sum:Duration = Duration::new(0,0);
-    let mut durs:[Duration;NUM_USERS] = [Duration::new(0,0);NUM_USERS];
-    for i in 0..NUM_USERS {
-        let start = Instant::now();
-        // One user at a time
-        let _ = sa.gen_survey(&vec![userids[i]], g, g2, &ra.vk).expect("SA survey creation failed!");
-        durs[i] = start.elapsed();
-        sum += durs[i];
-        println!("User {}: {:?}", i+1, durs[i]);
+    println!("Results:");
+    let report:TallyReport = tally(&store, &signatures)?;
+    for question in &report.questions {
+        match question.withheld {
+            false   => println!("\t{} ({} answered, {} skipped)", question.text, question.answered, question.skipped),
+            true    => println!("\t{}", question.text)
+        }
+        match &question.result {
+            QuestionResult::Choice { options } => for option in options {
+                println!("\t\t{}: {}", option.option, option.count);
+            },
+            QuestionResult::Text { answers } => for answer in answers {
+                println!("\t\t\"{}\"", answer);
+            },
+            other => println!("\t\t{:?}", other)
+        }
     }
     println!();
-    // Calculate mean
-    let mean = sum / (NUM_USERS as u32);
-    // Calculate standard deviation
-    let mut sum_of_diff:f32 = 0.0;
-    for i in 0..NUM_USERS {
-        sum_of_diff += f32::powf((((durs[i].as_millis() as i128) - (mean.as_millis() as i128)) as f32)/1000.0, 2.0);
-    }
-    let sd = ( sum_of_diff / ((NUM_USERS as f32)- 1.0)).sqrt();
- 
-    println!("Mean:\t\t{:?}", mean);
-    println!("Std Dev:\t{:?}s", sd);
-    println!("Total:\t\t{:?}", sum);
-}
 
 
+    // TODO: Have all users run on separate threads for efficiency
 
-#[test]
-#[allow(non_snake_case)]
-// Test Authorized for 30 users to get mean and standard deviation
-fn bench_30_user_authorized() {
-
-    use std::time::{Duration, Instant};
-
-    // Setup 
-    let rng = &mut rand::thread_rng();
-    let (g, g2):(G1, G2) = get_generator_pair();
-
-    let mut ra = RegistrationAuthority::new(g, g2);
-    let mut sa:User = SurveyAuthority::new(g, g2);
-    const NUM_USERS:usize = 30;
-    assert!(NUM_USERS > 1);
-    let mut userids:Vec<Fr> = Vec::new();
-    for _ in 0..NUM_USERS {
-        // Skip registering user -- we only care about user ids for generating survey
-        userids.push(Fr::random(rng));
-    }
-
-    // 30-participant survey for GenSurvey
-    println!("Generating {} survey signatures...", userids.len());
-    let (vid, signatures):(Fr, Vec<(Fr, G1, G2)>) = sa.gen_survey(&userids, g, g2, &ra.vk).expect("SA survey creation failed!");
- 
-    // Check authorized for each user
-    println!("User Authorized Benchmark Test ({} users)", NUM_USERS);
-    let mut sum:Duration = Duration::new(0,0);
-    let mut durs:[Duration;NUM_USERS] = [Duration::new(0,0);NUM_USERS];
-    let _ = sa.gen_survey(&userids, g, g2, &ra.vk).expect("SA survey creation failed!");
-    
-    for i in 0..NUM_USERS {
-        let start = Instant::now();
-        // One user at a time
-        let _ = authorized(userids[i], vid, &signatures, &sa.vk, &ra.vk, g2);
-        durs[i] = start.elapsed();
-        sum += durs[i];
-        println!("User {}: {:?}", i+1, durs[i]);
-    }
     println!();
-    // Calculate mean
-    let mean = sum / (NUM_USERS as u32);
-    // Calculate standard deviation
-    let mut sum_of_diff:f32 = 0.0;
-    for i in 0..NUM_USERS {
-        sum_of_diff += f32::powf((((durs[i].as_millis() as i128) - (mean.as_millis() as i128)) as f32)/1000.0, 2.0);
-    }
-    let sd = ( sum_of_diff / ((NUM_USERS as f32)- 1.0)).sqrt();
- 
-    println!("Mean:\t\t{:?}", mean);
-    println!("Std Dev:\t{:?}s", sd);
-    println!("Total:\t\t{:?}", sum);
-}
-
 
-
-#[test]
-#[ignore]
-#[allow(non_snake_case)]
-// Test Authorized for 300 users to get mean and standard deviation
-fn bench_300_user_authorized() {
-
-    use std::time::{Duration, Instant};
-
-    // Setup 
-    let rng = &mut rand::thread_rng();
-    let (g, g2):(G1, G2) = get_generator_pair();
-
-    let mut ra = RegistrationAuthority::new(g, g2);
-    let mut sa:User = SurveyAuthority::new(g, g2);
-    const NUM_USERS:usize = 300;
-    assert!(NUM_USERS > 1);
-    let mut userids:Vec<Fr> = Vec::new();
-    for _ in 0..NUM_USERS {
-        // Skip registering user -- we only care about user ids for generating survey
-        userids.push(Fr::random(rng));
-    }
-
-    // 300-participant survey for GenSurvey
-    println!("Generating {} survey signatures...", userids.len());
-    let (vid, signatures):(Fr, Vec<(Fr, G1, G2)>) = sa.gen_survey(&userids, g, g2, &ra.vk).expect("SA survey creation failed!");
- 
-    // Check authorized for each user
-    println!("User Authorized Benchmark Test ({} users)", NUM_USERS);
-    let mut sum:Duration = Duration::new(0,0);
-    let mut durs:[Duration;NUM_USERS] = [Duration::new(0,0);NUM_USERS];
-    let _ = sa.gen_survey(&userids, g, g2, &ra.vk).expect("SA survey creation failed!");
-    
-    for i in 0..NUM_USERS {
-        let start = Instant::now();
-        // One user at a time
-        let _ = authorized(userids[i], vid, &signatures, &sa.vk, &ra.vk, g2);
-        durs[i] = start.elapsed();
-        sum += durs[i];
-        println!("User {}: {:?}", i+1, durs[i]);
-    }
-    println!();
-    // Calculate mean
-    let mean = sum / (NUM_USERS as u32);
-    // Calculate standard deviation
-    let mut sum_of_diff:f32 = 0.0;
-    for i in 0..NUM_USERS {
-        sum_of_diff += f32::powf((((durs[i].as_millis() as i128) - (mean.as_millis() as i128)) as f32)/1000.0, 2.0);
-    }
-    let sd = ( sum_of_diff / ((NUM_USERS as f32)- 1.0)).sqrt();
- 
-    println!("Mean:\t\t{:?}", mean);
-    println!("Std Dev:\t{:?}s", sd);
-    println!("Total:\t\t{:?}", sum);
+    Ok(())
 }
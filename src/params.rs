@@ -0,0 +1,209 @@
+extern crate tbn;
+extern crate sha2;
+extern crate rand;
+
+use tbn::{Group, Fr, G1, G2, Gt, pairing};
+use sha2::{Sha256, Digest};
+use rand::{RngCore, CryptoRng};
+use crate::users::VerificationKey;
+use crate::encoding::{Encode, Decode, Canonical, Reader};
+use crate::hash;
+use crate::fixed_base::FixedBase;
+use crate::survey::ParticipantList;
+use crate::error::{Error, Result};
+
+/*
+ * ----------------------------------------------
+ * |    PUBLIC PARAMETERS                       |
+ * ----------------------------------------------
+ *
+ * All keys, credentials and surveys of one deployment are defined over the same generators
+ * (g, g2). They are generated once, published, and identified by a fingerprint (a hash of their
+ * canonical encoding) that every verification key records, so that keys made under different
+ * generators are refused instead of silently failing to verify.
+ *
+ * Parameters derived from a public seed with from_seed can be audited by anyone: recompute them
+ * from the seed and compare fingerprints.
+*/
+
+/// Hash identifying a set of public parameters
+pub type Fingerprint = [u8; 32];
+
+/// Generators (g, g2), the precomputed pairing e(g, g2) and their fingerprint
+#[derive(Clone)]
+pub struct PublicParams {
+    pub g: G1,
+    pub g2: G2,
+    /// e(g, g2)
+    pub gt: Gt,
+    pub fingerprint: Fingerprint,
+    // Window tables for g and g2, shared between clones
+    g_table: FixedBase<G1>,
+    g2_table: FixedBase<G2>
+}
+
+// The tables and e(g, g2) are determined by the generators
+impl PartialEq for PublicParams {
+    fn eq(&self, other: &Self) -> bool {
+        self.g == other.g && self.g2 == other.g2 && self.fingerprint == other.fingerprint
+    }
+}
+
+impl PublicParams {
+
+    /// Public parameters over random generators
+    pub fn generate() -> Self {
+        PublicParams::generate_with_rng(&mut rand::thread_rng())
+    }
+
+    /// Public parameters over generators drawn from the given rng
+    pub fn generate_with_rng<R: RngCore + CryptoRng>(rng: &mut R) -> Self {
+        let (g, g2):(G1, G2) = crate::get_generator_pair_with_rng(rng);
+        PublicParams::new(g, g2).expect("Random generators are non-zero")
+    }
+
+    /// Nothing-up-my-sleeve public parameters: generators hashed to the curve from a public seed
+    pub fn from_seed(seed: &str) -> Self {
+        let g:G1 = hash::hash_to_g1(b"anon_survey/generator/g", seed.as_bytes());
+        let g2:G2 = hash::hash_to_g2(b"anon_survey/generator/g2", seed.as_bytes());
+        PublicParams::new(g, g2).expect("Hashed generators are non-zero")
+    }
+
+    /// Whether these parameters were derived from the given seed
+    pub fn is_derived_from(&self, seed: &str) -> bool {
+        PublicParams::from_seed(seed).fingerprint == self.fingerprint
+    }
+
+    /// Public parameters over the given generators. Fails with InvalidParams if either is zero.
+    pub fn new(g: G1, g2: G2) -> Result<Self> {
+        if g.is_zero() || g2.is_zero() {
+            return Err(Error::InvalidParams);
+        }
+        Ok(PublicParams {
+            g,
+            g2,
+            gt: pairing(g, g2),
+            fingerprint: fingerprint(g, g2),
+            g_table: FixedBase::new(g),
+            g2_table: FixedBase::new(g2)
+        })
+    }
+
+    /// g * x, using the precomputed table for g
+    pub fn mul_g(&self, x: Fr) -> G1 {
+        self.g_table.mul(x)
+    }
+
+    /// g2 * x, using the precomputed table for g2
+    pub fn mul_g2(&self, x: Fr) -> G2 {
+        self.g2_table.mul(x)
+    }
+
+    /// Fails with InvalidParams if the key was not generated under these parameters
+    pub fn check_key(&self, vk: &VerificationKey) -> Result<()> {
+        match vk.params == self.fingerprint {
+            true    => Ok(()),
+            false   => Err(Error::InvalidParams)
+        }
+    }
+
+    /// Decode a verification key and check that it was generated under these parameters
+    pub fn load_key(&self, bytes: &[u8]) -> Result<VerificationKey> {
+        let vk = VerificationKey::from_bytes(bytes)?;
+        self.check_key(&vk)?;
+        Ok(vk)
+    }
+
+    /// Decode a published survey list, checking that the SA's and RA's keys it is to be verified
+    /// against belong to these parameters (the signatures themselves are checked by Authorized)
+    pub fn load_survey(&self, bytes: &[u8], vk_sa: &VerificationKey, vk_ra: &VerificationKey) -> Result<(Fr, ParticipantList)> {
+        self.check_key(vk_sa)?;
+        self.check_key(vk_ra)?;
+        <(Fr, ParticipantList)>::from_bytes(bytes)
+    }
+}
+
+// SHA-256 of the canonical encoding of (g, g2)
+fn fingerprint(g: G1, g2: G2) -> Fingerprint {
+    let mut bytes:Vec<u8> = b"anon_survey/params".to_vec();
+    g.encode_to(&mut bytes);
+    g2.encode_to(&mut bytes);
+
+    let mut digest:Fingerprint = [0; 32];
+    digest.copy_from_slice(&Sha256::digest(&bytes));
+    digest
+}
+
+// Encoded as (g, g2, fingerprint): e(g, g2) is recomputed and the fingerprint checked on decode
+impl Encode for PublicParams {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        self.g.encode_to(out);
+        self.g2.encode_to(out);
+        self.fingerprint.encode_to(out);
+    }
+}
+
+impl Decode for PublicParams {
+    fn decode_from(reader: &mut Reader) -> Result<Self> {
+        let params = PublicParams::new(G1::decode_from(reader)?, G2::decode_from(reader)?)?;
+        match Fingerprint::decode_from(reader)? == params.fingerprint {
+            true    => Ok(params),
+            false   => Err(Error::InvalidParams)
+        }
+    }
+}
+
+impl Canonical for PublicParams {
+    const TAG: u8 = 0x01;
+}
+
+
+/*
+ * Unit tests
+ */
+
+#[cfg(test)]
+use crate::users::{User, SurveyAuthority, RegistrationAuthority};
+
+#[test]
+// Test that parameters survive a round trip and keys are only accepted under their own parameters
+fn test_params() {
+    let params = PublicParams::generate();
+    let other = PublicParams::generate();
+    assert!( params.fingerprint != other.fingerprint );
+    assert!( PublicParams::from_bytes(&params.to_bytes()) == Ok(params.clone()) );
+
+    // Tampered fingerprint
+    let mut bytes:Vec<u8> = params.to_bytes();
+    let last:usize = bytes.len() - 1;
+    bytes[last] ^= 1;
+    assert!( PublicParams::from_bytes(&bytes) == Err(Error::InvalidParams) );
+
+    let ra = RegistrationAuthority::new(&params);
+    let mut sa:User = SurveyAuthority::new(&other);
+    assert!( params.load_key(&ra.vk.to_bytes()) == Ok(ra.vk) );
+    assert!( other.load_key(&ra.vk.to_bytes()) == Err(Error::InvalidParams) );
+
+    // Surveys can't be made or loaded against an RA with other parameters
+    assert_eq!( sa.gen_survey(&[Fr::one()], &other, &ra.vk), Err(Error::InvalidParams) );
+    let mut own_sa:User = SurveyAuthority::new(&params);
+    let survey = own_sa.gen_survey(&[Fr::one()], &params, &ra.vk).expect("SA survey creation failed!");
+    assert_eq!( params.load_survey(&survey.to_bytes(), &own_sa.vk, &ra.vk), Ok(survey.clone()) );
+    assert_eq!( params.load_survey(&survey.to_bytes(), &sa.vk, &ra.vk), Err(Error::InvalidParams) );
+}
+
+#[test]
+// Test that seeded parameters and keys can be recomputed by anyone from the seed
+fn test_params_from_seed() {
+    let params = PublicParams::from_seed("anon_survey test parameters");
+    assert!( params.is_derived_from("anon_survey test parameters") );
+    assert!( !params.is_derived_from("other parameters") );
+    assert!( !PublicParams::generate().is_derived_from("anon_survey test parameters") );
+
+    let ra = RegistrationAuthority::from_seed(&params, "RA");
+    let sa:User = SurveyAuthority::from_seed(&params, "SA");
+    assert!( (ra.vk.u, ra.vk.v, ra.vk.h) == hash::derive_bases("RA") );
+    assert!( (sa.vk.u, sa.vk.v, sa.vk.h) == hash::derive_bases("SA") );
+    // Only the public bases are derived: signing keys stay secret
+    assert!( ra.vk.pk != RegistrationAuthority::from_seed(&params, "RA").vk.pk );
+}
@@ -0,0 +1,516 @@
+extern crate tbn;
+extern crate sha2;
+
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use tbn::Fr;
+use sha2::{Sha256, Digest};
+use crate::survey::FrKey;
+use crate::encoding::{Encode, Decode, Canonical, Reader};
+use crate::durable::sync_dir;
+use crate::error::{Error, Result};
+
+/*
+ * ----------------------------------------------
+ * |    USER REGISTRY                           |
+ * ----------------------------------------------
+ *
+ * The RA keeps the ids of its registered users (the anonymity set) in a UserRegistry, along with
+ * the rotations (old id, new id) that replaced one id by another. Besides the in-memory registry,
+ * a FileRegistry keeps them in a directory so they survive restarts:
+ *      snapshot    canonical encoding of every id and rotation at the time of the last compaction
+ *      log         one record per change since then: op (u8) || ids || checksum (4 bytes)
+ * where op is 0 for an insertion or 1 for a removal of one id (32 bytes), 2 for a replacement of
+ * an old id by a new one (64 bytes) or 3 for a rotation recorded on its own (64 bytes), and the
+ * checksum is the start of the SHA-256 of op || ids. A replacement is a single record, so a crash
+ * can't leave only half of it. Every change is appended to the log and synced before it takes
+ * effect. On open, the log is replayed over the snapshot. A crash in the middle of a write can only
+ * leave an incomplete or corrupt record at the end of the log, which is cut off; one followed by
+ * more records means the log was damaged, and opening it fails rather than drop what follows.
+ *
+ * Once the log outgrows the registry it is compacted: a new snapshot is written next to the old
+ * one and renamed over it, then the log is emptied. Replaying a log over the snapshot that already
+ * contains it gives the same set, so a crash in between loses nothing.
+*/
+
+const OP_INSERT: u8 = 0;
+const OP_REMOVE: u8 = 1;
+const OP_REPLACE: u8 = 2;
+const OP_ROTATE: u8 = 3;
+// Logs shorter than this are never compacted
+const MIN_COMPACT_RECORDS: usize = 1024;
+
+/// Set of registered user ids
+pub trait UserRegistry {
+    /// Add id to the registry. Returns false (and changes nothing) if it was already there.
+    fn insert(&mut self, id: Fr) -> Result<bool>;
+
+    /// Remove id from the registry. Returns false if it was not there.
+    fn remove(&mut self, id: Fr) -> Result<bool>;
+
+    /// Replace old_id with new_id and record the rotation, as when a user re-identifies. Returns
+    /// false (and changes nothing) if new_id is already there, and fails with UnregisteredId if
+    /// old_id is not. Either all of it happens or none of it does: a user is never registered
+    /// under both ids, or under neither.
+    fn replace(&mut self, old_id: Fr, new_id: Fr) -> Result<bool>;
+
+    /// Note that old_id was replaced by new_id, without changing the registered ids; recording a
+    /// rotation to the same new id again changes nothing.
+    fn record_rotation(&mut self, old_id: Fr, new_id: Fr) -> Result<()>;
+
+    /// Completed rotations (old id, new id), in order
+    fn rotations(&self) -> Vec<(Fr, Fr)>;
+
+    fn contains(&self, id: Fr) -> bool;
+
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Every registered id, in no particular order
+    fn iter(&self) -> Box<dyn Iterator<Item = Fr> + '_>;
+
+    /// Every registered id, e.g. as the participants of a survey
+    fn ids(&self) -> Vec<Fr> {
+        self.iter().collect()
+    }
+}
+
+/// Registry held in memory only
+#[derive(Clone, Debug, Default)]
+pub struct MemoryRegistry {
+    ids: Vec<Fr>,
+    // Position of each id
+    index: HashMap<FrKey, usize>,
+    rotations: Vec<(Fr, Fr)>,
+    // New id of each rotation
+    rotated: HashSet<FrKey>
+}
+
+impl MemoryRegistry {
+
+    pub fn new() -> Self {
+        MemoryRegistry::default()
+    }
+
+    // Make the change a log record describes. Making it again changes nothing, so replaying a log
+    // over a snapshot that already contains it is harmless.
+    fn apply(&mut self, change: Change) -> Result<bool> {
+        match change {
+            Change::Insert(id)              => self.insert(id),
+            Change::Remove(id)              => self.remove(id),
+            Change::Replace(old_id, new_id) => {
+                self.remove(old_id)?;
+                let inserted:bool = self.insert(new_id)?;
+                self.record_rotation(old_id, new_id)?;
+                Ok(inserted)
+            },
+            Change::Rotate(old_id, new_id)  => self.record_rotation(old_id, new_id).map(|_| true)
+        }
+    }
+}
+
+impl UserRegistry for MemoryRegistry {
+
+    fn insert(&mut self, id: Fr) -> Result<bool> {
+        let key = FrKey::from(id);
+        if self.index.contains_key(&key) {
+            return Ok(false);
+        }
+        self.index.insert(key, self.ids.len());
+        self.ids.push(id);
+        Ok(true)
+    }
+
+    fn remove(&mut self, id: Fr) -> Result<bool> {
+        let i:usize = match self.index.remove(&FrKey::from(id)) {
+            Some(i) => i,
+            None    => return Ok(false)
+        };
+        // The last id takes the place of the removed one
+        self.ids.swap_remove(i);
+        if i < self.ids.len() {
+            self.index.insert(FrKey::from(self.ids[i]), i);
+        }
+        Ok(true)
+    }
+
+    // The new id takes the place of the old one
+    fn replace(&mut self, old_id: Fr, new_id: Fr) -> Result<bool> {
+        let i:usize = *self.index.get(&FrKey::from(old_id)).ok_or(Error::UnregisteredId)?;
+        if self.contains(new_id) {
+            return Ok(false);
+        }
+        self.index.remove(&FrKey::from(old_id));
+        self.index.insert(FrKey::from(new_id), i);
+        self.ids[i] = new_id;
+        self.record_rotation(old_id, new_id)?;
+        Ok(true)
+    }
+
+    fn record_rotation(&mut self, old_id: Fr, new_id: Fr) -> Result<()> {
+        if self.rotated.insert(FrKey::from(new_id)) {
+            self.rotations.push((old_id, new_id));
+        }
+        Ok(())
+    }
+
+    fn rotations(&self) -> Vec<(Fr, Fr)> {
+        self.rotations.clone()
+    }
+
+    fn contains(&self, id: Fr) -> bool {
+        self.index.contains_key(&FrKey::from(id))
+    }
+
+    fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = Fr> + '_> {
+        Box::new(self.ids.iter().copied())
+    }
+}
+
+// The index is determined by the ids, and the new ids by the rotations
+impl PartialEq for MemoryRegistry {
+    fn eq(&self, other: &Self) -> bool {
+        self.ids.len() == other.ids.len() && self.ids.iter().all(|id| other.contains(*id)) && self.rotations == other.rotations
+    }
+}
+
+// Registry: (ids, rotations), where the ids and the new ids of the rotations must be distinct
+impl Encode for MemoryRegistry {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        self.ids.encode_to(out);
+        self.rotations.encode_to(out);
+    }
+}
+
+impl Decode for MemoryRegistry {
+    fn decode_from(reader: &mut Reader) -> Result<Self> {
+        let mut registry = MemoryRegistry::new();
+        for id in Vec::<Fr>::decode_from(reader)? {
+            if !registry.insert(id)? {
+                return Err(Error::MalformedEncoding);
+            }
+        }
+        for (old_id, new_id) in Vec::<(Fr, Fr)>::decode_from(reader)? {
+            if !registry.rotated.insert(FrKey::from(new_id)) {
+                return Err(Error::MalformedEncoding);
+            }
+            registry.rotations.push((old_id, new_id));
+        }
+        Ok(registry)
+    }
+}
+
+impl Canonical for MemoryRegistry {
+    const TAG: u8 = 0x19;
+}
+
+/// Registry kept in a directory as a snapshot and a log of changes since
+pub struct FileRegistry {
+    dir: PathBuf,
+    registry: MemoryRegistry,
+    log: File,
+    // Records in the log, and their length in bytes
+    log_records: usize,
+    log_len: usize
+}
+
+impl FileRegistry {
+
+    /// Open the registry in dir, creating the directory and an empty registry if needed. Fails
+    /// with MalformedEncoding if the snapshot is corrupt, or a record other than the last in the
+    /// log is.
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<Self> {
+        let dir:PathBuf = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+
+        let mut registry:MemoryRegistry = match fs::read(dir.join("snapshot")) {
+            Ok(bytes)   => MemoryRegistry::from_bytes(&bytes)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => MemoryRegistry::new(),
+            Err(e)      => return Err(e.into())
+        };
+
+        // Replay the log, up to a torn record at its end
+        let log_bytes:Vec<u8> = match fs::read(dir.join("log")) {
+            Ok(bytes)   => bytes,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e)      => return Err(e.into())
+        };
+        let mut log_records:usize = 0;
+        let mut log_len:usize = 0;
+        while log_len < log_bytes.len() {
+            let rest:&[u8] = &log_bytes[log_len..];
+            match decode_record(rest) {
+                Some((change, len)) => {
+                    registry.apply(change)?;
+                    log_records += 1;
+                    log_len += len;
+                },
+                None if record_len(rest).is_some_and(|len| rest.len() <= len) => break,
+                None => return Err(Error::MalformedEncoding)
+            }
+        }
+
+        let log:File = OpenOptions::new().create(true).append(true).open(dir.join("log"))?;
+        if log_bytes.len() != log_len {
+            log.set_len(log_len as u64)?;
+            log.sync_all()?;
+        }
+        Ok(FileRegistry { dir, registry, log, log_records, log_len })
+    }
+
+    /// Write a snapshot of the registry and empty the log
+    pub fn compact(&mut self) -> Result<()> {
+        let tmp:PathBuf = self.dir.join("snapshot.tmp");
+        let mut file:File = File::create(&tmp)?;
+        file.write_all(&self.registry.to_bytes())?;
+        file.sync_all()?;
+        fs::rename(&tmp, self.dir.join("snapshot"))?;
+        sync_dir(&self.dir)?;
+
+        self.log.set_len(0)?;
+        self.log.sync_all()?;
+        self.log_records = 0;
+        self.log_len = 0;
+        Ok(())
+    }
+
+    // Durably record a change before applying it, compacting the log if it has grown too long. If
+    // the record can't be written, the log is cut back to the records before it.
+    fn apply(&mut self, change: Change) -> Result<bool> {
+        let record:Vec<u8> = encode_record(change);
+        if let Err(e) = self.log.write_all(&record).and_then(|_| self.log.sync_data()) {
+            let _ = self.log.set_len(self.log_len as u64);
+            return Err(e.into());
+        }
+        self.log_records += 1;
+        self.log_len += record.len();
+        let changed:bool = self.registry.apply(change)?;
+        // The change is already durable, so a failed compaction is just retried on the next one
+        if self.log_records >= MIN_COMPACT_RECORDS.max(self.registry.len()) {
+            let _ = self.compact();
+        }
+        Ok(changed)
+    }
+}
+
+impl UserRegistry for FileRegistry {
+
+    fn insert(&mut self, id: Fr) -> Result<bool> {
+        if self.registry.contains(id) {
+            return Ok(false);
+        }
+        self.apply(Change::Insert(id))
+    }
+
+    fn remove(&mut self, id: Fr) -> Result<bool> {
+        if !self.registry.contains(id) {
+            return Ok(false);
+        }
+        self.apply(Change::Remove(id))
+    }
+
+    fn replace(&mut self, old_id: Fr, new_id: Fr) -> Result<bool> {
+        if !self.registry.contains(old_id) {
+            return Err(Error::UnregisteredId);
+        }
+        if self.registry.contains(new_id) {
+            return Ok(false);
+        }
+        self.apply(Change::Replace(old_id, new_id))
+    }
+
+    fn record_rotation(&mut self, old_id: Fr, new_id: Fr) -> Result<()> {
+        if !self.registry.rotated.contains(&FrKey::from(new_id)) {
+            self.apply(Change::Rotate(old_id, new_id))?;
+        }
+        Ok(())
+    }
+
+    fn rotations(&self) -> Vec<(Fr, Fr)> {
+        self.registry.rotations()
+    }
+
+    fn contains(&self, id: Fr) -> bool {
+        self.registry.contains(id)
+    }
+
+    fn len(&self) -> usize {
+        self.registry.len()
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = Fr> + '_> {
+        self.registry.iter()
+    }
+}
+
+// Change recorded in a FileRegistry's log
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Change {
+    Insert(Fr),
+    Remove(Fr),
+    Replace(Fr, Fr),
+    Rotate(Fr, Fr)
+}
+
+// Log record for a change: op || ids || checksum
+fn encode_record(change: Change) -> Vec<u8> {
+    let mut record:Vec<u8> = Vec::new();
+    match change {
+        Change::Insert(id) => {
+            record.push(OP_INSERT);
+            id.encode_to(&mut record);
+        },
+        Change::Remove(id) => {
+            record.push(OP_REMOVE);
+            id.encode_to(&mut record);
+        },
+        Change::Replace(old_id, new_id) => {
+            record.push(OP_REPLACE);
+            old_id.encode_to(&mut record);
+            new_id.encode_to(&mut record);
+        },
+        Change::Rotate(old_id, new_id) => {
+            record.push(OP_ROTATE);
+            old_id.encode_to(&mut record);
+            new_id.encode_to(&mut record);
+        }
+    }
+    let checksum = Sha256::digest(&record);
+    record.extend_from_slice(&checksum[..4]);
+    record
+}
+
+// Length of the log record at the start of bytes according to its op, or None if the op is unknown
+fn record_len(bytes: &[u8]) -> Option<usize> {
+    match bytes.first() {
+        Some(&OP_INSERT) | Some(&OP_REMOVE)  => Some(1 + 32 + 4),
+        Some(&OP_REPLACE) | Some(&OP_ROTATE) => Some(1 + 64 + 4),
+        _                                    => None
+    }
+}
+
+// Change recorded in the log record at the start of bytes and the record's length, or None if it
+// is incomplete or corrupt
+fn decode_record(bytes: &[u8]) -> Option<(Change, usize)> {
+    let len:usize = record_len(bytes)?;
+    if bytes.len() < len {
+        return None;
+    }
+    let (body, checksum) = bytes[..len].split_at(len - 4);
+    if Sha256::digest(body)[..4] != *checksum {
+        return None;
+    }
+    let mut reader = Reader::new(&body[1..]);
+    let id:Fr = Fr::decode_from(&mut reader).ok()?;
+    let change:Change = match body[0] {
+        OP_INSERT   => Change::Insert(id),
+        OP_REMOVE   => Change::Remove(id),
+        OP_REPLACE  => Change::Replace(id, Fr::decode_from(&mut reader).ok()?),
+        _           => Change::Rotate(id, Fr::decode_from(&mut reader).ok()?)
+    };
+    Some((change, len))
+}
+
+
+/*
+ * Unit tests
+ */
+
+#[test]
+// Test that both registries behave as sets, and that the file registry survives a restart
+fn test_registry() {
+    let rng = &mut rand::thread_rng();
+    let ids:Vec<Fr> = (0..5).map(|_| Fr::random(rng)).collect();
+    // Removed when dropped, even if an assertion fails
+    let tmp = tempfile::tempdir().expect("Creating temporary directory failed");
+    let dir:&Path = tmp.path();
+
+    let mut memory = MemoryRegistry::new();
+    let mut file = FileRegistry::open(dir).expect("Opening registry failed");
+    let registries:[&mut dyn UserRegistry; 2] = [&mut memory, &mut file];
+    for registry in registries {
+        for id in &ids {
+            assert_eq!( registry.insert(*id), Ok(true) );
+        }
+        assert_eq!( registry.insert(ids[0]), Ok(false) );
+        assert_eq!( registry.remove(ids[1]), Ok(true) );
+        assert_eq!( registry.remove(ids[1]), Ok(false) );
+        assert_eq!( registry.remove(Fr::random(rng)), Ok(false) );
+        assert_eq!( registry.len(), 4 );
+        assert!( !registry.contains(ids[1]) && registry.contains(ids[4]) );
+        assert!( registry.ids().len() == 4 && ids.iter().all(|id| registry.ids().contains(id) == registry.contains(*id)) );
+        assert_eq!( registry.replace(ids[4], ids[0]), Ok(false) );
+        assert_eq!( registry.replace(ids[1], Fr::random(rng)), Err(Error::UnregisteredId) );
+        assert_eq!( registry.replace(ids[4], ids[1]), Ok(true) );
+        assert!( registry.contains(ids[1]) && !registry.contains(ids[4]) && registry.len() == 4 );
+        assert_eq!( registry.record_rotation(ids[4], ids[1]), Ok(()) );
+        assert_eq!( registry.rotations(), vec![(ids[4], ids[1])] );
+    }
+    drop(file);
+
+    let mut reopened = FileRegistry::open(dir).expect("Reopening registry failed");
+    assert!( reopened.registry == memory );
+    assert!( fs::read(dir.join("log")).unwrap().ends_with(&encode_record(Change::Replace(ids[4], ids[1]))) );
+    assert_eq!( MemoryRegistry::from_bytes(&memory.to_bytes()), Ok(memory.clone()) );
+
+    // Compaction keeps the ids, and replaying the log over a newer snapshot changes nothing
+    let log:Vec<u8> = fs::read(dir.join("log")).unwrap();
+    reopened.compact().expect("Compaction failed");
+    assert_eq!( fs::metadata(dir.join("log")).unwrap().len(), 0 );
+    fs::write(dir.join("log"), &log).unwrap();
+    let mut reopened = FileRegistry::open(dir).expect("Reopening registry failed");
+    assert!( reopened.registry == memory );
+
+    // A torn or corrupt record at the end of the log is dropped, and the log cut before it
+    assert_eq!( reopened.insert(ids[4]), Ok(true) );
+    drop(reopened);
+    let log:Vec<u8> = fs::read(dir.join("log")).unwrap();
+    let len:usize = log.len();
+    let mut torn:Vec<u8> = log.clone();
+    torn.extend_from_slice(&encode_record(Change::Remove(ids[0]))[..10]);
+    fs::write(dir.join("log"), &torn).unwrap();
+    let reopened = FileRegistry::open(dir).expect("Reopening registry failed");
+    assert!( reopened.contains(ids[4]) && reopened.contains(ids[0]) );
+    assert_eq!( fs::metadata(dir.join("log")).unwrap().len() as usize, len );
+    drop(reopened);
+    let mut corrupt:Vec<u8> = log.clone();
+    corrupt[len - 1] ^= 1;
+    fs::write(dir.join("log"), &corrupt).unwrap();
+    let reopened = FileRegistry::open(dir).expect("Reopening registry failed");
+    assert!( reopened.registry == memory );
+    assert_eq!( fs::metadata(dir.join("log")).unwrap().len() as usize, len - encode_record(Change::Insert(ids[4])).len() );
+    drop(reopened);
+
+    // A corrupt record followed by others fails to open, and the log is kept as it is
+    let mut damaged:Vec<u8> = log[..len - encode_record(Change::Insert(ids[4])).len()].to_vec();
+    damaged[1] ^= 1;
+    damaged.extend_from_slice(&encode_record(Change::Insert(ids[4])));
+    fs::write(dir.join("log"), &damaged).unwrap();
+    assert_eq!( FileRegistry::open(dir).map(|r| r.len()), Err(Error::MalformedEncoding) );
+    assert_eq!( fs::read(dir.join("log")).unwrap(), damaged );
+    fs::write(dir.join("log"), &log[..len - encode_record(Change::Insert(ids[4])).len()]).unwrap();
+
+    // Compaction kicks in on its own once the log outgrows the registry
+    let mut reopened = FileRegistry::open(dir).expect("Reopening registry failed");
+    let more:Vec<Fr> = (0..MIN_COMPACT_RECORDS).map(|_| Fr::random(rng)).collect();
+    for id in &more {
+        reopened.insert(*id).expect("Insertion failed");
+    }
+    assert!( reopened.log_records < MIN_COMPACT_RECORDS );
+    drop(reopened);
+    let reopened = FileRegistry::open(dir).expect("Reopening registry failed");
+    assert!( reopened.len() == memory.len() + more.len() && more.iter().all(|id| reopened.contains(*id)) );
+
+    // A corrupt snapshot is refused rather than silently emptying the registry
+    fs::write(dir.join("snapshot"), b"garbage").unwrap();
+    assert_eq!( FileRegistry::open(dir).map(|r| r.len()), Err(Error::MalformedEncoding) );
+}
@@ -0,0 +1,235 @@
+use std::fmt;
+use std::str::FromStr;
+use crate::error::{Error, Result};
+
+/*
+ * ----------------------------------------------
+ * |    QUESTION SCHEMA                         |
+ * ----------------------------------------------
+ *
+ * The questions of a survey are part of its descriptor, so they are fixed by the survey id. A
+ * response to a survey with a descriptor has one (possibly empty) answer per question, written as
+ * text with one line per question:
+ *      choice:<option>                 single choice, by option index
+ *      choices:<option>,<option>,...   multiple choice, by increasing option index
+ *      scale:<point>                   Likert scale, from 1 to the number of points
+ *      number:<integer>                numeric range
+ *      text:<text>                     free text, with backslashes and newlines escaped as \\ and \n
+ * An empty line leaves the question unanswered, which is only allowed if it is not required.
+*/
+
+/// What kind of answer a question takes
+#[derive(Clone, Debug, PartialEq)]
+pub enum QuestionKind {
+    /// Exactly one of the options
+    SingleChoice { options: Vec<String> },
+    /// Any non-empty subset of the options
+    MultipleChoice { options: Vec<String> },
+    /// A point from 1 to points (at least 2)
+    Likert { points: u8 },
+    /// Non-empty text of at most max_len bytes
+    FreeText { max_len: u32 },
+    /// An integer between min and max (inclusive)
+    NumericRange { min: i64, max: i64 }
+}
+
+/// A survey question: its wording, kind and whether it must be answered
+#[derive(Clone, Debug, PartialEq)]
+pub struct Question {
+    pub text: String,
+    pub kind: QuestionKind,
+    pub required: bool
+}
+
+impl Question {
+
+    pub fn new(text: &str, kind: QuestionKind, required: bool) -> Self {
+        Question { text: String::from(text), kind, required }
+    }
+
+    /// Whether the question can be answered at all: choices need options, scales at least two
+    /// points and ranges min <= max
+    pub fn is_well_formed(&self) -> bool {
+        match &self.kind {
+            QuestionKind::SingleChoice { options }      => !options.is_empty(),
+            QuestionKind::MultipleChoice { options }    => !options.is_empty(),
+            QuestionKind::Likert { points }             => *points >= 2,
+            QuestionKind::FreeText { max_len }          => *max_len > 0,
+            QuestionKind::NumericRange { min, max }     => min <= max
+        }
+    }
+
+    /// Whether answer is a valid answer to this question (None meaning unanswered)
+    pub fn accepts(&self, answer: &Option<Answer>) -> bool {
+        let answer = match answer {
+            Some(answer)    => answer,
+            None            => return !self.required
+        };
+        match (&self.kind, answer) {
+            (QuestionKind::SingleChoice { options }, Answer::Choice(i))     => (*i as usize) < options.len(),
+            (QuestionKind::MultipleChoice { options }, Answer::Choices(is)) => {
+                !is.is_empty() && is.windows(2).all(|pair| pair[0] < pair[1]) && is.iter().all(|i| (*i as usize) < options.len())
+            },
+            (QuestionKind::Likert { points }, Answer::Scale(point))         => *point >= 1 && point <= points,
+            (QuestionKind::FreeText { max_len }, Answer::Text(text))        => !text.is_empty() && text.len() <= *max_len as usize,
+            (QuestionKind::NumericRange { min, max }, Answer::Number(n))    => min <= n && n <= max,
+            _ => false
+        }
+    }
+}
+
+/// Answer to a single question
+#[derive(Clone, Debug, PartialEq)]
+pub enum Answer {
+    Choice(u32),
+    Choices(Vec<u32>),
+    Scale(u8),
+    Number(i64),
+    Text(String)
+}
+
+/// Answers to every question of a survey, in order
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Response {
+    pub answers: Vec<Option<Answer>>
+}
+
+impl Response {
+
+    pub fn new(answers: Vec<Option<Answer>>) -> Self {
+        Response { answers }
+    }
+
+    /// Fails with InvalidResponse unless there is one acceptable answer for each question
+    pub fn check(&self, questions: &[Question]) -> Result<()> {
+        match self.answers.len() == questions.len() && questions.iter().zip(&self.answers).all(|(q, a)| q.accepts(a)) {
+            true    => Ok(()),
+            false   => Err(Error::InvalidResponse)
+        }
+    }
+}
+
+impl fmt::Display for Response {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, answer) in self.answers.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            match answer {
+                None                        => Ok(()),
+                Some(Answer::Choice(i))     => write!(f, "choice:{}", i),
+                Some(Answer::Choices(is))   => {
+                    let list:Vec<String> = is.iter().map(|i| i.to_string()).collect();
+                    write!(f, "choices:{}", list.join(","))
+                },
+                Some(Answer::Scale(point))  => write!(f, "scale:{}", point),
+                Some(Answer::Number(n))     => write!(f, "number:{}", n),
+                Some(Answer::Text(text))    => write!(f, "text:{}", text.replace('\\', "\\\\").replace('\n', "\\n"))
+            }?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Response {
+    type Err = Error;
+
+    /// Parse the text form of a response. Fails with InvalidResponse if a line is malformed.
+    fn from_str(s: &str) -> Result<Self> {
+        s.split('\n').map(parse_answer).collect::<Result<Vec<Option<Answer>>>>().map(Response::new)
+    }
+}
+
+// One line of a response
+fn parse_answer(line: &str) -> Result<Option<Answer>> {
+    if line.is_empty() {
+        return Ok(None);
+    }
+    let (kind, value):(&str, &str) = match line.find(':') {
+        Some(i) => (&line[..i], &line[i + 1..]),
+        None    => return Err(Error::InvalidResponse)
+    };
+    let answer = match kind {
+        "choice"    => Answer::Choice(value.parse().map_err(|_| Error::InvalidResponse)?),
+        "choices"   => Answer::Choices(value.split(',').map(|i| i.parse().map_err(|_| Error::InvalidResponse)).collect::<Result<Vec<u32>>>()?),
+        "scale"     => Answer::Scale(value.parse().map_err(|_| Error::InvalidResponse)?),
+        "number"    => Answer::Number(value.parse().map_err(|_| Error::InvalidResponse)?),
+        "text"      => Answer::Text(unescape(value)?),
+        _           => return Err(Error::InvalidResponse)
+    };
+    Ok(Some(answer))
+}
+
+// Undo the escaping of backslashes and newlines in free text
+fn unescape(value: &str) -> Result<String> {
+    let mut text = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            text.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('\\')  => text.push('\\'),
+            Some('n')   => text.push('\n'),
+            _           => return Err(Error::InvalidResponse)
+        }
+    }
+    Ok(text)
+}
+
+
+/*
+ * Unit tests
+ */
+
+#[cfg(test)]
+// One question of every kind, only the last one optional
+pub(crate) fn sample_questions() -> Vec<Question> {
+    vec![
+        Question::new("Pizza or pasta?", QuestionKind::SingleChoice { options: vec![String::from("Pizza"), String::from("Pasta")] }, true),
+        Question::new("Which days?", QuestionKind::MultipleChoice { options: vec![String::from("Mon"), String::from("Wed"), String::from("Fri")] }, true),
+        Question::new("How hungry are you?", QuestionKind::Likert { points: 5 }, true),
+        Question::new("How many slices?", QuestionKind::NumericRange { min: 0, max: 8 }, true),
+        Question::new("Anything else?", QuestionKind::FreeText { max_len: 100 }, false)
+    ]
+}
+
+#[test]
+// Test that responses survive their text form and are checked against the questions
+fn test_response_schema() {
+    let questions:Vec<Question> = sample_questions();
+    let response = Response::new(vec![
+        Some(Answer::Choice(1)),
+        Some(Answer::Choices(vec![0, 2])),
+        Some(Answer::Scale(5)),
+        Some(Answer::Number(3)),
+        Some(Answer::Text(String::from("No olives\\please\nthanks")))
+    ]);
+    assert_eq!( response.check(&questions), Ok(()) );
+    assert_eq!( response.to_string().lines().count(), 5 );
+    assert_eq!( response.to_string().parse::<Response>(), Ok(response.clone()) );
+
+    // Optional questions can be skipped, required ones can't
+    let mut skipped = response.clone();
+    skipped.answers[4] = None;
+    assert_eq!( skipped.to_string().parse::<Response>(), Ok(skipped.clone()) );
+    assert_eq!( skipped.check(&questions), Ok(()) );
+    skipped.answers[0] = None;
+    assert_eq!( skipped.check(&questions), Err(Error::InvalidResponse) );
+
+    // Out of range, repeated or mistyped answers, and missing answers
+    for (i, answer) in [(0, Answer::Choice(2)), (1, Answer::Choices(vec![2, 0])), (1, Answer::Choices(vec![])),
+                            (2, Answer::Scale(0)), (3, Answer::Number(9)), (4, Answer::Text(String::new())), (0, Answer::Scale(1))] {
+        let mut bad = response.clone();
+        bad.answers[i] = Some(answer);
+        assert_eq!( bad.check(&questions), Err(Error::InvalidResponse) );
+    }
+    assert_eq!( Response::new(response.answers[..4].to_vec()).check(&questions), Err(Error::InvalidResponse) );
+
+    assert_eq!( "yes".parse::<Response>(), Err(Error::InvalidResponse) );
+    assert_eq!( "choice:one".parse::<Response>(), Err(Error::InvalidResponse) );
+    assert_eq!( "text:a\\b".parse::<Response>(), Err(Error::InvalidResponse) );
+    assert!( sample_questions().iter().all(|q| q.is_well_formed()) );
+    assert!( !Question::new("?", QuestionKind::NumericRange { min: 1, max: 0 }, false).is_well_formed() );
+}
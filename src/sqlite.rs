@@ -0,0 +1,480 @@
+extern crate rusqlite;
+
+use std::path::Path;
+use std::time::Duration;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::sync::atomic::{AtomicBool, Ordering};
+use rusqlite::{Connection, params};
+use tbn::{Fr, G1};
+use crate::users::{User, Submission};
+use crate::registry::{UserRegistry, MemoryRegistry};
+use crate::store::{SubmissionBackend, MemoryStore};
+use crate::survey::{FrKey, SurveyList, ParticipantList};
+use crate::encoding::{Encode, Decode, Canonical, Reader};
+use crate::error::{Error, Result};
+
+/*
+ * ----------------------------------------------
+ * |    SQLITE STORAGE                          |
+ * ----------------------------------------------
+ *
+ * With the sqlite feature, a single database file holds everything a single-host deployment needs
+ * to survive restarts: the RA's registered ids and rotations (SqliteRegistry), the submissions an
+ * SA accepted with the time each was received, along with each survey's epoch and close
+ * (SqliteStore), and each SA's owned surveys, participant lists and closed surveys
+ * (Database::save_surveys). The schema is created and upgraded by numbered migrations, tracked in
+ * SQLite's user_version.
+ *
+ * Registries and stores serve reads from memory and write every change through to the database
+ * before it takes effect; updates that touch several rows (replacing a re-identified id, saving
+ * an SA's surveys) run in a single transaction. Since neither would see another's writes, only one
+ * registry and one store can be open on a database (and its clones) at a time: opening a second
+ * fails until the first is dropped. The same goes for other connections to the file: a Database
+ * holds an exclusive lock on it, so opening the file again fails until every clone of the first
+ * is dropped. Clones and handles share the connection behind a lock, so they can be moved to
+ * other threads.
+*/
+
+// Schema changes, in order: the database is at version i once the first i have been applied
+const MIGRATIONS: &[&str] = &[
+    "CREATE TABLE registered_ids (
+        id          BLOB PRIMARY KEY NOT NULL
+    );
+    CREATE TABLE owned_surveys (
+        sa          BLOB NOT NULL,
+        position    INTEGER NOT NULL,
+        vid         BLOB NOT NULL,
+        PRIMARY KEY (sa, position)
+    );
+    CREATE TABLE survey_lists (
+        sa          BLOB NOT NULL,
+        vid         BLOB NOT NULL,
+        list        BLOB NOT NULL,
+        closed      INTEGER NOT NULL,
+        PRIMARY KEY (sa, vid)
+    );
+    CREATE TABLE submissions (
+        seq         INTEGER PRIMARY KEY AUTOINCREMENT,
+        vid         BLOB NOT NULL,
+        token       BLOB NOT NULL,
+        submission  BLOB NOT NULL,
+        UNIQUE (vid, token)
+    );",
+    "CREATE TABLE survey_states (
+        vid         BLOB PRIMARY KEY NOT NULL,
+        epoch       INTEGER NOT NULL,
+        closed_at   INTEGER
+    );",
+    "CREATE TABLE rotations (
+        seq         INTEGER PRIMARY KEY AUTOINCREMENT,
+        old_id      BLOB NOT NULL,
+        new_id      BLOB NOT NULL UNIQUE
+    );",
+    "ALTER TABLE submissions ADD COLUMN received_at INTEGER NOT NULL DEFAULT 0;"
+];
+
+impl From<rusqlite::Error> for Error {
+    fn from(e: rusqlite::Error) -> Self {
+        Error::Database(e.to_string())
+    }
+}
+
+/// Connection to a database file, migrated to the current schema, shared by its clones
+#[derive(Clone)]
+pub struct Database {
+    conn: Arc<Mutex<Connection>>,
+    // Whether a registry, and a store, are open on the connection
+    registry_open: Arc<AtomicBool>,
+    store_open: Arc<AtomicBool>
+}
+
+// Claim on the one registry or store a database allows at a time, given up when dropped
+struct Claim(Arc<AtomicBool>);
+
+impl Claim {
+
+    // Fails with Database if the registry or store (what) is already open
+    fn take(open: &Arc<AtomicBool>, what: &str) -> Result<Self> {
+        match open.compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire) {
+            Ok(_)   => Ok(Claim(open.clone())),
+            Err(_)  => Err(Error::Database(format!("a {} is already open on this database", what)))
+        }
+    }
+}
+
+impl Drop for Claim {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::Release);
+    }
+}
+
+// The connection, for the duration of a statement or transaction
+fn lock(conn: &Mutex<Connection>) -> Result<MutexGuard<'_, Connection>> {
+    conn.lock().map_err(|_| Error::Database(String::from("connection lock poisoned")))
+}
+
+impl Database {
+
+    /// Open (or create) the database at path and apply any pending migrations. Fails with
+    /// Database if it was written by a newer version with migrations this one doesn't know, or if
+    /// another connection has the file open.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let conn = Connection::open(path)?;
+        // Take the file's exclusive lock right away and keep it until the connection is closed,
+        // failing at once rather than waiting if someone else holds it
+        conn.busy_timeout(Duration::ZERO)?;
+        conn.pragma_update(None, "locking_mode", "EXCLUSIVE")?;
+        conn.execute_batch("BEGIN EXCLUSIVE; COMMIT;")?;
+        Database::migrate(conn)
+    }
+
+    /// Database held in memory, gone once every handle on it is dropped
+    pub fn open_in_memory() -> Result<Self> {
+        Database::migrate(Connection::open_in_memory()?)
+    }
+
+    fn migrate(mut conn: Connection) -> Result<Self> {
+        let version:usize = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        if version > MIGRATIONS.len() {
+            return Err(Error::Database(format!("schema version {} is newer than {}", version, MIGRATIONS.len())));
+        }
+        for (i, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+            let tx = conn.transaction()?;
+            tx.execute_batch(migration)?;
+            tx.pragma_update(None, "user_version", i + 1)?;
+            tx.commit()?;
+        }
+        Ok(Database {
+            conn: Arc::new(Mutex::new(conn)),
+            registry_open: Arc::new(AtomicBool::new(false)),
+            store_open: Arc::new(AtomicBool::new(false))
+        })
+    }
+
+    /// Number of migrations applied to the database
+    pub fn schema_version(&self) -> Result<usize> {
+        Ok(lock(&self.conn)?.query_row("PRAGMA user_version", [], |row| row.get(0))?)
+    }
+
+    /// The RA's registered ids and rotations. Fails with Database while another registry is open.
+    pub fn registry(&self) -> Result<SqliteRegistry> {
+        let claim = Claim::take(&self.registry_open, "registry")?;
+        let conn = lock(&self.conn)?;
+        let mut registry = MemoryRegistry::new();
+        let mut query = conn.prepare("SELECT id FROM registered_ids ORDER BY rowid")?;
+        for id in query.query_map([], |row| row.get::<_, Vec<u8>>(0))? {
+            registry.insert(decode(&id?)?)?;
+        }
+        let mut query = conn.prepare("SELECT old_id, new_id FROM rotations ORDER BY seq")?;
+        for row in query.query_map([], |row| Ok((row.get::<_, Vec<u8>>(0)?, row.get::<_, Vec<u8>>(1)?)))? {
+            let (old_id, new_id) = row?;
+            registry.record_rotation(decode(&old_id)?, decode(&new_id)?)?;
+        }
+        Ok(SqliteRegistry { conn: self.conn.clone(), registry, _claim: claim })
+    }
+
+    /// The accepted submissions, and the epoch and close of each survey. Fails with Database while
+    /// another store is open.
+    pub fn store(&self) -> Result<SqliteStore> {
+        let claim = Claim::take(&self.store_open, "store")?;
+        let conn = lock(&self.conn)?;
+        let mut store = MemoryStore::new();
+        let mut query = conn.prepare("SELECT submission, received_at FROM submissions ORDER BY seq")?;
+        for row in query.query_map([], |row| Ok((row.get::<_, Vec<u8>>(0)?, row.get::<_, u64>(1)?)))? {
+            let (bytes, received_at) = row?;
+            store.put(Submission::from_bytes(&bytes)?, received_at)?;
+        }
+        let mut query = conn.prepare("SELECT vid, epoch, closed_at FROM survey_states")?;
+        for row in query.query_map([], |row| Ok((row.get::<_, Vec<u8>>(0)?, row.get::<_, u64>(1)?, row.get::<_, Option<u64>>(2)?)))? {
+            let (vid, epoch, closed_at) = row?;
+            let vid:Fr = decode(&vid)?;
+            store.set_epoch(vid, epoch)?;
+            if let Some(closed_at) = closed_at {
+                store.close(vid, closed_at)?;
+            }
+        }
+        Ok(SqliteStore { conn: self.conn.clone(), store, _claim: claim })
+    }
+
+    /// Replace what the database holds for the SA with its owned surveys, participant lists and
+    /// closed surveys, in a single transaction. SAs are told apart by their key's fingerprint.
+    pub fn save_surveys(&self, sa: &User) -> Result<()> {
+        let key:Vec<u8> = sa.vk.fingerprint().to_vec();
+        let mut conn = lock(&self.conn)?;
+        let tx = conn.transaction()?;
+        tx.execute("DELETE FROM owned_surveys WHERE sa = ?1", [&key])?;
+        tx.execute("DELETE FROM survey_lists WHERE sa = ?1", [&key])?;
+        for (position, vid) in sa.owned_surveys.iter().enumerate() {
+            tx.execute("INSERT INTO owned_surveys (sa, position, vid) VALUES (?1, ?2, ?3)", params![key, position, encode(vid)])?;
+        }
+        for (vid, list) in sa.verid_list.iter() {
+            let closed:bool = sa.closed_surveys.contains(&FrKey::from(vid));
+            tx.execute("INSERT INTO survey_lists (sa, vid, list, closed) VALUES (?1, ?2, ?3, ?4)",
+                       params![key, encode(&vid), (vid, list.clone()).to_bytes(), closed])?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Restore the owned surveys, participant lists and closed surveys last saved for the SA
+    pub fn load_surveys(&self, sa: &mut User) -> Result<()> {
+        let key:Vec<u8> = sa.vk.fingerprint().to_vec();
+        let conn = lock(&self.conn)?;
+        let mut owned_surveys:Vec<Fr> = Vec::new();
+        let mut query = conn.prepare("SELECT vid FROM owned_surveys WHERE sa = ?1 ORDER BY position")?;
+        for vid in query.query_map([&key], |row| row.get::<_, Vec<u8>>(0))? {
+            owned_surveys.push(decode(&vid?)?);
+        }
+
+        let mut verid_list = SurveyList::new();
+        let mut closed_surveys = std::collections::HashSet::new();
+        let mut query = conn.prepare("SELECT list, closed FROM survey_lists WHERE sa = ?1")?;
+        for row in query.query_map([&key], |row| Ok((row.get::<_, Vec<u8>>(0)?, row.get::<_, bool>(1)?)))? {
+            let (bytes, closed) = row?;
+            let (vid, list):(Fr, ParticipantList) = Canonical::from_bytes(&bytes)?;
+            if closed {
+                closed_surveys.insert(FrKey::from(vid));
+            }
+            verid_list.insert(vid, list);
+        }
+
+        sa.owned_surveys = owned_surveys;
+        sa.verid_list = verid_list;
+        sa.closed_surveys = closed_surveys;
+        Ok(())
+    }
+}
+
+/// Registry kept in the registered_ids and rotations tables of a database
+pub struct SqliteRegistry {
+    conn: Arc<Mutex<Connection>>,
+    registry: MemoryRegistry,
+    _claim: Claim
+}
+
+impl UserRegistry for SqliteRegistry {
+
+    fn insert(&mut self, id: Fr) -> Result<bool> {
+        if self.registry.contains(id) {
+            return Ok(false);
+        }
+        lock(&self.conn)?.execute("INSERT INTO registered_ids (id) VALUES (?1)", [encode(&id)])?;
+        self.registry.insert(id)
+    }
+
+    fn remove(&mut self, id: Fr) -> Result<bool> {
+        if !self.registry.contains(id) {
+            return Ok(false);
+        }
+        lock(&self.conn)?.execute("DELETE FROM registered_ids WHERE id = ?1", [encode(&id)])?;
+        self.registry.remove(id)
+    }
+
+    // Both ids and the rotation change in one transaction, so a re-identified user is never lost
+    // or counted twice
+    fn replace(&mut self, old_id: Fr, new_id: Fr) -> Result<bool> {
+        if self.registry.contains(new_id) {
+            return Ok(false);
+        }
+        // Dropping the transaction on an early return rolls it back
+        let mut conn = lock(&self.conn)?;
+        let tx = conn.transaction()?;
+        if tx.execute("DELETE FROM registered_ids WHERE id = ?1", [encode(&old_id)])? != 1 {
+            return Err(Error::UnregisteredId);
+        }
+        tx.execute("INSERT INTO registered_ids (id) VALUES (?1)", [encode(&new_id)])?;
+        tx.execute("INSERT OR IGNORE INTO rotations (old_id, new_id) VALUES (?1, ?2)", [encode(&old_id), encode(&new_id)])?;
+        tx.commit()?;
+        drop(conn);
+        self.registry.replace(old_id, new_id)
+    }
+
+    fn record_rotation(&mut self, old_id: Fr, new_id: Fr) -> Result<()> {
+        lock(&self.conn)?.execute("INSERT OR IGNORE INTO rotations (old_id, new_id) VALUES (?1, ?2)", [encode(&old_id), encode(&new_id)])?;
+        self.registry.record_rotation(old_id, new_id)
+    }
+
+    fn rotations(&self) -> Vec<(Fr, Fr)> {
+        self.registry.rotations()
+    }
+
+    fn contains(&self, id: Fr) -> bool {
+        self.registry.contains(id)
+    }
+
+    fn len(&self) -> usize {
+        self.registry.len()
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = Fr> + '_> {
+        self.registry.iter()
+    }
+}
+
+/// Submission store kept in the submissions and survey_states tables of a database
+pub struct SqliteStore {
+    conn: Arc<Mutex<Connection>>,
+    store: MemoryStore,
+    _claim: Claim
+}
+
+impl SqliteStore {
+
+    /// Time the stored submission for a survey token of survey vid was received at, if any, so
+    /// that it can be checked against the survey's window (0 if it was stored before migration 4)
+    pub fn received_at(&self, vid: Fr, token: G1) -> Result<Option<u64>> {
+        let mut token_bytes:Vec<u8> = Vec::new();
+        token.encode_to(&mut token_bytes);
+        let conn = lock(&self.conn)?;
+        let mut query = conn.prepare("SELECT received_at FROM submissions WHERE vid = ?1 AND token = ?2")?;
+        let mut rows = query.query_map(params![encode(&vid), token_bytes], |row| row.get::<_, u64>(0))?;
+        Ok(rows.next().transpose()?)
+    }
+}
+
+impl SubmissionBackend for SqliteStore {
+
+    // A replaced submission keeps its place in the table, as it does in memory
+    fn put(&mut self, submission: Submission, received_at: u64) -> Result<Option<Submission>> {
+        let mut token:Vec<u8> = Vec::new();
+        submission.token.encode_to(&mut token);
+        lock(&self.conn)?.execute("INSERT INTO submissions (vid, token, submission, received_at) VALUES (?1, ?2, ?3, ?4)
+                           ON CONFLICT (vid, token) DO UPDATE SET submission = excluded.submission, received_at = excluded.received_at",
+                          params![encode(&submission.vid), token, submission.to_bytes(), received_at])?;
+        self.store.put(submission, received_at)
+    }
+
+    fn get(&self, vid: Fr, token: G1) -> Option<&Submission> {
+        self.store.get(vid, token)
+    }
+
+    fn submissions(&self, vid: Fr) -> Vec<&Submission> {
+        self.store.submissions(vid)
+    }
+
+    fn len(&self) -> usize {
+        self.store.len()
+    }
+
+    fn set_epoch(&mut self, vid: Fr, epoch: u64) -> Result<()> {
+        lock(&self.conn)?.execute("INSERT INTO survey_states (vid, epoch) VALUES (?1, ?2)
+                           ON CONFLICT (vid) DO UPDATE SET epoch = excluded.epoch",
+                          params![encode(&vid), epoch])?;
+        self.store.set_epoch(vid, epoch)
+    }
+
+    fn epoch(&self, vid: Fr) -> u64 {
+        self.store.epoch(vid)
+    }
+
+    fn close(&mut self, vid: Fr, closed_at: u64) -> Result<()> {
+        lock(&self.conn)?.execute("INSERT INTO survey_states (vid, epoch, closed_at) VALUES (?1, ?2, ?3)
+                           ON CONFLICT (vid) DO UPDATE SET closed_at = excluded.closed_at",
+                          params![encode(&vid), self.store.epoch(vid), closed_at])?;
+        self.store.close(vid, closed_at)
+    }
+
+    fn closed_at(&self, vid: Fr) -> Option<u64> {
+        self.store.closed_at(vid)
+    }
+}
+
+// Column value of an element of Fr
+fn encode(x: &Fr) -> Vec<u8> {
+    let mut bytes:Vec<u8> = Vec::new();
+    x.encode_to(&mut bytes);
+    bytes
+}
+
+// Element of Fr read back from a column
+fn decode(bytes: &[u8]) -> Result<Fr> {
+    let mut reader = Reader::new(bytes);
+    let x:Fr = Fr::decode_from(&mut reader)?;
+    reader.finish()?;
+    Ok(x)
+}
+
+
+/*
+ * Unit tests
+ */
+
+#[cfg(test)]
+use crate::params::PublicParams;
+#[cfg(test)]
+use crate::users::{SurveyAuthority, RegistrationAuthority, VerificationKey};
+#[cfg(test)]
+use crate::verifier::{SubmissionStore, CheckOutcome};
+#[cfg(test)]
+use crate::clock::FixedClock;
+
+#[test]
+// Test that registrations, survey lists and submissions survive reopening the database, and that
+// re-identifying swaps ids in the registry and records the rotation
+fn test_sqlite_database() {
+    let params = PublicParams::generate();
+    // Removed when dropped, even if an assertion fails
+    let dir = tempfile::tempdir().expect("Creating temporary directory failed");
+    let path = dir.path().join("survey.db");
+    let db = Database::open(&path).expect("Opening database failed");
+    assert_eq!( db.schema_version(), Ok(MIGRATIONS.len()) );
+    // Clones share the connection, from any thread
+    let clone:Database = db.clone();
+    assert_eq!( std::thread::spawn(move || clone.schema_version()).join().unwrap(), Ok(MIGRATIONS.len()) );
+    // No other connection can open the file meanwhile
+    assert!( matches!(Database::open(&path), Err(Error::Database(_))) );
+
+    let mut ra = RegistrationAuthority::new(&params).with_registry(db.registry().expect("Opening registry failed"));
+    let mut sa:User = SurveyAuthority::new(&params);
+    let (mut user, mut other) = (User::new(), User::new());
+    assert!( user.reg_user(&mut ra).is_ok() && other.reg_user(&mut ra).is_ok() );
+    let old_id:Fr = other.re_identify(&mut ra).expect("Re-identifying failed");
+    let ids:Vec<Fr> = ra.registry.ids();
+    assert!( ids.len() == 2 && !ra.registry.contains(old_id) );
+    let rotations:Vec<(Fr, Fr)> = ra.rotations();
+    assert!( rotations.len() == 1 && rotations[0].0 == old_id && ids.contains(&rotations[0].1) );
+
+    let (vid, signatures) = sa.gen_survey(&ids, &params, &ra.vk).expect("SA survey creation failed!");
+    let mut store = SubmissionStore::new(vid).with_backend(db.store().expect("Opening store failed")).with_clock(FixedClock(1500));
+    let submission = user.submit_survey(vid, "yes", &signatures, &sa.vk, &ra.vk, &params).expect("Submission failed");
+    assert_eq!( store.check(submission.clone(), &sa.vk, &ra.vk, &params), CheckOutcome::Accepted );
+    let mut revoked:ParticipantList = signatures.clone();
+    revoked.epoch = 1;
+    assert_eq!( store.update_list(&revoked), Ok(()) );
+    sa.close_survey(&mut store).expect("Closing survey failed");
+    let (closed_at, vk_ra):(Option<u64>, VerificationKey) = (store.closed_at(), ra.vk);
+    db.save_surveys(&sa).expect("Saving surveys failed");
+    drop((ra, store, db));
+
+    // Everything is back after a restart, and the migrations are not applied twice
+    let db = Database::open(&path).expect("Reopening database failed");
+    assert_eq!( db.schema_version(), Ok(MIGRATIONS.len()) );
+    let mut registry = db.registry().expect("Opening registry failed");
+    assert!( registry.len() == 2 && ids.iter().all(|id| registry.contains(*id)) );
+    assert_eq!( registry.rotations(), rotations );
+    // Replacing an id that isn't registered changes nothing, in memory or in the table
+    assert_eq!( registry.replace(old_id, Fr::one()), Err(Error::UnregisteredId) );
+    assert!( !registry.contains(Fr::one()) );
+    // Only one registry is open at a time, and the next one reads the same table
+    assert!( matches!(db.registry(), Err(Error::Database(_))) );
+    drop(registry);
+    let registry = db.registry().expect("Opening registry failed");
+    assert!( registry.len() == 2 && !registry.contains(Fr::one()) );
+    assert_eq!( db.store().expect("Opening store failed").submissions(vid), vec![&submission] );
+    assert_eq!( db.store().and_then(|store| store.received_at(vid, submission.token)), Ok(Some(1500)) );
+    // A store opened over the database is still closed, on the same epoch
+    let mut store = SubmissionStore::new(vid).with_backend(db.store().expect("Opening store failed"));
+    assert!( closed_at.is_some() && store.closed_at() == closed_at && store.epoch() == 1 );
+    assert_eq!( store.check(submission.clone(), &sa.vk, &vk_ra, &params), CheckOutcome::Rejected(Error::SurveyClosed) );
+    let (owned_surveys, verid_list, closed_surveys) = (sa.owned_surveys.clone(), sa.verid_list.clone(), sa.closed_surveys.clone());
+    sa.owned_surveys.clear();
+    sa.verid_list = SurveyList::new();
+    sa.closed_surveys.clear();
+    db.load_surveys(&mut sa).expect("Loading surveys failed");
+    assert!( sa.owned_surveys == owned_surveys && sa.verid_list == verid_list && sa.closed_surveys == closed_surveys );
+
+    // A database from a later version is refused
+    lock(&db.conn).unwrap().pragma_update(None, "user_version", MIGRATIONS.len() + 1).unwrap();
+    drop((registry, store, db));
+    assert!( matches!(Database::open(&path), Err(Error::Database(_))) );
+}
@@ -0,0 +1,483 @@
+extern crate tbn;
+extern crate sha2;
+
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use tbn::{Fr, G1};
+use sha2::{Sha256, Digest};
+use crate::users::{VerificationKey, Submission};
+use crate::params::PublicParams;
+use crate::survey::{FrKey, SurveyList, SurveyDescriptor};
+use crate::encoding::{Encode, Decode, Canonical, Reader};
+use crate::verifier;
+use crate::error::{Error, Result};
+
+/*
+ * ----------------------------------------------
+ * |    SUBMISSION STORES                       |
+ * ----------------------------------------------
+ *
+ * The submissions that pass Check (verifier::SubmissionStore) are kept in a SubmissionBackend,
+ * keyed by survey id and survey token, along with the state Check depends on: the epoch of each
+ * survey's participant list and when the SA closed it. Besides the in-memory store, a FileStore
+ * appends every change to a log file:
+ *      record      length (u32) || body || checksum (4 bytes)
+ *      body        0 || time received (u64) || canonical encoding of the submission
+ *                  1 || vid || epoch (u64)         the survey's list moved on to epoch
+ *                  2 || vid || time closed (u64)   the SA closed the survey
+ * where the checksum is the start of the SHA-256 of the body. Every record is synced before the
+ * change counts as made, and a later submission for the same (vid, token) replaces the earlier
+ * one. On open, the log is replayed record by record. A crash in the middle of a write can only
+ * leave an incomplete or corrupt record at the end of the log, which is cut off; one followed by
+ * more records means the log was damaged, and opening it fails rather than drop what follows.
+ *
+ * The log holds everything Check looked at, so anyone with the SA's and RA's keys and the
+ * survey's published list can rebuild the store from it with FileStore::rebuild, running Check
+ * again on every submission under the epoch in force when it was logged: that the rebuild succeeds
+ * shows the stored responses are exactly ones the store should have accepted.
+*/
+
+// Kinds of log records
+const RECORD_SUBMIT: u8 = 0;
+const RECORD_EPOCH: u8 = 1;
+const RECORD_CLOSE: u8 = 2;
+
+/// Accepted submissions, keyed by survey id and survey token, and the state of each survey
+pub trait SubmissionBackend {
+    /// Store a submission received at time received_at, replacing (and returning) any stored one
+    /// with the same vid and token
+    fn put(&mut self, submission: Submission, received_at: u64) -> Result<Option<Submission>>;
+
+    /// Stored submission for a survey token of survey vid, if any
+    fn get(&self, vid: Fr, token: G1) -> Option<&Submission>;
+
+    /// Stored submissions for survey vid, in the order their tokens were first stored
+    fn submissions(&self, vid: Fr) -> Vec<&Submission>;
+
+    /// Number of stored submissions, across all surveys
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Record that the participant list of survey vid moved on to epoch
+    fn set_epoch(&mut self, vid: Fr, epoch: u64) -> Result<()>;
+
+    /// Epoch of the participant list of survey vid, 0 until one is set
+    fn epoch(&self, vid: Fr) -> u64;
+
+    /// Record that survey vid was closed at time closed_at
+    fn close(&mut self, vid: Fr, closed_at: u64) -> Result<()>;
+
+    /// Time survey vid was closed at, if it was
+    fn closed_at(&self, vid: Fr) -> Option<u64>;
+}
+
+// Map key of a submission: its survey id and the encoding of its token
+fn key(vid: Fr, token: G1) -> (FrKey, Vec<u8>) {
+    let mut token_bytes:Vec<u8> = Vec::new();
+    token.encode_to(&mut token_bytes);
+    (FrKey::from(vid), token_bytes)
+}
+
+/// Store held in memory only
+#[derive(Clone, Debug, Default)]
+pub struct MemoryStore {
+    submissions: Vec<Submission>,
+    // Position of each (vid, token)
+    index: HashMap<(FrKey, Vec<u8>), usize>,
+    epochs: HashMap<FrKey, u64>,
+    closed: HashMap<FrKey, u64>
+}
+
+impl MemoryStore {
+
+    pub fn new() -> Self {
+        MemoryStore::default()
+    }
+
+    // Make the change a log record describes
+    fn apply(&mut self, record: Record) -> Result<()> {
+        match record {
+            Record::Submit(submission, received_at) => self.put(*submission, received_at).map(|_| ()),
+            Record::Epoch(vid, epoch)               => self.set_epoch(vid, epoch),
+            Record::Close(vid, closed_at)           => self.close(vid, closed_at)
+        }
+    }
+}
+
+impl SubmissionBackend for MemoryStore {
+
+    fn put(&mut self, submission: Submission, _received_at: u64) -> Result<Option<Submission>> {
+        match self.index.get(&key(submission.vid, submission.token)) {
+            Some(&i)    => Ok(Some(std::mem::replace(&mut self.submissions[i], submission))),
+            None        => {
+                self.index.insert(key(submission.vid, submission.token), self.submissions.len());
+                self.submissions.push(submission);
+                Ok(None)
+            }
+        }
+    }
+
+    fn get(&self, vid: Fr, token: G1) -> Option<&Submission> {
+        self.index.get(&key(vid, token)).map(|&i| &self.submissions[i])
+    }
+
+    fn submissions(&self, vid: Fr) -> Vec<&Submission> {
+        self.submissions.iter().filter(|stored| stored.vid == vid).collect()
+    }
+
+    fn len(&self) -> usize {
+        self.submissions.len()
+    }
+
+    fn set_epoch(&mut self, vid: Fr, epoch: u64) -> Result<()> {
+        self.epochs.insert(FrKey::from(vid), epoch);
+        Ok(())
+    }
+
+    fn epoch(&self, vid: Fr) -> u64 {
+        self.epochs.get(&FrKey::from(vid)).cloned().unwrap_or(0)
+    }
+
+    fn close(&mut self, vid: Fr, closed_at: u64) -> Result<()> {
+        self.closed.insert(FrKey::from(vid), closed_at);
+        Ok(())
+    }
+
+    fn closed_at(&self, vid: Fr) -> Option<u64> {
+        self.closed.get(&FrKey::from(vid)).cloned()
+    }
+}
+
+// Change recorded in a FileStore's log
+#[derive(Clone, Debug, PartialEq)]
+enum Record {
+    Submit(Box<Submission>, u64),
+    Epoch(Fr, u64),
+    Close(Fr, u64)
+}
+
+/// Store kept in an append-only log file
+pub struct FileStore {
+    store: MemoryStore,
+    log: File,
+    // Length of the log up to the last record written in full
+    log_len: u64,
+    // Whether a torn record follows, to be cut off before the next one is written
+    torn: bool
+}
+
+impl FileStore {
+
+    /// Open the store logged at path, creating an empty log if needed. Submissions are not checked
+    /// again, see rebuild for that.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let (records, log_len) = FileStore::replay(path.as_ref())?;
+        let mut store = MemoryStore::new();
+        for record in records {
+            store.apply(record)?;
+        }
+        let mut file = FileStore::with_store(path.as_ref(), store, log_len)?;
+        file.cut()?;
+        Ok(file)
+    }
+
+    /// Open the store logged at path, running Check again on every logged submission against the
+    /// published participant lists in lists. Each submission must have been made under the epoch
+    /// in force when it was logged (so submissions accepted before a revocation stay valid), the
+    /// log may not move a survey's epoch backwards or past its published list's, nor close a
+    /// survey with no published list, and nothing may be submitted to a survey after it was
+    /// closed. Surveys with one of the given descriptors must also have received each submission
+    /// within their window, with a response answering their questions. Fails with
+    /// InvalidSubmissionLog listing the (0-based) records that don't pass, if any. The log is left
+    /// as it is: a torn record at its end is only cut off once something is stored.
+    pub fn rebuild<P: AsRef<Path>>(path: P, vk_sa: &VerificationKey, vk_ra: &VerificationKey, params: &PublicParams,
+                                   lists: &SurveyList, descriptors: &[SurveyDescriptor]) -> Result<Self> {
+        let (records, log_len) = FileStore::replay(path.as_ref())?;
+        let mut store = MemoryStore::new();
+        let mut invalid:Vec<usize> = Vec::new();
+        for (i, record) in records.into_iter().enumerate() {
+            let valid:Result<()> = match &record {
+                Record::Submit(submission, received_at) => {
+                    let descriptor = descriptors.iter().find(|descriptor| descriptor.vid() == submission.vid);
+                    lists.get(submission.vid).ok_or(Error::UnknownSurvey)
+                        .and_then(|_| match store.closed_at(submission.vid) {
+                            Some(_) => Err(Error::SurveyClosed),
+                            None    => Ok(())
+                        })
+                        .and_then(|_| verifier::check(submission, vk_sa, vk_ra, submission.vid, store.epoch(submission.vid), params))
+                        .and_then(|_| descriptor.map_or(Ok(()), |descriptor| descriptor.check_open(*received_at)))
+                        .and_then(|_| descriptor.map_or(Ok(()), |descriptor| descriptor.check_response(&submission.response)))
+                },
+                Record::Epoch(vid, epoch) => match lists.get(*vid) {
+                    Some(list) if store.epoch(*vid) <= *epoch && *epoch <= list.epoch => Ok(()),
+                    Some(_) => Err(Error::StaleSurveyList),
+                    None    => Err(Error::UnknownSurvey)
+                },
+                Record::Close(vid, _) => lists.get(*vid).map(|_| ()).ok_or(Error::UnknownSurvey)
+            };
+            match valid {
+                Ok(())  => store.apply(record)?,
+                Err(_)  => invalid.push(i)
+            }
+        }
+        match invalid.is_empty() {
+            true    => FileStore::with_store(path.as_ref(), store, log_len),
+            false   => Err(Error::InvalidSubmissionLog(invalid))
+        }
+    }
+
+    // Store over the log at path, holding what was replayed from its first log_len bytes
+    fn with_store(path: &Path, store: MemoryStore, log_len: u64) -> Result<Self> {
+        let log:File = OpenOptions::new().create(true).append(true).open(path)?;
+        let torn:bool = log.metadata()?.len() > log_len;
+        Ok(FileStore { store, log, log_len, torn })
+    }
+
+    // Records in the log at path, and the length of the log up to the end of the last of them.
+    // Only the final record may be incomplete or corrupt; the log is not changed.
+    fn replay(path: &Path) -> Result<(Vec<Record>, u64)> {
+        let bytes:Vec<u8> = match fs::read(path) {
+            Ok(bytes)   => bytes,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok((Vec::new(), 0)),
+            Err(e)      => return Err(e.into())
+        };
+        let mut reader = Reader::new(&bytes);
+        let mut records:Vec<Record> = Vec::new();
+        while reader.remaining() > 0 {
+            match decode_record(&mut reader) {
+                Ok(record)  => records.push(record),
+                Err(_) if is_last_record(&reader) => break,
+                Err(e)      => return Err(e)
+            }
+        }
+        Ok((records, (bytes.len() - reader.remaining()) as u64))
+    }
+
+    // Cut a torn record off the end of the log, if replaying it left one there
+    fn cut(&mut self) -> Result<()> {
+        if self.torn {
+            self.log.set_len(self.log_len)?;
+            self.log.sync_all()?;
+            self.torn = false;
+        }
+        Ok(())
+    }
+
+    // Append a record to the log and sync it. If that fails, the log is cut back to the records
+    // before it, so a partly written record can't be followed by later ones.
+    fn append(&mut self, record: &Record) -> Result<()> {
+        self.cut()?;
+        let bytes:Vec<u8> = encode_record(record);
+        match self.log.write_all(&bytes).and_then(|_| self.log.sync_data()) {
+            Ok(())  => {
+                self.log_len += bytes.len() as u64;
+                Ok(())
+            },
+            Err(e)  => {
+                let _ = self.log.set_len(self.log_len);
+                Err(e.into())
+            }
+        }
+    }
+}
+
+impl SubmissionBackend for FileStore {
+
+    fn put(&mut self, submission: Submission, received_at: u64) -> Result<Option<Submission>> {
+        self.append(&Record::Submit(Box::new(submission.clone()), received_at))?;
+        self.store.put(submission, received_at)
+    }
+
+    fn get(&self, vid: Fr, token: G1) -> Option<&Submission> {
+        self.store.get(vid, token)
+    }
+
+    fn submissions(&self, vid: Fr) -> Vec<&Submission> {
+        self.store.submissions(vid)
+    }
+
+    fn len(&self) -> usize {
+        self.store.len()
+    }
+
+    fn set_epoch(&mut self, vid: Fr, epoch: u64) -> Result<()> {
+        self.append(&Record::Epoch(vid, epoch))?;
+        self.store.set_epoch(vid, epoch)
+    }
+
+    fn epoch(&self, vid: Fr) -> u64 {
+        self.store.epoch(vid)
+    }
+
+    fn close(&mut self, vid: Fr, closed_at: u64) -> Result<()> {
+        self.append(&Record::Close(vid, closed_at))?;
+        self.store.close(vid, closed_at)
+    }
+
+    fn closed_at(&self, vid: Fr) -> Option<u64> {
+        self.store.closed_at(vid)
+    }
+}
+
+// Log record: length || body || checksum
+fn encode_record(record: &Record) -> Vec<u8> {
+    let mut body:Vec<u8> = Vec::new();
+    match record {
+        Record::Submit(submission, received_at) => {
+            body.push(RECORD_SUBMIT);
+            received_at.encode_to(&mut body);
+            body.extend_from_slice(&submission.to_bytes());
+        },
+        Record::Epoch(vid, epoch) => {
+            body.push(RECORD_EPOCH);
+            vid.encode_to(&mut body);
+            epoch.encode_to(&mut body);
+        },
+        Record::Close(vid, closed_at) => {
+            body.push(RECORD_CLOSE);
+            vid.encode_to(&mut body);
+            closed_at.encode_to(&mut body);
+        }
+    }
+    let mut bytes:Vec<u8> = Vec::new();
+    (body.len() as u32).encode_to(&mut bytes);
+    bytes.extend_from_slice(&body);
+    bytes.extend_from_slice(&Sha256::digest(&body)[..4]);
+    bytes
+}
+
+// Next record in the log, leaving the reader where it was if the record is incomplete or corrupt
+fn decode_record(reader: &mut Reader) -> Result<Record> {
+    let mut record:Reader = *reader;
+    let len:usize = u32::decode_from(&mut record)? as usize;
+    let bytes:&[u8] = record.take(len)?;
+    if Sha256::digest(bytes)[..4] != *record.take(4)? {
+        return Err(Error::MalformedEncoding);
+    }
+    let mut body = Reader::new(bytes);
+    let decoded:Record = match body.read_u8()? {
+        RECORD_SUBMIT   => {
+            let received_at:u64 = u64::decode_from(&mut body)?;
+            Record::Submit(Box::new(Submission::from_bytes(body.take(body.remaining())?)?), received_at)
+        },
+        RECORD_EPOCH    => Record::Epoch(Fr::decode_from(&mut body)?, u64::decode_from(&mut body)?),
+        RECORD_CLOSE    => Record::Close(Fr::decode_from(&mut body)?, u64::decode_from(&mut body)?),
+        _               => return Err(Error::MalformedEncoding)
+    };
+    body.finish()?;
+    *reader = record;
+    Ok(decoded)
+}
+
+// Whether the record the reader is at runs to the end of the log (or past it), as the record being
+// written when a crash hit would
+fn is_last_record(reader: &Reader) -> bool {
+    let mut record:Reader = *reader;
+    match u32::decode_from(&mut record) {
+        Ok(len) => record.remaining() <= len as usize + 4,
+        Err(_)  => true
+    }
+}
+
+
+/*
+ * Unit tests
+ */
+
+#[cfg(test)]
+use crate::zkp;
+#[cfg(test)]
+use crate::users::User;
+#[cfg(test)]
+use crate::survey::ParticipantList;
+
+#[test]
+// Test that both stores keep one submission per token along with each survey's state, and that the
+// file store survives a restart and can be rebuilt by verifying its log
+fn test_submission_stores() {
+    let (params, ra, sa, user, vid, signatures) = zkp::setup_submission();
+    let submit = |user:&User, response:&str| user.submit_survey(vid, response, &signatures, &sa.vk, &ra.vk, &params).expect("Submission failed");
+    let (first, second) = (submit(&user, "yes"), submit(&user, "no"));
+    let mut lists = SurveyList::new();
+    lists.insert(vid, signatures.clone());
+    // Removed when dropped, even if an assertion fails
+    let dir = tempfile::tempdir().expect("Creating temporary directory failed");
+    let path = dir.path().join("submissions");
+
+    let mut memory = MemoryStore::new();
+    let mut file = FileStore::open(&path).expect("Opening store failed");
+    let stores:[&mut dyn SubmissionBackend; 2] = [&mut memory, &mut file];
+    for store in stores {
+        assert_eq!( store.put(first.clone(), 0), Ok(None) );
+        assert_eq!( store.put(second.clone(), 0), Ok(Some(first.clone())) );
+        assert_eq!( store.len(), 1 );
+        assert_eq!( store.get(vid, first.token), Some(&second) );
+        assert_eq!( store.get(vid + Fr::one(), first.token), None );
+        assert_eq!( store.submissions(vid), vec![&second] );
+        assert_eq!( (store.epoch(vid), store.closed_at(vid)), (0, None) );
+    }
+    drop(file);
+
+    // The log keeps both records, and replaying it ends on the latest response
+    let reopened = FileStore::open(&path).expect("Reopening store failed");
+    assert_eq!( reopened.submissions(vid), vec![&second] );
+    let rebuilt = FileStore::rebuild(&path, &sa.vk, &ra.vk, &params, &lists, &[]).expect("Rebuilding store failed");
+    assert_eq!( rebuilt.submissions(vid), vec![&second] );
+    drop((reopened, rebuilt));
+
+    // Submissions to a survey without a published list don't pass
+    assert_eq!( FileStore::rebuild(&path, &sa.vk, &ra.vk, &params, &SurveyList::new(), &[]).map(|store| store.len()), Err(Error::InvalidSubmissionLog(vec![0, 1])) );
+
+    // A torn record at the end of the log is dropped
+    let mut log:Vec<u8> = fs::read(&path).unwrap();
+    let len:usize = log.len();
+    log.extend_from_slice(&encode_record(&Record::Submit(Box::new(first.clone()), 0))[..100]);
+    fs::write(&path, &log).unwrap();
+    // Rebuilding leaves it in place, opening cuts it off
+    assert_eq!( FileStore::rebuild(&path, &sa.vk, &ra.vk, &params, &lists, &[]).map(|store| store.len()), Ok(1) );
+    assert_eq!( fs::metadata(&path).unwrap().len() as usize, len + 100 );
+    assert_eq!( FileStore::open(&path).map(|store| store.len()), Ok(1) );
+    assert_eq!( fs::metadata(&path).unwrap().len() as usize, len );
+
+    // A corrupt record followed by others fails to open, and the log is kept as it is
+    let mut corrupt:Vec<u8> = log[..len].to_vec();
+    corrupt[10] ^= 1;
+    fs::write(&path, &corrupt).unwrap();
+    assert_eq!( FileStore::open(&path).map(|store| store.len()), Err(Error::MalformedEncoding) );
+    assert_eq!( FileStore::rebuild(&path, &sa.vk, &ra.vk, &params, &lists, &[]).map(|store| store.len()), Err(Error::MalformedEncoding) );
+    assert_eq!( fs::read(&path).unwrap(), corrupt );
+    fs::write(&path, &log[..len]).unwrap();
+
+    // A well-formed record that does not verify is found on rebuild, though open takes it as is
+    let mut tampered:Submission = second.clone();
+    tampered.response = String::from("maybe");
+    FileStore::open(&path).and_then(|mut store| store.put(tampered, 0)).expect("Storing failed");
+    assert_eq!( FileStore::open(&path).map(|store| store.get(vid, first.token).map(|stored| stored.response.clone())), Ok(Some(String::from("maybe"))) );
+    assert_eq!( FileStore::rebuild(&path, &sa.vk, &ra.vk, &params, &lists, &[]).map(|store| store.len()), Err(Error::InvalidSubmissionLog(vec![2])) );
+    fs::remove_file(&path).unwrap();
+
+    // After a revocation, submissions under the old epoch are refused, and after closing, any at all
+    let mut file = FileStore::open(&path).expect("Opening store failed");
+    assert_eq!( file.put(first.clone(), 0), Ok(None) );
+    assert_eq!( file.set_epoch(vid, 1), Ok(()) );
+    assert_eq!( file.put(second.clone(), 0), Ok(Some(first.clone())) );
+    assert_eq!( file.close(vid, 5), Ok(()) );
+    assert_eq!( file.put(first.clone(), 6), Ok(Some(second.clone())) );
+    drop(file);
+    let reopened = FileStore::open(&path).expect("Reopening store failed");
+    assert_eq!( (reopened.epoch(vid), reopened.closed_at(vid)), (1, Some(5)) );
+    let mut revoked:ParticipantList = signatures.clone();
+    revoked.epoch = 1;
+    lists.insert(vid, revoked);
+    assert_eq!( FileStore::rebuild(&path, &sa.vk, &ra.vk, &params, &lists, &[]).map(|store| store.len()), Err(Error::InvalidSubmissionLog(vec![2, 4])) );
+    // The log can't move the epoch past the published list's
+    lists.insert(vid, signatures.clone());
+    assert_eq!( FileStore::rebuild(&path, &sa.vk, &ra.vk, &params, &lists, &[]).map(|store| store.len()), Err(Error::InvalidSubmissionLog(vec![1, 4])) );
+    // Nor change or close a survey that was never published
+    assert_eq!( FileStore::rebuild(&path, &sa.vk, &ra.vk, &params, &SurveyList::new(), &[]).map(|store| store.len()), Err(Error::InvalidSubmissionLog(vec![0, 1, 2, 3, 4])) );
+}
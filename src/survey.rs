@@ -0,0 +1,342 @@
+extern crate tbn;
+extern crate sha2;
+
+use std::collections::{HashMap, BTreeMap};
+use std::ops::Deref;
+use std::iter::FromIterator;
+use tbn::{Fr, G1, G2};
+use sha2::{Sha512, Digest};
+use crate::users::VerificationKey;
+use crate::params::{PublicParams, Fingerprint};
+use crate::schema::{Question, Response};
+use crate::zkp::{self, KeySignature};
+use crate::encoding::{Encode, Canonical};
+use crate::error::{Error, Result};
+
+/*
+ * ----------------------------------------------
+ * |    SURVEY LISTS                            |
+ * ----------------------------------------------
+ *
+ * A published survey list Lvid holds the SA's signature (sigma_1, sigma_2) on (vid, id) for every
+ * participant id. Participants are looked up by id (Authorized, SubmitSurvey) and surveys by vid
+ * (the SA's own records), so both are indexed by the canonical encoding of the id instead of being
+ * scanned. Entries keep the order in which they were added, which is the order they are published in.
+ *
+ * A list can be amended after publication. Every amendment bumps its version, so verifiers can tell
+ * which list they hold. Adding participants only signs the new ids, but revoking one has to stop
+ * their already published signature from being accepted: it starts a new epoch, and the SA signs
+ * (vid, epoch) rather than vid itself, re-signing everyone who remains. Submissions name the epoch
+ * they were made under and are refused once a later epoch has begun.
+ *
+ * Survey ids are random by default, but can instead be derived from a SurveyDescriptor (title,
+ * questions, opening times and the SA's key), so that the id commits to what the survey is and
+ * participants can check that the survey they are answering is the one the SA set up. The SA also
+ * signs the descriptor, so clients can render the questions knowing who asked them, and verifiers
+ * can refuse responses that don't fit the questions (see schema). A descriptor can also set a
+ * minimum number of responses below which results are withheld, since answers from a handful of
+ * people can often be traced back to them.
+*/
+
+/// Public description of a survey, which its id can be derived from
+#[derive(Clone, Debug, PartialEq)]
+pub struct SurveyDescriptor {
+    pub title: String,
+    pub questions: Vec<Question>,
+    /// Unix times (in seconds) at which the survey opens and closes for submissions: it accepts
+    /// them from opens_at up to, but not including, closes_at
+    pub opens_at: u64,
+    pub closes_at: u64,
+    /// Least number of distinct survey tokens that must have responded before any results are
+    /// released (0 or 1 for no threshold)
+    pub min_responses: u32,
+    /// Fingerprint of the verification key of the SA running the survey
+    pub sa: Fingerprint
+}
+
+impl SurveyDescriptor {
+
+    pub fn new(title: &str, questions: Vec<Question>, opens_at: u64, closes_at: u64, vk_sa: &VerificationKey) -> Self {
+        SurveyDescriptor {
+            title: String::from(title),
+            questions,
+            opens_at,
+            closes_at,
+            min_responses: 0,
+            sa: vk_sa.fingerprint()
+        }
+    }
+
+    /// Withhold results until k participants have responded
+    pub fn with_min_responses(mut self, k: u32) -> Self {
+        self.min_responses = k;
+        self
+    }
+
+    /// Survey id committing to the descriptor: SHA-512 of its canonical encoding, reduced into Fr
+    pub fn vid(&self) -> Fr {
+        let mut transcript:Vec<u8> = b"anon_survey/survey_id".to_vec();
+        self.encode_to(&mut transcript);
+
+        let mut digest:[u8; 64] = [0; 64];
+        digest.copy_from_slice(&Sha512::digest(&transcript));
+        Fr::interpret(&digest)
+    }
+
+    /// Fails with InvalidDescriptor unless vid was derived from this descriptor, the descriptor
+    /// names the SA with key vk_sa, the survey closes no earlier than it opens and it has at least
+    /// one question, all of which can be answered
+    pub fn check(&self, vid: Fr, vk_sa: &VerificationKey) -> Result<()> {
+        let well_formed:bool = !self.questions.is_empty() && self.questions.iter().all(|question| question.is_well_formed());
+        match self.vid() == vid && self.sa == vk_sa.fingerprint() && self.opens_at <= self.closes_at && well_formed {
+            true    => Ok(()),
+            false   => Err(Error::InvalidDescriptor)
+        }
+    }
+
+    /// Fails with SurveyNotOpen before the survey opens and SurveyClosed from the time it closes
+    pub fn check_open(&self, now: u64) -> Result<()> {
+        if now < self.opens_at {
+            return Err(Error::SurveyNotOpen);
+        }
+        match now < self.closes_at {
+            true    => Ok(()),
+            false   => Err(Error::SurveyClosed)
+        }
+    }
+
+    /// Fails with InvalidResponse unless response is the text form of a Response answering the
+    /// survey's questions
+    pub fn check_response(&self, response: &str) -> Result<()> {
+        response.parse::<Response>()?.check(&self.questions)
+    }
+}
+
+/// Survey descriptor signed by the SA running the survey
+#[derive(Clone, Debug, PartialEq)]
+pub struct SignedDescriptor {
+    pub descriptor: SurveyDescriptor,
+    pub signature: KeySignature
+}
+
+impl SignedDescriptor {
+
+    /// Fails with InvalidDescriptor if the descriptor does not check against vid and vk_sa, and
+    /// InvalidSignature unless the SA with key vk_sa signed it
+    pub fn verify(&self, vid: Fr, vk_sa: &VerificationKey, params: &PublicParams) -> Result<()> {
+        params.check_key(vk_sa)?;
+        self.descriptor.check(vid, vk_sa)?;
+        match zkp::verify_with_key(params, vk_sa, &self.descriptor.to_bytes(), &self.signature) {
+            true    => Ok(()),
+            false   => Err(Error::InvalidSignature)
+        }
+    }
+}
+
+/// Value signed for survey vid in the given epoch: vid itself until a participant is first
+/// revoked, then a hash of (vid, epoch)
+pub fn signed_vid(vid: Fr, epoch: u64) -> Fr {
+    if epoch == 0 {
+        return vid;
+    }
+    let mut transcript:Vec<u8> = b"anon_survey/survey_epoch".to_vec();
+    vid.encode_to(&mut transcript);
+    epoch.encode_to(&mut transcript);
+
+    let mut digest:[u8; 64] = [0; 64];
+    digest.copy_from_slice(&Sha512::digest(&transcript));
+    Fr::interpret(&digest)
+}
+
+/// Canonical 32-byte big endian encoding of an element of Fr, usable as a map key
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct FrKey(pub [u8; 32]);
+
+impl From<Fr> for FrKey {
+    fn from(x: Fr) -> Self {
+        let mut bytes:Vec<u8> = Vec::new();
+        x.encode_to(&mut bytes);
+        let mut key:[u8; 32] = [0; 32];
+        key.copy_from_slice(&bytes);
+        FrKey(key)
+    }
+}
+
+/// Published list of participant entries (id, sigma_1, sigma_2) of one survey, with at most one
+/// entry per id. Derefs to the slice of entries in publication order.
+#[derive(Clone, Debug, Default)]
+pub struct ParticipantList {
+    /// Number of amendments since the list was first published
+    pub version: u64,
+    /// Number of amendments that revoked participants: entries are signatures on signed_vid(vid, epoch)
+    pub epoch: u64,
+    entries: Vec<(Fr, G1, G2)>,
+    // Position of each id's entry
+    index: HashMap<FrKey, usize>
+}
+
+impl ParticipantList {
+
+    pub fn new() -> Self {
+        ParticipantList::default()
+    }
+
+    /// Add a participant entry. If the id is already on the list its signature is replaced in
+    /// place and the old entry returned.
+    pub fn insert(&mut self, entry: (Fr, G1, G2)) -> Option<(Fr, G1, G2)> {
+        match self.index.get(&FrKey::from(entry.0)) {
+            Some(&i)    => Some(std::mem::replace(&mut self.entries[i], entry)),
+            None        => {
+                self.index.insert(FrKey::from(entry.0), self.entries.len());
+                self.entries.push(entry);
+                None
+            }
+        }
+    }
+
+    /// Entry of participant id, if they are on the list
+    pub fn get(&self, id: Fr) -> Option<&(Fr, G1, G2)> {
+        self.index.get(&FrKey::from(id)).map(|&i| &self.entries[i])
+    }
+
+    pub fn contains(&self, id: Fr) -> bool {
+        self.index.contains_key(&FrKey::from(id))
+    }
+
+    /// Remove participant id from the list, returning their entry. Later entries move up by one.
+    pub fn remove(&mut self, id: Fr) -> Option<(Fr, G1, G2)> {
+        let i:usize = self.index.remove(&FrKey::from(id))?;
+        let entry = self.entries.remove(i);
+        for (part_id, _, _) in &self.entries[i..] {
+            *self.index.get_mut(&FrKey::from(*part_id)).expect("Every entry is indexed") -= 1;
+        }
+        Some(entry)
+    }
+}
+
+impl Deref for ParticipantList {
+    type Target = [(Fr, G1, G2)];
+
+    fn deref(&self) -> &Self::Target {
+        &self.entries
+    }
+}
+
+// The index is determined by the entries
+impl PartialEq for ParticipantList {
+    fn eq(&self, other: &Self) -> bool {
+        (self.version, self.epoch) == (other.version, other.epoch) && self.entries == other.entries
+    }
+}
+
+impl FromIterator<(Fr, G1, G2)> for ParticipantList {
+    fn from_iter<I: IntoIterator<Item = (Fr, G1, G2)>>(iter: I) -> Self {
+        let mut list = ParticipantList::new();
+        for entry in iter {
+            list.insert(entry);
+        }
+        list
+    }
+}
+
+impl From<Vec<(Fr, G1, G2)>> for ParticipantList {
+    fn from(entries: Vec<(Fr, G1, G2)>) -> Self {
+        entries.into_iter().collect()
+    }
+}
+
+impl<'a> IntoIterator for &'a ParticipantList {
+    type Item = &'a (Fr, G1, G2);
+    type IntoIter = std::slice::Iter<'a, (Fr, G1, G2)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.iter()
+    }
+}
+
+/// Participant lists of the surveys an SA owns, by survey id
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SurveyList {
+    surveys: BTreeMap<FrKey, (Fr, ParticipantList)>
+}
+
+impl SurveyList {
+
+    pub fn new() -> Self {
+        SurveyList::default()
+    }
+
+    /// Record the participant list of survey vid, returning the list it replaces if any
+    pub fn insert(&mut self, vid: Fr, list: ParticipantList) -> Option<ParticipantList> {
+        self.surveys.insert(FrKey::from(vid), (vid, list)).map(|(_, old)| old)
+    }
+
+    pub fn get(&self, vid: Fr) -> Option<&ParticipantList> {
+        self.surveys.get(&FrKey::from(vid)).map(|(_, list)| list)
+    }
+
+    pub fn get_mut(&mut self, vid: Fr) -> Option<&mut ParticipantList> {
+        self.surveys.get_mut(&FrKey::from(vid)).map(|(_, list)| list)
+    }
+
+    pub fn contains(&self, vid: Fr) -> bool {
+        self.surveys.contains_key(&FrKey::from(vid))
+    }
+
+    pub fn len(&self) -> usize {
+        self.surveys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.surveys.is_empty()
+    }
+
+    /// (vid, participant list) of every survey, ordered by the encoding of vid
+    pub fn iter(&self) -> impl Iterator<Item = (Fr, &ParticipantList)> {
+        self.surveys.values().map(|(vid, list)| (*vid, list))
+    }
+}
+
+
+/*
+ * Unit tests
+ */
+
+#[cfg(test)]
+use tbn::Group;
+
+#[test]
+// Test that participants are found by id, keep their order and are never listed twice
+fn test_participant_list() {
+    let rng = &mut rand::thread_rng();
+    let ids:Vec<Fr> = (0..10).map(|_| Fr::random(rng)).collect();
+    let mut list:ParticipantList = ids.iter().map(|id| (*id, G1::one() * *id, G2::one())).collect();
+
+    assert_eq!( list.len(), 10 );
+    assert!( list.iter().map(|entry| entry.0).eq(ids.iter().cloned()) );
+    assert_eq!( list.get(ids[4]), Some(&(ids[4], G1::one() * ids[4], G2::one())) );
+    assert!( list.get(Fr::random(rng)).is_none() && !list.contains(Fr::random(rng)) );
+
+    // Re-signing an id replaces its entry in place
+    let old = list.insert((ids[4], G1::zero(), G2::zero()));
+    assert_eq!( old, Some((ids[4], G1::one() * ids[4], G2::one())) );
+    assert_eq!( list.len(), 10 );
+    assert_eq!( list[4], (ids[4], G1::zero(), G2::zero()) );
+
+    // Removal keeps the order of the remaining entries and their index
+    assert_eq!( list.remove(ids[2]).map(|entry| entry.0), Some(ids[2]) );
+    assert!( list.remove(ids[2]).is_none() && !list.contains(ids[2]) );
+    assert_eq!( list.len(), 9 );
+    assert_eq!( list.get(ids[9]), Some(&list[8]) );
+    assert_eq!( list.get(ids[3]).map(|entry| entry.0), Some(ids[3]) );
+    assert_eq!( signed_vid(ids[0], 0), ids[0] );
+    assert!( signed_vid(ids[0], 1) != signed_vid(ids[0], 2) );
+
+    let mut surveys = SurveyList::new();
+    let vid = Fr::random(rng);
+    assert!( surveys.insert(vid, list.clone()).is_none() );
+    assert!( surveys.contains(vid) && !surveys.contains(Fr::random(rng)) );
+    assert_eq!( surveys.get(vid), Some(&list) );
+    assert_eq!( surveys.insert(vid, ParticipantList::new()), Some(list) );
+    assert_eq!( surveys.len(), 1 );
+}
@@ -0,0 +1,364 @@
+use std::collections::BTreeMap;
+use crate::schema::{QuestionKind, Answer, Response};
+use crate::survey::{SurveyDescriptor, ParticipantList};
+use crate::verifier::SubmissionStore;
+use crate::error::{Error, Result};
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize};
+
+/*
+ * ----------------------------------------------
+ * |    TALLY                                   |
+ * ----------------------------------------------
+ *
+ * Results of a survey are computed from the submissions its SubmissionStore accepted, which hold at
+ * most one response per survey token, so every participant is counted once (with their last
+ * response). Each question is summarised according to its kind:
+ *      - single and multiple choice: how many responses picked each option
+ *      - Likert scale: histogram over the points, mean and median
+ *      - numeric range: count of every value given, mean and median
+ *      - free text: the answers themselves
+ * Two choice or scale questions can also be cross-tabulated against each other. Reports (and with
+ * the serde feature, their JSON) only ever gain fields, so dashboards can rely on their layout.
+ *
+ * Nothing is released before the survey has ended, and until at least min_responses (k) of the
+ * descriptor's participants have responded. A question (or pair of cross-tabulated questions)
+ * answered by fewer than k of them is withheld from the report, down to how many answered it. The
+ * published participant list bounds k: a threshold above the number of participants can never be
+ * met, which means the list or descriptor is wrong.
+*/
+
+/// Results of a survey
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TallyReport {
+    pub title: String,
+    /// Number of responses tallied
+    pub responses: usize,
+    /// Stored responses that don't answer the descriptor's questions, and were left out
+    pub invalid: usize,
+    /// Results of each question, in order
+    pub questions: Vec<QuestionTally>
+}
+
+/// Results of a single question
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct QuestionTally {
+    pub text: String,
+    /// Responses that answered the question, and that left it unanswered (both 0 if it is withheld)
+    pub answered: usize,
+    pub skipped: usize,
+    pub result: QuestionResult,
+    /// Whether the result is withheld, in which case the counts above are too
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub withheld: bool
+}
+
+/// Summary of the answers to a question, by its kind
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "type", rename_all = "snake_case"))]
+pub enum QuestionResult {
+    /// Number of responses picking each option, for single and multiple choice questions
+    Choice { options: Vec<OptionCount> },
+    /// histogram[i] responses picked point i + 1 of the scale
+    Scale { histogram: Vec<usize>, mean: Option<f64>, median: Option<f64> },
+    /// Number of responses giving each value, by increasing value
+    Numeric { histogram: Vec<ValueCount>, mean: Option<f64>, median: Option<f64> },
+    /// Every free text answer, in the order the responses are stored
+    Text { answers: Vec<String> },
+    /// Too few responses answered the question to release its answers
+    Withheld
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct OptionCount {
+    pub option: String,
+    pub count: usize
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ValueCount {
+    pub value: i64,
+    pub count: usize
+}
+
+/// Cross-tabulation of two choice or scale questions: counts[i][j] responses gave row answer i
+/// together with column answer j. Each option picked in a multiple choice answer counts separately.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct CrossTab {
+    pub row_question: String,
+    pub column_question: String,
+    pub rows: Vec<String>,
+    pub columns: Vec<String>,
+    pub counts: Vec<Vec<usize>>
+}
+
+/// Tally the submissions accepted by store, for the survey with published participant list Lvid.
+/// Fails with InvalidDescriptor if the store has no descriptor to read the questions from or its
+/// minimum number of responses exceeds the number of participants, SurveyStillOpen until the survey
+/// has ended, and TooFewResponses until that many have responded.
+#[allow(non_snake_case)]
+pub fn tally(store: &SubmissionStore, Lvid: &ParticipantList) -> Result<TallyReport> {
+    let descriptor:&SurveyDescriptor = ended(store)?;
+    let (responses, invalid):(Vec<Response>, usize) = responses(store, descriptor);
+    let k:usize = threshold(descriptor, Lvid, responses.len())?;
+
+    let questions:Vec<QuestionTally> = descriptor.questions.iter().enumerate().map(|(i, question)| {
+        let answers:Vec<&Answer> = responses.iter().filter_map(|response| response.answers[i].as_ref()).collect();
+        let result = match &question.kind {
+            _ if answers.len() < k => QuestionResult::Withheld,
+            QuestionKind::SingleChoice { options } | QuestionKind::MultipleChoice { options } => {
+                let mut counts:Vec<usize> = vec![0; options.len()];
+                for picked in answers.iter().flat_map(|answer| choices(answer)) {
+                    counts[picked as usize] += 1;
+                }
+                QuestionResult::Choice {
+                    options: options.iter().zip(counts).map(|(option, count)| OptionCount { option: option.clone(), count }).collect()
+                }
+            },
+            QuestionKind::Likert { points } => {
+                let values:Vec<i64> = answers.iter().filter_map(|answer| match answer { Answer::Scale(point) => Some(*point as i64), _ => None }).collect();
+                let mut histogram:Vec<usize> = vec![0; *points as usize];
+                for value in &values {
+                    histogram[*value as usize - 1] += 1;
+                }
+                QuestionResult::Scale { histogram, mean: mean(&values), median: median(&values) }
+            },
+            QuestionKind::NumericRange { .. } => {
+                let values:Vec<i64> = answers.iter().filter_map(|answer| match answer { Answer::Number(n) => Some(*n), _ => None }).collect();
+                let mut counts:BTreeMap<i64, usize> = BTreeMap::new();
+                for value in &values {
+                    *counts.entry(*value).or_insert(0) += 1;
+                }
+                QuestionResult::Numeric {
+                    histogram: counts.into_iter().map(|(value, count)| ValueCount { value, count }).collect(),
+                    mean: mean(&values),
+                    median: median(&values)
+                }
+            },
+            QuestionKind::FreeText { .. } => QuestionResult::Text {
+                answers: answers.iter().filter_map(|answer| match answer { Answer::Text(text) => Some(text.clone()), _ => None }).collect()
+            }
+        };
+        let withheld:bool = result == QuestionResult::Withheld;
+        let (answered, skipped):(usize, usize) = match withheld {
+            true    => (0, 0),
+            false   => (answers.len(), responses.len() - answers.len())
+        };
+        QuestionTally { text: question.text.clone(), answered, skipped, result, withheld }
+    }).collect();
+
+    Ok(TallyReport { title: descriptor.title.clone(), responses: responses.len(), invalid, questions })
+}
+
+/// Cross-tabulate questions rows and columns (by index) over the submissions accepted by store,
+/// for the survey with published participant list Lvid. Responses that skipped either question are
+/// left out. Fails like tally, with InvalidDescriptor if either question is not a choice or scale
+/// question, and with TooFewResponses if fewer responses than the threshold answered both.
+#[allow(non_snake_case)]
+pub fn cross_tab(store: &SubmissionStore, Lvid: &ParticipantList, rows: usize, columns: usize) -> Result<CrossTab> {
+    let descriptor:&SurveyDescriptor = ended(store)?;
+    let row_labels:Vec<String> = categories(descriptor, rows).ok_or(Error::InvalidDescriptor)?;
+    let column_labels:Vec<String> = categories(descriptor, columns).ok_or(Error::InvalidDescriptor)?;
+
+    let responses:Vec<Response> = responses(store, descriptor).0;
+    let k:usize = threshold(descriptor, Lvid, responses.len())?;
+    let both:Vec<(&Answer, &Answer)> = responses.iter()
+        .filter_map(|response| response.answers[rows].as_ref().zip(response.answers[columns].as_ref()))
+        .collect();
+    if both.len() < k {
+        return Err(Error::TooFewResponses { responses: both.len(), required: k });
+    }
+
+    let mut counts:Vec<Vec<usize>> = vec![vec![0; column_labels.len()]; row_labels.len()];
+    for (row, column) in both {
+        for i in choices(row) {
+            for j in choices(column) {
+                counts[i as usize][j as usize] += 1;
+            }
+        }
+    }
+    Ok(CrossTab {
+        row_question: descriptor.questions[rows].text.clone(),
+        column_question: descriptor.questions[columns].text.clone(),
+        rows: row_labels,
+        columns: column_labels,
+        counts
+    })
+}
+
+// Descriptor of the survey whose submissions store holds, once the survey has ended
+pub(crate) fn ended(store: &SubmissionStore) -> Result<&SurveyDescriptor> {
+    let descriptor:&SurveyDescriptor = store.descriptor.as_ref().ok_or(Error::InvalidDescriptor)?;
+    match store.has_ended() {
+        true    => Ok(descriptor),
+        false   => Err(Error::SurveyStillOpen)
+    }
+}
+
+// Stored responses that answer the descriptor's questions, and the number of those that don't
+pub(crate) fn responses(store: &SubmissionStore, descriptor: &SurveyDescriptor) -> (Vec<Response>, usize) {
+    let parsed:Vec<Option<Response>> = store.stored().iter()
+        .map(|submission| submission.response.parse::<Response>().ok().filter(|response| response.check(&descriptor.questions).is_ok()))
+        .collect();
+    let invalid:usize = parsed.iter().filter(|response| response.is_none()).count();
+    (parsed.into_iter().flatten().collect(), invalid)
+}
+
+// Minimum number of responses k, once the survey has that many
+#[allow(non_snake_case)]
+pub(crate) fn threshold(descriptor: &SurveyDescriptor, Lvid: &ParticipantList, responses: usize) -> Result<usize> {
+    let k:usize = descriptor.min_responses as usize;
+    if k > Lvid.len() {
+        return Err(Error::InvalidDescriptor);
+    }
+    match responses >= k {
+        true    => Ok(k),
+        false   => Err(Error::TooFewResponses { responses, required: k })
+    }
+}
+
+// Labels of the answers to a choice or scale question
+fn categories(descriptor: &SurveyDescriptor, question: usize) -> Option<Vec<String>> {
+    match &descriptor.questions.get(question)?.kind {
+        QuestionKind::SingleChoice { options } | QuestionKind::MultipleChoice { options } => Some(options.clone()),
+        QuestionKind::Likert { points } => Some((1..=*points).map(|point| point.to_string()).collect()),
+        _ => None
+    }
+}
+
+// Category indices picked by a choice or scale answer
+fn choices(answer: &Answer) -> Vec<u32> {
+    match answer {
+        Answer::Choice(i)       => vec![*i],
+        Answer::Choices(is)     => is.clone(),
+        Answer::Scale(point)    => vec![*point as u32 - 1],
+        _                       => Vec::new()
+    }
+}
+
+fn mean(values: &[i64]) -> Option<f64> {
+    match values.is_empty() {
+        true    => None,
+        false   => Some(values.iter().map(|value| *value as f64).sum::<f64>() / values.len() as f64)
+    }
+}
+
+// Middle value, or the mean of the two middle values
+fn median(values: &[i64]) -> Option<f64> {
+    let mut sorted:Vec<i64> = values.to_vec();
+    sorted.sort_unstable();
+    let n:usize = sorted.len();
+    match n {
+        0                   => None,
+        _ if n % 2 == 1     => Some(sorted[n / 2] as f64),
+        _                   => Some((sorted[n / 2 - 1] as f64 + sorted[n / 2] as f64) / 2.0)
+    }
+}
+
+
+/*
+ * Unit tests
+ */
+
+#[cfg(test)]
+use crate::users::{User, SurveyAuthority, RegistrationAuthority};
+#[cfg(test)]
+use crate::params::PublicParams;
+#[cfg(test)]
+use crate::verifier::CheckOutcome;
+#[cfg(test)]
+use crate::clock::FixedClock;
+#[cfg(test)]
+use tbn::Fr;
+
+#[test]
+// Test that every kind of question is summarised, once per participant, and cross-tabulated
+fn test_tally() {
+    let params = PublicParams::generate();
+    let mut ra = RegistrationAuthority::new(&params);
+    let mut sa:User = SurveyAuthority::new(&params);
+    let mut users:Vec<User> = (0..3).map(|_| User::new()).collect();
+    for user in users.iter_mut() {
+        assert!( user.reg_user(&mut ra).is_ok() );
+    }
+    let descriptor = SurveyDescriptor::new("Lunch", crate::schema::sample_questions(), 0, 3600, &sa.vk);
+    let (vid, signatures) = sa.gen_survey_from_descriptor(&descriptor, &ra.registry.ids(), &params, &ra.vk).expect("SA survey creation failed!");
+    let mut store = SubmissionStore::for_descriptor(descriptor).with_clock(FixedClock(0));
+
+    let responses:Vec<Response> = vec![
+        Response::new(vec![Some(Answer::Choice(0)), Some(Answer::Choices(vec![0, 1])), Some(Answer::Scale(2)), Some(Answer::Number(2)), None]),
+        Response::new(vec![Some(Answer::Choice(1)), Some(Answer::Choices(vec![1])), Some(Answer::Scale(5)), Some(Answer::Number(3)), Some(Answer::Text(String::from("Olives")))]),
+        Response::new(vec![Some(Answer::Choice(0)), Some(Answer::Choices(vec![2])), Some(Answer::Scale(4)), Some(Answer::Number(2)), None]),
+        // The first user changes their mind: only this response of theirs counts
+        Response::new(vec![Some(Answer::Choice(1)), Some(Answer::Choices(vec![0])), Some(Answer::Scale(1)), Some(Answer::Number(8)), None])
+    ];
+    for (user, response) in users.iter().cycle().zip(&responses) {
+        let submission = user.submit_survey(vid, &response.to_string(), &signatures, &sa.vk, &ra.vk, &params).expect("Submission failed");
+        assert!( store.check(submission, &sa.vk, &ra.vk, &params) != CheckOutcome::Rejected(Error::InvalidResponse) );
+    }
+
+    // Nothing is released while the survey is open, and once its closing time has passed it counts as ended
+    assert_eq!( tally(&store, &signatures), Err(Error::SurveyStillOpen) );
+    assert_eq!( cross_tab(&store, &signatures, 0, 1), Err(Error::SurveyStillOpen) );
+    let expired = SubmissionStore::for_descriptor(store.descriptor.clone().unwrap()).with_clock(FixedClock(3600));
+    assert_eq!( tally(&expired, &signatures).map(|report| report.responses), Ok(0) );
+    assert_eq!( store.close(), Ok(()) );
+
+    let report:TallyReport = tally(&store, &signatures).expect("Tally failed");
+    assert_eq!( (report.title.as_str(), report.responses, report.invalid), ("Lunch", 3, 0) );
+    let counts = |result: &QuestionResult| match result {
+        QuestionResult::Choice { options } => options.iter().map(|option| option.count).collect::<Vec<usize>>(),
+        _ => panic!("Not a choice question")
+    };
+    assert_eq!( counts(&report.questions[0].result), vec![1, 2] );
+    assert_eq!( counts(&report.questions[1].result), vec![1, 1, 1] );
+    assert_eq!( report.questions[2].result, QuestionResult::Scale { histogram: vec![1, 0, 0, 1, 1], mean: Some(10.0 / 3.0), median: Some(4.0) } );
+    assert_eq!( report.questions[3].result, QuestionResult::Numeric {
+        histogram: vec![ValueCount { value: 2, count: 1 }, ValueCount { value: 3, count: 1 }, ValueCount { value: 8, count: 1 }],
+        mean: Some(13.0 / 3.0),
+        median: Some(3.0)
+    } );
+    assert_eq!( report.questions[4].result, QuestionResult::Text { answers: vec![String::from("Olives")] } );
+    assert_eq!( (report.questions[4].answered, report.questions[4].skipped, report.questions[4].withheld), (1, 2, false) );
+    #[cfg(feature = "serde")]
+    {
+        let value:serde_json::Value = serde_json::to_value(&report).unwrap();
+        assert_eq!( value["questions"][0]["result"]["options"][1], serde_json::json!({ "option": "Pasta", "count": 2 }) );
+        assert_eq!( value["questions"][4]["result"]["type"], "text" );
+        assert_eq!( serde_json::from_value::<TallyReport>(value).unwrap(), report );
+    }
+    assert_eq!( median(&[1, 2, 3, 10]), Some(2.5) );
+    assert_eq!( mean(&[]), None );
+
+    let table:CrossTab = cross_tab(&store, &signatures, 0, 1).expect("Cross-tab failed");
+    assert_eq!( table.rows, vec!["Pizza", "Pasta"] );
+    assert_eq!( table.counts, vec![vec![0, 0, 1], vec![1, 1, 0]] );
+    assert_eq!( cross_tab(&store, &signatures, 0, 2).map(|table| table.columns.len()), Ok(5) );
+    assert_eq!( cross_tab(&store, &signatures, 0, 3), Err(Error::InvalidDescriptor) );
+    assert_eq!( cross_tab(&store, &signatures, 0, 9), Err(Error::InvalidDescriptor) );
+    assert_eq!( tally(&SubmissionStore::new(vid), &signatures), Err(Error::InvalidDescriptor) );
+
+    // With a threshold of 2, the free text answered once is withheld, and so is the cross-tab with it
+    store.descriptor = store.descriptor.map(|descriptor| descriptor.with_min_responses(2));
+    let report:TallyReport = tally(&store, &signatures).expect("Tally failed");
+    assert!( report.questions[..4].iter().all(|question| question.result != QuestionResult::Withheld) );
+    assert_eq!( report.questions[4].result, QuestionResult::Withheld );
+    assert_eq!( (report.questions[4].answered, report.questions[4].skipped, report.questions[4].withheld), (0, 0, true) );
+    #[cfg(feature = "serde")]
+    assert_eq!( serde_json::to_value(&report).unwrap()["questions"][4]["answered"], 0 );
+    assert!( cross_tab(&store, &signatures, 0, 1).is_ok() );
+
+    // Thresholds above the number of responses, or of participants
+    store.descriptor = store.descriptor.map(|descriptor| descriptor.with_min_responses(4));
+    assert_eq!( tally(&store, &signatures), Err(Error::InvalidDescriptor) );
+    let mut more:ParticipantList = signatures.clone();
+    more.insert((Fr::one(), signatures[0].1, signatures[0].2));
+    assert_eq!( tally(&store, &more), Err(Error::TooFewResponses { responses: 3, required: 4 }) );
+    assert_eq!( cross_tab(&store, &more, 0, 1), Err(Error::TooFewResponses { responses: 3, required: 4 }) );
+}
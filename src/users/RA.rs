@@ -1,8 +1,19 @@
 extern crate tbn;
 extern crate rand;
 
-use tbn::{Group, Fr, G1, G2, Gt, pairing};
+use tbn::{Group, Fr, G1, G2, Gt};
+use rand::{RngCore, CryptoRng};
 use super::{VerificationKey};
+use crate::params::PublicParams;
+use crate::hash;
+use crate::survey::ParticipantList;
+use crate::registry::{UserRegistry, MemoryRegistry};
+use crate::clock::{Clock, SystemClock};
+use crate::error::{Error, Result};
+#[cfg(feature = "keystore")]
+use crate::keystore::{KeyFile, KdfParams};
+#[cfg(feature = "keystore")]
+use std::path::Path;
 
 /*
  * ----------------------------------------------
@@ -15,49 +26,267 @@ use super::{VerificationKey};
  *          + Issue master user token to allow users to participate in surveys
  *          + Generate signature key-pair that allows them to sign and others to verify values
  *          + Specify a list of user IDs (authenticated by the RA) to send the survey to
+ *      - Move a registered user to a new ID, once they show the credential of their old ID
+ *
+ * Registered ids are kept in a UserRegistry, in memory unless the RA is given a durable one.
+ *
+ * A rotation is RegUser for the new id, run with the same seed commitment u^s: the old credential
+ * must verify against it, and the proof of knowledge of s that follows shows the request comes
+ * from the credential's owner. Survey lists are signed on ids, so the RA records every rotation
+ * in its registry, for SAs to find the entries of their lists that name an id which has since been
+ * rotated away, even after a restart.
+ *
+ * A registration or rotation is pending between the challenge and the user's response. Pending
+ * entries expire after PENDING_SECS by the RA's clock and at most MAX_PENDING are kept, so
+ * unanswered requests can't pile up. A response names the challenge it answers, and a failed proof
+ * only cancels the entry holding that challenge: knowing someone's id is not enough to cancel it.
 */
 
+// Seconds a challenge may go unanswered before its registration is dropped
+const PENDING_SECS: u64 = 300;
+// Registrations that can be in progress at once
+const MAX_PENDING: usize = 1024;
+
+/// Master credential issued to a user by the RA during RegUser. It is a Boneh-Boyen style
+/// signature on the pair (id, s), where s is the user's secret seed:
+///      sigma_1 = g^x * (v^id * u^s * h)^r,     sigma_2 = g2^r
+/// The RA only ever sees the seed as the commitment u^s, so it cannot later link the survey
+/// tokens derived from s back to the user.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct MasterCredential {
+    pub sigma_1: G1,
+    pub sigma_2: G2
+}
+
+/// First message sent by a user in RegUser: their id, a commitment u^s to their secret seed and
+/// the announcement u^k of a Schnorr proof of knowledge of s
+#[derive(Copy, Clone, Debug)]
+pub struct RegistrationRequest {
+    pub id: Fr,
+    pub commitment: G1,
+    pub announcement: G1
+}
+
 pub struct RegistrationAuthority {
     pub vk: VerificationKey,
     sk: Fr,
-    // A list of users for the anonymous survey system. Essentially an anonymity set
-    pub userid_list: Vec<Fr>
+    /// Public parameters the RA's keys (and any credentials it issues) are defined over
+    pub params: PublicParams,
+    /// The users of the anonymous survey system. Essentially an anonymity set
+    pub registry: Box<dyn UserRegistry>,
+    // Time challenges are given and expire by
+    clock: Box<dyn Clock>,
+    // Registrations that have been challenged but not yet answered
+    pending: Vec<Pending>
+}
+
+// Registration waiting for the user's response to the challenge
+struct Pending {
+    request: RegistrationRequest,
+    challenge: Fr,
+    // Id the registration replaces, for a rotation
+    replaces: Option<Fr>,
+    expires_at: u64
 }
 
 impl RegistrationAuthority {
     
     /* Create Registration Authority */
-    pub fn new(g:G1, g2:G2) -> Self {
+    pub fn new(params:&PublicParams) -> Self {
+        Self::new_with_rng(params, &mut rand::thread_rng())
+    }
+
+    /// Create a Registration Authority, drawing its keys from the given rng
+    pub fn new_with_rng<R: RngCore + CryptoRng>(params:&PublicParams, rng:&mut R) -> Self {
 
         // TODO: Call user initialization
-        
+
+        // Generate random u,v,h in G_1
+        let bases:(G1, G1, G1) = (G1::random(rng), G1::random(rng), G1::random(rng));
+
+        Self::with_bases(params, bases, rng)
+    }
+
+    /// Create a Registration Authority whose public bases u, v, h are hashed from a public seed,
+    /// so anyone can check that they were not chosen with a known relation
+    pub fn from_seed(params:&PublicParams, seed:&str) -> Self {
+        Self::from_seed_with_rng(params, seed, &mut rand::thread_rng())
+    }
+
+    /// from_seed, drawing the secret signing key from the given rng
+    pub fn from_seed_with_rng<R: RngCore + CryptoRng>(params:&PublicParams, seed:&str, rng:&mut R) -> Self {
+        Self::with_bases(params, hash::derive_bases(seed), rng)
+    }
+
+    fn with_bases<R: RngCore + CryptoRng>(params:&PublicParams, bases:(G1, G1, G1), rng:&mut R) -> Self {
+
         // Generate parameters for RA
-        let (vk, x) =  Self::gen_RA(g, g2);
+        let (vk, x) =  Self::gen_RA(params, bases, rng);
 
-        let userid_list:Vec<Fr> = Vec::new();
+        let registry:Box<dyn UserRegistry> = Box::new(MemoryRegistry::new());
         // Return user with verification and signing key for registering users
-        RegistrationAuthority {vk, sk: x, userid_list}
+        RegistrationAuthority {vk, sk: x, params: params.clone(), registry, clock: Box::new(SystemClock), pending: Vec::new()}
     }
 
-    /* Generate public and private keys for registration authority */
-    #[allow(non_snake_case)]
-    fn gen_RA(g:G1, g2:G2) -> (VerificationKey, Fr) {
+    /// Keep registered ids in the given registry (e.g. a FileRegistry, to restore the ids
+    /// registered before a restart) instead of in memory
+    pub fn with_registry<U: UserRegistry + 'static>(mut self, registry:U) -> Self {
+        self.registry = Box::new(registry);
+        self
+    }
 
-        // crytpographiclaly secure thread-local rng
-        let rng = &mut rand::thread_rng();
+    /// Read the time pending registrations expire by from clock instead of the system clock
+    pub fn with_clock<C: Clock + 'static>(mut self, clock:C) -> Self {
+        self.clock = Box::new(clock);
+        self
+    }
 
-        // Generate random u,v,h in G_1
-        let u:G1 = G1::random(rng);
-        let v:G1 = G1::random(rng);
-        let h:G1 = G1::random(rng);
+    /// Save the RA's key pair to a key file at path, with the secret key encrypted under passphrase
+    #[cfg(feature = "keystore")]
+    pub fn save_keys<P: AsRef<Path>>(&self, path:P, passphrase:&str) -> Result<()> {
+        self.save_keys_with_rng(path, passphrase, &mut rand::thread_rng())
+    }
+
+    /// save_keys, drawing the salt and nonce from the given rng
+    #[cfg(feature = "keystore")]
+    pub fn save_keys_with_rng<P: AsRef<Path>, R: RngCore + CryptoRng>(&self, path:P, passphrase:&str, rng:&mut R) -> Result<()> {
+        KeyFile::seal(&self.vk, self.sk, passphrase, KdfParams::default(), rng)?.save(path)
+    }
+
+    /// Restart a Registration Authority from the key file at path, so that credentials it issued
+    /// before stay valid. Its registry starts out empty and in memory, see with_registry. Fails as
+    /// KeyFile::load and KeyFile::open do.
+    #[cfg(feature = "keystore")]
+    pub fn load_keys<P: AsRef<Path>>(params:&PublicParams, path:P, passphrase:&str) -> Result<Self> {
+        let file:KeyFile = KeyFile::load(path)?;
+        let sk:Fr = file.open(passphrase, params)?;
+        Ok(RegistrationAuthority { vk: file.vk, sk, params: params.clone(), registry: Box::new(MemoryRegistry::new()), clock: Box::new(SystemClock), pending: Vec::new() })
+    }
+
+    /* RegUser (RA side), step 1: challenge the user's proof of knowledge of their seed */
+    /// Fails with DuplicateId if the id is already registered or has a registration in progress,
+    /// and TooManyPending if MAX_PENDING registrations are
+    pub fn challenge(&mut self, request: RegistrationRequest) -> Result<Fr> {
+        self.challenge_with_rng(request, &mut rand::thread_rng())
+    }
+
+    /// challenge, drawing the challenge from the given rng
+    pub fn challenge_with_rng<R: RngCore + CryptoRng>(&mut self, request: RegistrationRequest, rng: &mut R) -> Result<Fr> {
+        self.challenge_for(request, None, rng)
+    }
+
+    /* Rotation, step 1: check the old registration and challenge the user's proof of knowledge */
+    /// Start moving the registration of old_id to request.id, where credential is the master
+    /// credential issued on old_id and request commits to the same seed. issue_credential then
+    /// completes the rotation, swapping the ids in the registry in a single update. Fails with
+    /// UnregisteredId if old_id is not registered, DuplicateId if it is already being rotated or
+    /// the new id is taken, InvalidSignature if the credential does not match old_id and the
+    /// commitment, and TooManyPending like challenge.
+    pub fn rotate_id(&mut self, old_id: Fr, credential: &MasterCredential, request: RegistrationRequest) -> Result<Fr> {
+        self.rotate_id_with_rng(old_id, credential, request, &mut rand::thread_rng())
+    }
+
+    /// rotate_id, drawing the challenge from the given rng
+    pub fn rotate_id_with_rng<R: RngCore + CryptoRng>(&mut self, old_id: Fr, credential: &MasterCredential, request: RegistrationRequest, rng: &mut R) -> Result<Fr> {
+
+        if !self.registry.contains(old_id) {
+            return Err(Error::UnregisteredId);
+        }
+        self.expire_pending();
+        if self.pending.iter().any(|pending| pending.replaces == Some(old_id)) {
+            return Err(Error::DuplicateId);
+        }
+
+        // e(sigma_1, g2) == e(g, g2)^x * e(v^id * u^s * h, sigma_2), with u^s from the request
+        let base:G1 = self.vk.v * old_id + request.commitment + self.vk.h;
+        if tbn::pairing(credential.sigma_1, self.params.g2) != self.vk.pk * tbn::pairing(base, credential.sigma_2) {
+            return Err(Error::InvalidSignature);
+        }
+
+        self.challenge_for(request, Some(old_id), rng)
+    }
+
+    /// Completed rotations (old id, new id), in order, as kept by the registry
+    pub fn rotations(&self) -> Vec<(Fr, Fr)> {
+        self.registry.rotations()
+    }
+
+    /// Completed rotations (old id, new id) of the ids on a survey's participant list, which the
+    /// SA may want to re-sign for the new ids
+    pub fn rotated_participants(&self, list: &ParticipantList) -> Vec<(Fr, Fr)> {
+        self.rotations().into_iter().filter(|(old_id, _)| list.contains(*old_id)).collect()
+    }
+
+    fn challenge_for<R: RngCore + CryptoRng>(&mut self, request: RegistrationRequest, replaces: Option<Fr>, rng: &mut R) -> Result<Fr> {
+
+        self.expire_pending();
+        if self.registry.contains(request.id) || self.pending.iter().any(|pending| pending.request.id == request.id) {
+            return Err(Error::DuplicateId);
+        }
+        if self.pending.len() >= MAX_PENDING {
+            return Err(Error::TooManyPending);
+        }
+
+        let challenge:Fr = Fr::random(rng);
+        self.pending.push(Pending { request, challenge, replaces, expires_at: self.clock.now().saturating_add(PENDING_SECS) });
+        Ok(challenge)
+    }
+
+    // Drop the registrations whose challenge went unanswered for too long
+    fn expire_pending(&mut self) {
+        let now:u64 = self.clock.now();
+        self.pending.retain(|pending| pending.expires_at > now);
+    }
+
+    /* RegUser (RA side), step 2: verify the user's response and issue a master credential */
+    /// Answer challenge c for id with response. Fails with UnregisteredId if no unexpired
+    /// registration of id was given that challenge, InvalidProof (and forgets the registration) if
+    /// the proof does not verify, DuplicateId if the registry already holds the id, or as the
+    /// registry does if the id cannot be stored
+    pub fn issue_credential(&mut self, id: Fr, c: Fr, response: Fr) -> Result<MasterCredential> {
+        self.issue_credential_with_rng(id, c, response, &mut rand::thread_rng())
+    }
+
+    /// issue_credential, drawing the signature randomness from the given rng
+    pub fn issue_credential_with_rng<R: RngCore + CryptoRng>(&mut self, id: Fr, c: Fr, response: Fr, rng: &mut R) -> Result<MasterCredential> {
+
+        self.expire_pending();
+        let index = self.pending.iter().position(|pending| pending.request.id == id && pending.challenge == c).ok_or(Error::UnregisteredId)?;
+        let Pending { request, replaces, .. } = self.pending.remove(index);
+
+        // Schnorr verification: u^z == u^k * (u^s)^c
+        if self.vk.u * response != request.announcement + request.commitment * c {
+            return Err(Error::InvalidProof);
+        }
+
+        // Sign (id, s) without learning s, using the commitment u^s in place of the seed
+        let r:Fr = Fr::random(rng);
+        let sigma_1:G1 = self.params.g * self.sk + (self.vk.v * id + request.commitment + self.vk.h) * r;
+        let sigma_2:G2 = self.params.g2 * r;
+
+        // User is now part of the anonymity set (in place of their old id)
+        let registered:bool = match replaces {
+            Some(old_id)    => self.registry.replace(old_id, id)?,
+            None            => self.registry.insert(id)?
+        };
+        if !registered {
+            return Err(Error::DuplicateId);
+        }
+
+        Ok(MasterCredential { sigma_1, sigma_2 })
+    }
+
+    /* Generate public and private keys for registration authority */
+    #[allow(non_snake_case)]
+    fn gen_RA<R: RngCore + CryptoRng>(params:&PublicParams, (u, v, h):(G1, G1, G1), rng:&mut R) -> (VerificationKey, Fr) {
 
         // Generate secret x as element of cyclic group with order r (q, in ANONIZE's notation)
         let x:Fr = Fr::random(rng);
 
         // Compute e(g, g2)^x
-        let pair:Gt = pairing(g, g2).pow(x);
+        let pair:Gt = params.gt.pow(x);
 
-        let vk = VerificationKey { u, v, h, pk: pair };
+        let vk = VerificationKey { u, v, h, pk: pair, params: params.fingerprint };
 
         // Return parameters for Registration Authority
         (vk, x)
@@ -73,10 +302,128 @@ impl RegistrationAuthority {
 #[allow(non_snake_case)]
 // Test to ensure that e(g, g2)^(sk_RA) = vk_RA
 fn test_RA_keys() {
+    let params = PublicParams::generate();
+    let ra = RegistrationAuthority::new(&params);
+    assert!( tbn::pairing(params.g, params.g2).pow(ra.sk) == ra.vk.pk );
+}
+
+#[test]
+// Test that the RA refuses to issue a credential when the proof of knowledge of the seed fails
+fn test_issue_credential_bad_proof() {
+    let rng = &mut rand::thread_rng();
+    let mut ra = RegistrationAuthority::new(&PublicParams::generate());
+
+    let (id, s, k):(Fr, Fr, Fr) = (Fr::random(rng), Fr::random(rng), Fr::random(rng));
+    let request = RegistrationRequest { id, commitment: ra.vk.u * s, announcement: ra.vk.u * k };
+    let c:Fr = ra.challenge(request).expect("RA refused fresh id");
+
+    // A response to another challenge doesn't cancel the registration
+    assert_eq!( ra.issue_credential(id, c + Fr::one(), k), Err(Error::UnregisteredId) );
+    // Response computed with the wrong seed
+    assert_eq!( ra.issue_credential(id, c, k + c * (s + Fr::one())), Err(Error::InvalidProof) );
+    assert!( !ra.registry.contains(id) );
+    // Failed attempt is not left pending
+    assert_eq!( ra.issue_credential(id, c, k + c * s), Err(Error::UnregisteredId) );
+}
+
+#[test]
+// Test that an id can only be registered once
+fn test_challenge_duplicate_id() {
+    let rng = &mut rand::thread_rng();
+    let mut ra = RegistrationAuthority::new(&PublicParams::generate());
+
+    let (id, s, k):(Fr, Fr, Fr) = (Fr::random(rng), Fr::random(rng), Fr::random(rng));
+    let request = RegistrationRequest { id, commitment: ra.vk.u * s, announcement: ra.vk.u * k };
+    let c:Fr = ra.challenge(request).expect("RA refused fresh id");
+    assert_eq!( ra.challenge(request), Err(Error::DuplicateId) );
+    assert!( ra.issue_credential(id, c, k + c * s).is_ok() );
+    assert_eq!( ra.challenge(request), Err(Error::DuplicateId) );
+}
+
+#[test]
+// Test that unanswered challenges expire, and that only so many can be pending at once
+fn test_pending_registrations() {
+    use std::{rc::Rc, cell::Cell};
     let rng = &mut rand::thread_rng();
-    let (g, g2):(G1, G2) = (G1::random(rng), G2::random(rng));
-    let ra = RegistrationAuthority::new(g, g2);
-    assert!( pairing(g, g2).pow(ra.sk) == ra.vk.pk ); 
+    let time = Rc::new(Cell::new(1000));
+    let clock = { let time = time.clone(); move || time.get() };
+    let mut ra = RegistrationAuthority::new(&PublicParams::generate()).with_clock(clock);
+
+    let (id, s, k):(Fr, Fr, Fr) = (Fr::random(rng), Fr::random(rng), Fr::random(rng));
+    let request = RegistrationRequest { id, commitment: ra.vk.u * s, announcement: ra.vk.u * k };
+    let c:Fr = ra.challenge(request).expect("RA refused fresh id");
+    time.set(1000 + PENDING_SECS);
+    assert_eq!( ra.issue_credential(id, c, k + c * s), Err(Error::UnregisteredId) );
+    let c:Fr = ra.challenge(request).expect("RA refused expired id");
+    assert!( ra.issue_credential(id, c, k + c * s).is_ok() );
+
+    for _ in 0..MAX_PENDING {
+        assert!( ra.challenge(RegistrationRequest { id: Fr::random(rng), ..request }).is_ok() );
+    }
+    assert_eq!( ra.challenge(RegistrationRequest { id: Fr::random(rng), ..request }), Err(Error::TooManyPending) );
+    time.set(1000 + 2 * PENDING_SECS);
+    assert!( ra.challenge(RegistrationRequest { id: Fr::random(rng), ..request }).is_ok() );
+}
+
+#[test]
+// Test that an RA given a durable registry still knows its users after a restart
+fn test_durable_registry() {
+    use crate::registry::FileRegistry;
+    let rng = &mut rand::thread_rng();
+    let params = PublicParams::generate();
+    let dir = tempfile::tempdir().expect("Creating temporary directory failed");
+    let mut ra = RegistrationAuthority::new(&params).with_registry(FileRegistry::open(dir.path()).expect("Opening registry failed"));
+
+    let (id, s, k):(Fr, Fr, Fr) = (Fr::random(rng), Fr::random(rng), Fr::random(rng));
+    let request = RegistrationRequest { id, commitment: ra.vk.u * s, announcement: ra.vk.u * k };
+    let c:Fr = ra.challenge(request).expect("RA refused fresh id");
+    assert!( ra.issue_credential(id, c, k + c * s).is_ok() );
+    drop(ra);
+
+    let mut restarted = RegistrationAuthority::new(&params).with_registry(FileRegistry::open(dir.path()).expect("Reopening registry failed"));
+    assert!( restarted.registry.contains(id) && restarted.registry.len() == 1 );
+    assert_eq!( restarted.challenge(request), Err(Error::DuplicateId) );
+}
+
+#[test]
+// Test that only the holder of a registration can rotate it, and that the rotation is recorded
+fn test_rotate_id() {
+    use super::{User, SurveyAuthority};
+    let rng = &mut rand::thread_rng();
+    let params = PublicParams::generate();
+    let mut ra = RegistrationAuthority::new(&params);
+    let mut sa:User = SurveyAuthority::new(&params);
+    let (mut user, mut other) = (User::new(), User::new());
+    assert!( user.reg_user(&mut ra).is_ok() && other.reg_user(&mut ra).is_ok() );
+    let (_, signatures) = sa.gen_survey(&ra.registry.ids(), &params, &ra.vk).expect("SA survey creation failed!");
+
+    let old_id:Fr = user.id;
+    assert_eq!( user.re_identify(&mut ra), Ok(old_id) );
+    assert!( ra.registry.contains(user.id) && !ra.registry.contains(old_id) && ra.registry.len() == 2 );
+    assert_eq!( ra.rotations(), vec![(old_id, user.id)] );
+    assert_eq!( ra.rotated_participants(&signatures), vec![(old_id, user.id)] );
+
+    // The old id is gone, and another user's credential doesn't open a rotation
+    let cred:MasterCredential = user.credential.expect("User is registered");
+    let (k, new_id):(Fr, Fr) = (Fr::random(rng), Fr::random(rng));
+    let request = RegistrationRequest { id: new_id, commitment: ra.vk.u * user.seed, announcement: ra.vk.u * k };
+    assert_eq!( ra.rotate_id(old_id, &cred, request), Err(Error::UnregisteredId) );
+    assert_eq!( ra.rotate_id(other.id, &cred, request), Err(Error::InvalidSignature) );
+    let stolen = RegistrationRequest { commitment: ra.vk.u * other.seed, ..request };
+    assert_eq!( ra.rotate_id(user.id, &cred, stolen), Err(Error::InvalidSignature) );
+    assert_eq!( ra.rotate_id(user.id, &cred, RegistrationRequest { id: other.id, ..request }), Err(Error::DuplicateId) );
+
+    // Without knowledge of the seed, the rotation fails and leaves the registry as it was
+    let c:Fr = ra.rotate_id(user.id, &cred, request).expect("RA refused rotation");
+    assert_eq!( ra.rotate_id(user.id, &cred, RegistrationRequest { id: Fr::random(rng), ..request }), Err(Error::DuplicateId) );
+    assert_eq!( ra.issue_credential(new_id, c, k + c * (user.seed + Fr::one())), Err(Error::InvalidProof) );
+    assert!( ra.registry.contains(user.id) && !ra.registry.contains(new_id) && ra.rotations().len() == 1 );
+
+    // A user the RA never registered keeps their id
+    let mut stranger = User::new();
+    let stranger_id:Fr = stranger.id;
+    assert_eq!( stranger.re_identify(&mut ra), Err(Error::UnregisteredId) );
+    assert!( stranger.id == stranger_id );
 }
 
 
@@ -84,80 +431,32 @@ fn test_RA_keys() {
  * Benchmark tests
  */
 
+#[cfg(test)]
+#[allow(non_snake_case)]
+// GenRA num_trials times
+fn bench_gen_RA(num_trials: usize) {
+
+    // Setup
+    let params = PublicParams::generate();
+
+    println!("GenRA Benchmark Test ({} trials)", num_trials);
+    crate::bench_trials("Trial", num_trials, |_| {
+        let _ra = RegistrationAuthority::new(&params);
+    });
+}
+
 #[test]
 #[ignore]
 #[allow(non_snake_case)]
 // Test 100 iterations of GenRA to get mean and standard deviation
 fn bench_100_gen_RA() {
-
-    use std::time::{Duration, Instant};
-
-    // Setup 
-    let rng = &mut rand::thread_rng();
-    let (g, g2):(G1, G2) = (G1::random(rng), G2::random(rng));
-    
-    // 100 irerations of GenRA
-    const NUM_TRIALS:usize = 100;
-    assert!(NUM_TRIALS > 1);
-    println!("GenRA Benchmark Test ({} trials)", NUM_TRIALS);
-    let mut sum:Duration = Duration::new(0,0);
-    let mut durs:[Duration;NUM_TRIALS] = [Duration::new(0,0);NUM_TRIALS];
-    for i in 0..NUM_TRIALS {
-        let start = Instant::now(); 
-        let _ra = RegistrationAuthority::new(g, g2);
-        durs[i] = start.elapsed();
-        sum += durs[i];
-        println!("Trial {}:\t{:?}", i+1, durs[i]);
-    }
-    println!();
-    // Calculate mean
-    let mean = sum / (NUM_TRIALS as u32);
-    // Calculate standard deviation
-    let mut sum_of_diff:f32 = 0.0;
-    for i in 0..NUM_TRIALS {
-        sum_of_diff += f32::powf((((durs[i].as_millis() as i128) - (mean.as_millis() as i128)) as f32)/1000.0, 2.0);
-    }
-    let sd = ( sum_of_diff / ((NUM_TRIALS as f32)- 1.0)).sqrt();
- 
-    println!("Mean:\t\t{:?}", mean);
-    println!("Std Dev:\t{:?}s", sd);
+    bench_gen_RA(100);
 }
 
-
 #[test]
+#[ignore]
 #[allow(non_snake_case)]
 // Test 5 iterations of GenRA to get mean and standard deviation
 fn bench_5_gen_RA() {
-
-    use std::time::{Duration, Instant};
-
-    // Setup 
-    let rng = &mut rand::thread_rng();
-    let (g, g2):(G1, G2) = (G1::random(rng), G2::random(rng));
-    
-    // 5 irerations of GenRA
-    const NUM_TRIALS:usize = 5;
-    assert!(NUM_TRIALS > 1);
-    println!("GenRA Benchmark Test ({} trials)", NUM_TRIALS);
-    let mut sum:Duration = Duration::new(0,0);
-    let mut durs:[Duration;NUM_TRIALS] = [Duration::new(0,0);NUM_TRIALS];
-    for i in 0..NUM_TRIALS {
-        let start = Instant::now(); 
-        let _ra = RegistrationAuthority::new(g, g2);
-        durs[i] = start.elapsed();
-        sum += durs[i];
-        println!("Trial {}:\t{:?}", i+1, durs[i]);
-    }
-    println!();
-    // Calculate mean
-    let mean = sum / (NUM_TRIALS as u32);
-    // Calculate standard deviation
-    let mut sum_of_diff:f32 = 0.0;
-    for i in 0..NUM_TRIALS {
-        sum_of_diff += f32::powf((((durs[i].as_millis() as i128) - (mean.as_millis() as i128)) as f32)/1000.0, 2.0);
-    }
-    let sd = ( sum_of_diff / ((NUM_TRIALS as f32)- 1.0)).sqrt();
- 
-    println!("Mean:\t\t{:?}", mean);
-    println!("Std Dev:\t{:?}s", sd);
+    bench_gen_RA(5);
 }
@@ -1,18 +1,80 @@
 extern crate tbn;
 extern crate rand;
+extern crate sha2;
 
 
 #[allow(non_snake_case)]
 pub mod RA;
-pub use self::RA::RegistrationAuthority;
+pub use self::RA::{RegistrationAuthority, RegistrationRequest, MasterCredential};
 use tbn::{Group, Fr, G1, G2, Gt, pairing};
-
-// Signaure verification key used by Survey & Registration Authorities
+use rand::{RngCore, CryptoRng};
+use sha2::{Sha256, Sha512, Digest};
+use crate::zkp::{self, SurveyProof};
+use crate::params::{PublicParams, Fingerprint};
+use std::collections::HashSet;
+use crate::hash;
+use crate::fixed_base::FixedBase;
+use crate::survey::{self, FrKey, ParticipantList, SurveyList, SurveyDescriptor, SignedDescriptor};
+use crate::encoding::{Encode, Canonical};
+use crate::clock::Clock;
+use crate::verifier::SubmissionStore;
+use crate::error::{Error, Result};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+#[cfg(feature = "keystore")]
+use crate::keystore::{KeyFile, KdfParams};
+#[cfg(feature = "keystore")]
+use std::path::Path;
+
+/// Signaure verification key used by Survey & Registration Authorities
+#[derive(Copy, Clone, PartialEq)]
 pub struct VerificationKey {
+    /// Random elements of G1 that signed values are encoded against
     pub u: G1,
     pub v: G1,
     pub h: G1,
-    pub pk: Gt
+    /// e(g, g2)^sk for the authority's secret signing key sk
+    pub pk: Gt,
+    /// Fingerprint of the public parameters the key was generated under
+    pub params: Fingerprint
+}
+
+impl VerificationKey {
+
+    /// SHA-256 of the key's canonical encoding, identifying the authority that owns it
+    pub fn fingerprint(&self) -> Fingerprint {
+        let mut bytes:Vec<u8> = b"anon_survey/verification_key".to_vec();
+        self.encode_to(&mut bytes);
+
+        let mut digest:Fingerprint = [0; 32];
+        digest.copy_from_slice(&Sha256::digest(&bytes));
+        digest
+    }
+
+    /// Survey id that the authority owning this key gives to a survey it generates under label.
+    /// Ids commit to the key's fingerprint, so no SA can take another's id and link the survey
+    /// tokens issued for both surveys.
+    pub fn survey_id(&self, label: Fr) -> Fr {
+        let mut transcript:Vec<u8> = b"anon_survey/survey_label".to_vec();
+        transcript.extend_from_slice(&self.fingerprint());
+        label.encode_to(&mut transcript);
+
+        let mut digest:[u8; 64] = [0; 64];
+        digest.copy_from_slice(&Sha512::digest(&transcript));
+        Fr::interpret(&digest)
+    }
+}
+
+/// Anonymous survey response: the user's token for survey vid, their response and a proof that the
+/// token belongs to a registered user the SA authorized, without revealing which one
+#[derive(Clone, Debug, PartialEq)]
+pub struct Submission {
+    pub vid: Fr,
+    /// Epoch of the survey list the SA's signature was taken from
+    pub epoch: u64,
+    pub token: G1,
+    pub response: String,
+    pub proof: SurveyProof
 }
 
 pub struct User {
@@ -20,75 +82,248 @@ pub struct User {
     // Unique user ID (kept hidden to others when submitting surveys -- user chooses when to
     // reveal
     id: Fr,
+    // Secret seed from which survey tokens are derived (never revealed, not even to the RA)
+    seed: Fr,
+    /// Master credential issued by the RA on (id, seed) once registered
+    pub credential: Option<MasterCredential>,
     pub vk: VerificationKey,
     // Secret signing key used by Survey & Registration Authorities
     sk: Fr,
-    // List of owned surveys (by vid)
+    /// List of owned surveys (by vid)
     pub owned_surveys: Vec<Fr>,
-    // (survey id, {RA's published user ids -> their signature})
-    pub verid_list: Vec<(Fr, Vec<(Fr, G1, G2)>)>
+    /// survey id -> {RA's published user ids -> their signature}
+    pub verid_list: SurveyList,
+    /// Owned surveys (by vid) that were closed, whose lists can no longer be amended
+    pub closed_surveys: HashSet<FrKey>,
+    // Window tables for the bases (u, v) of the SA's key and h of the RA key it last signed against
+    tables: Option<(FixedBase<G1>, FixedBase<G1>, FixedBase<G1>)>
+}
+
+impl Default for User {
+    fn default() -> Self {
+        User::new()
+    }
 }
 
 impl User {
     
 
-    // Generate (hopefully) unique id and return new User
+    /// Generate (hopefully) unique id and return new User
     pub fn new() -> Self {
+        User::new_with_rng(&mut rand::thread_rng())
+    }
+
+    /// Create a User, drawing their id and secret seed from the given rng
+    pub fn new_with_rng<R: RngCore + CryptoRng>(rng: &mut R) -> Self {
 
-        // crytpographiclaly secure thread-local rng
-        let rng = &mut rand::thread_rng();
-        
         // Create empty struct if user ever decides to become SA
         let vk = VerificationKey {
             u: G1::zero(),
             v: G1::zero(),
             h: G1::zero(),
             pk: Gt::one(),
+            params: [0; 32]
         };
 
         User {
             id: Fr::random(rng),
+            seed: Fr::random(rng),
+            credential: None,
             vk,
             sk: Fr::zero(),
             owned_surveys: Vec::new(),
-            verid_list: Vec::new()
+            verid_list: SurveyList::new(),
+            closed_surveys: HashSet::new(),
+            tables: None
         }
     }
 
-    // Re-generate id and returns old ID
-    pub fn re_identify(&mut self, ra: &mut RegistrationAuthority) -> Fr {
+    /// Move the user's registration to a fresh id and return the old one. The user keeps their
+    /// seed, and proves to the RA that they hold the old id's credential. Fails with UnregisteredId
+    /// if the user or their id is not registered, and as RegistrationAuthority::rotate_id and
+    /// issue_credential do; on failure the user keeps their old id and credential.
+    pub fn re_identify(&mut self, ra: &mut RegistrationAuthority) -> Result<Fr> {
+        self.re_identify_with_rng(ra, &mut rand::thread_rng())
+    }
+
+    /// re_identify, drawing the new id (and registration randomness) from the given rng
+    pub fn re_identify_with_rng<R: RngCore + CryptoRng>(&mut self, ra: &mut RegistrationAuthority, rng: &mut R) -> Result<Fr> {
+
+        let old_cred:MasterCredential = self.credential.ok_or(Error::UnregisteredId)?;
+
+        // Generate new ID and register it with RA in place of the old one
+        let (old_id, new_id):(Fr, Fr) = (self.id, Fr::random(rng));
+        let cred:MasterCredential = self.register_with_rng(ra, new_id, Some((old_id, &old_cred)), rng)?;
+
+        self.id = new_id;
+        self.credential = Some(cred);
+        Ok(old_id)
+    }
 
-        // Generate new ID
-        let old_id:Fr = (*self).id;
-        let rng = &mut rand::thread_rng();
-        (*self).id = Fr::random(rng);
     
-        // Re-register new ID with RA, removing old ID if necessary
-        let opt_index = (*ra).userid_list.iter().position(|id| *id == old_id);
-        match opt_index {
-            Some(old_id_index) => (*ra).userid_list.remove(old_id_index),
-            _ => Fr::zero()
+    /// RegUser (user side): commit to the secret seed, prove knowledge of it to the RA and receive
+    /// a master credential on (id, seed).
+    pub fn reg_user(&mut self, ra: &mut RegistrationAuthority) -> Result<()> {
+        self.reg_user_with_rng(ra, &mut rand::thread_rng())
+    }
+
+    /// reg_user, drawing the proof's randomness from the given rng. Since both sides of the
+    /// exchange run in this process, the RA's challenge and signature are drawn from it too.
+    pub fn reg_user_with_rng<R: RngCore + CryptoRng>(&mut self, ra: &mut RegistrationAuthority, rng: &mut R) -> Result<()> {
+        let cred:MasterCredential = self.register_with_rng(ra, self.id, None, rng)?;
+        self.credential = Some(cred);
+        Ok(())
+    }
+
+    // RegUser for id, as a rotation from the (old id, credential) it replaces if any
+    fn register_with_rng<R: RngCore + CryptoRng>(&self, ra: &mut RegistrationAuthority, id: Fr, replaces: Option<(Fr, &MasterCredential)>,
+                                                 rng: &mut R) -> Result<MasterCredential> {
+
+        // Commit to seed as u^s and announce u^k for the proof of knowledge of s
+        let k:Fr = Fr::random(rng);
+        let request = RegistrationRequest {
+            id,
+            commitment: ra.vk.u * self.seed,
+            announcement: ra.vk.u * k
+        };
+        let c:Fr = match replaces {
+            Some((old_id, old_cred))    => ra.rotate_id_with_rng(old_id, old_cred, request, rng)?,
+            None                        => ra.challenge_with_rng(request, rng)?
         };
-        (*self).reg_user(ra);
 
-        return old_id;
+        // Respond to the RA's challenge: z = k + c*s
+        let response:Fr = k + c * self.seed;
+        let cred:MasterCredential = ra.issue_credential_with_rng(id, c, response, rng)?;
+        match verify_credential(&cred, id, self.seed, &ra.vk, ra.params.g2) {
+            true    => Ok(cred),
+            false   => Err(Error::InvalidSignature)
+        }
     }
 
-    
-    pub fn reg_user(&mut self, ra: &mut RegistrationAuthority) {
-        // TODO: Follow the protocol to register with RA, send id to RA, and receive master token
-        
-        // Add own id to list provided by RA
-        (*ra).userid_list.push((*self).id);
+    /// SubmitSurvey: derive this user's token for survey vid and prove in zero knowledge that it
+    /// matches a master credential from the RA and a signature in the SA's published list Lvid.
+    /// Fails if the user is not registered or not authorized to take the survey, or if either key
+    /// was generated under other public parameters.
+    #[allow(non_snake_case)]
+    pub fn submit_survey(&self, vid: Fr, response: &str, Lvid: &ParticipantList, vk_sa: &VerificationKey,
+                         vk_ra: &VerificationKey, params: &PublicParams) -> Result<Submission> {
+        self.submit_survey_with_rng(vid, response, Lvid, vk_sa, vk_ra, params, &mut rand::thread_rng())
+    }
+
+    /// submit_survey, drawing the proof's randomness from the given rng
+    #[allow(non_snake_case, clippy::too_many_arguments)]
+    pub fn submit_survey_with_rng<R: RngCore + CryptoRng>(&self, vid: Fr, response: &str, Lvid: &ParticipantList, vk_sa: &VerificationKey,
+                                                          vk_ra: &VerificationKey, params: &PublicParams, rng: &mut R) -> Result<Submission> {
+
+        params.check_key(vk_sa)?;
+        params.check_key(vk_ra)?;
+        let cred:MasterCredential = self.credential.ok_or(Error::UnregisteredId)?;
+        // Find own signature in the survey's published list
+        let (_, sigma_1, sigma_2) = Lvid.get(self.id).ok_or(Error::NotAuthorized)?;
+
+        let (token, proof) = zkp::prove_submission(params, vk_ra, vk_sa, vid, Lvid.epoch, self.id, self.seed, &cred, (*sigma_1, *sigma_2), response, rng)?;
+        Ok(Submission { vid, epoch: Lvid.epoch, token, response: String::from(response), proof })
+    }
+
+    /// SubmitSurvey for the survey derived from descriptor, first checking that the descriptor
+    /// belongs to the SA with key vk_sa, that the survey is open at the clock's current time and
+    /// that the response answers its questions. Fails with SurveyNotOpen or SurveyClosed outside
+    /// of the survey's window, and InvalidResponse if the response doesn't fit the questions.
+    #[allow(non_snake_case, clippy::too_many_arguments)]
+    pub fn submit_described_survey<C: Clock>(&self, descriptor: &SurveyDescriptor, response: &str, Lvid: &ParticipantList, vk_sa: &VerificationKey,
+                                             vk_ra: &VerificationKey, params: &PublicParams, clock: &C) -> Result<Submission> {
+        self.submit_described_survey_with_rng(descriptor, response, Lvid, vk_sa, vk_ra, params, clock, &mut rand::thread_rng())
+    }
+
+    /// submit_described_survey, drawing the proof's randomness from the given rng
+    #[allow(non_snake_case, clippy::too_many_arguments)]
+    pub fn submit_described_survey_with_rng<C: Clock, R: RngCore + CryptoRng>(&self, descriptor: &SurveyDescriptor, response: &str, Lvid: &ParticipantList,
+                                                                              vk_sa: &VerificationKey, vk_ra: &VerificationKey, params: &PublicParams,
+                                                                              clock: &C, rng: &mut R) -> Result<Submission> {
+        let vid:Fr = descriptor.vid();
+        descriptor.check(vid, vk_sa)?;
+        descriptor.check_open(clock.now())?;
+        descriptor.check_response(response)?;
+        self.submit_survey_with_rng(vid, response, Lvid, vk_sa, vk_ra, params, rng)
+    }
+
+    // Sign the participants of a new survey under id vid and record it as owned
+    #[allow(non_snake_case)]
+    fn publish_survey_with_rng<R: RngCore + CryptoRng>(&mut self, vid:Fr, L:&[Fr], params:&PublicParams, vk_ra: &VerificationKey, rng:&mut R) -> Result<(Fr, ParticipantList)> {
+        params.check_key(&self.vk)?;
+        params.check_key(vk_ra)?;
+
+        if self.verid_list.contains(vid) {
+            return Err(Error::DuplicateSurvey);
+        }
+        // Add vid to the list of owned surveys (by ID)
+        self.owned_surveys.push(vid);
+
+        // Add (sigma_1, sigma_2) to the list assoc with this vid and participant id
+        let id_list = ParticipantList::from(self.sign_participants(vid, 0, L, params, vk_ra, rng));
+        // Record the survey under its vid and "publish" list of signatures for each participant
+        self.verid_list.insert(vid, id_list.clone());
+        Ok((vid, id_list))
+    }
+
+    // Sign (signed_vid(vid, epoch), id) for every participant id in L
+    #[allow(non_snake_case)]
+    fn sign_participants<R: RngCore + CryptoRng>(&mut self, vid:Fr, epoch:u64, L:&[Fr], params:&PublicParams, vk_ra:&VerificationKey, rng:&mut R) -> Vec<(Fr, G1, G2)> {
+
+        /* --------------------------------------------------------------------------
+         *          Variation of Boneh-Boyen (BB) ID-based Signature Scheme
+         * --------------------------------------------------------------------------
+         */
+
+        /* Hoist invariant code to loop pre-header for efficiency */
+        // Sign with secret key
+        let sign_val:G1 = params.mul_g(self.sk);
+        // Sign with vid, or its hash with the epoch once participants have been revoked
+        let m:Fr = survey::signed_vid(vid, epoch);
+        // Fixed-base tables for u, v and h_RA, built once per pair of keys
+        let (u, v, h_ra) = self.signing_tables(vk_ra);
+
+        // Choose random r in Z_q for every participant up front, so that the published list only
+        // depends on the rng and not on how signing is spread across threads
+        let rs:Vec<Fr> = L.iter().map(|_| Fr::random(rng)).collect();
+
+        // Authorize all users in L (even if they're not registered -- this would be caught later)
+        // to submit a survey by constructing a signature with their id
+        let sign = |(id, r):(&Fr, &Fr)| -> (Fr, G1, G2) {
+            // First signature g^y * (u^m * v^id * h)^r, with the exponent distributed over the
+            // fixed bases: sign with m, participant ID and RA's h
+            let sigma_1:G1 = sign_val + u.mul(m * *r) + v.mul(*id * *r) + h_ra.mul(*r);
+            // Also sign 2nd group generator with random to get second signature
+            let sigma_2:G2 = params.mul_g2(*r);
+            (*id, sigma_1, sigma_2)
+        };
+        // Each signature is independent of the others
+        #[cfg(feature = "parallel")]
+        return L.par_iter().zip(rs.par_iter()).map(sign).collect();
+        #[cfg(not(feature = "parallel"))]
+        return L.iter().zip(rs.iter()).map(sign).collect();
+    }
+
+    // Tables for the SA's bases u, v and the RA's base h, rebuilt only if either key changed
+    fn signing_tables(&mut self, vk_ra: &VerificationKey) -> (FixedBase<G1>, FixedBase<G1>, FixedBase<G1>) {
+        let (u, v, h):(G1, G1, G1) = (self.vk.u, self.vk.v, vk_ra.h);
+        match &self.tables {
+            Some(tables) if (tables.0.base(), tables.1.base(), tables.2.base()) == (u, v, h) => tables.clone(),
+            _ => {
+                let tables = (FixedBase::new(u), FixedBase::new(v), FixedBase::new(h));
+                self.tables = Some(tables.clone());
+                tables
+            }
+        }
     }
 
     // TODO: Allow user to dynamically implement SurveyAuthority trait if they wish to do so after
     // initialization.
 
     // Generate signature-verification keys on the fly to be able to generate surveys
-//    pub fn become_SA(&mut self, g:G1, g2:G2) -> impl SurveyAuthority {
+//    pub fn become_SA(&mut self, params:&PublicParams) -> impl SurveyAuthority {
         
-//        let (vk, y) = SurveyAuthority::gen_SA(g, g2);
+//        let (vk, y) = SurveyAuthority::gen_SA(params);
 //        (*self).vk = vk;
 //        (*self).y = y;
 //        println!("{:?}", (*self).vk.u);
@@ -98,6 +333,13 @@ impl User {
 
 
 
+// Check that a master credential is a valid RA signature on (id, seed):
+//      e(sigma_1, g2) = e(g, g2)^x * e(v^id * u^s * h, sigma_2)
+fn verify_credential(cred: &MasterCredential, id: Fr, seed: Fr, vk_ra: &VerificationKey, g2: G2) -> bool {
+    pairing(cred.sigma_1, g2) == vk_ra.pk * pairing(vk_ra.v * id + vk_ra.u * seed + vk_ra.h, cred.sigma_2)
+}
+
+
 /*
  * ----------------------------------------------
  * |    SURVEY AUTHORITY (SA)                   |
@@ -110,118 +352,282 @@ impl User {
  *          + Specify a list of user IDs (authenticated by the RA) to send the survey to
 */
 
-pub trait SurveyAuthority {
+pub trait SurveyAuthority: Sized {
     
-    // Static method aliasing gen_SA
-    fn new(g:G1, g2:G2) -> Self;
+    /// Static method aliasing gen_SA
+    fn new(params:&PublicParams) -> Self {
+        Self::new_with_rng(params, &mut rand::thread_rng())
+    }
+
+    /// Static method aliasing gen_SA_with_rng
+    fn new_with_rng<R: RngCore + CryptoRng>(params:&PublicParams, rng:&mut R) -> Self;
+
+    /// Static method aliasing gen_SA_from_seed
+    fn from_seed(params:&PublicParams, seed:&str) -> Self {
+        Self::from_seed_with_rng(params, seed, &mut rand::thread_rng())
+    }
+
+    /// Static method aliasing gen_SA_from_seed_with_rng
+    fn from_seed_with_rng<R: RngCore + CryptoRng>(params:&PublicParams, seed:&str, rng:&mut R) -> Self;
+
+    /// Save the SA's key pair to a key file at path, with the secret key encrypted under passphrase
+    #[cfg(feature = "keystore")]
+    fn save_keys<P: AsRef<Path>>(&self, path:P, passphrase:&str) -> Result<()> {
+        self.save_keys_with_rng(path, passphrase, &mut rand::thread_rng())
+    }
+
+    /// save_keys, drawing the salt and nonce from the given rng
+    #[cfg(feature = "keystore")]
+    fn save_keys_with_rng<P: AsRef<Path>, R: RngCore + CryptoRng>(&self, path:P, passphrase:&str, rng:&mut R) -> Result<()>;
+
+    /// Restart a Survey Authority from the key file at path. Fails as KeyFile::load and
+    /// KeyFile::open do.
+    #[cfg(feature = "keystore")]
+    fn load_keys<P: AsRef<Path>>(params:&PublicParams, path:P, passphrase:&str) -> Result<Self> where Self: Sized;
     
     #[allow(non_snake_case)]
-    // Static method that creates values for SA
-    fn gen_SA(g:G1, g2:G2) -> (VerificationKey, Fr) {
+    /// Static method that creates values for SA
+    fn gen_SA(params:&PublicParams) -> (VerificationKey, Fr) {
+        Self::gen_SA_with_rng(params, &mut rand::thread_rng())
+    }
 
-        // crytpographiclaly secure thread-local rng
-        let rng = &mut rand::thread_rng();
+    #[allow(non_snake_case)]
+    /// gen_SA, drawing the keys from the given rng
+    fn gen_SA_with_rng<R: RngCore + CryptoRng>(params:&PublicParams, rng:&mut R) -> (VerificationKey, Fr) {
 
         // Generate random u,v,h in G_1
-        let u:G1 = G1::random(rng);
-        let v:G1 = G1::random(rng);
-        let h:G1 = G1::random(rng);
+        let bases:(G1, G1, G1) = (G1::random(rng), G1::random(rng), G1::random(rng));
+        Self::gen_SA_with_bases(params, bases, rng)
+    }
+
+    #[allow(non_snake_case)]
+    /// Static method that creates values for SA, with public bases u, v, h hashed from a public
+    /// seed so anyone can check that they were not chosen with a known relation
+    fn gen_SA_from_seed(params:&PublicParams, seed:&str) -> (VerificationKey, Fr) {
+        Self::gen_SA_from_seed_with_rng(params, seed, &mut rand::thread_rng())
+    }
+
+    #[allow(non_snake_case)]
+    /// gen_SA_from_seed, drawing the secret signing key from the given rng
+    fn gen_SA_from_seed_with_rng<R: RngCore + CryptoRng>(params:&PublicParams, seed:&str, rng:&mut R) -> (VerificationKey, Fr) {
+        Self::gen_SA_with_bases(params, hash::derive_bases(seed), rng)
+    }
+
+    #[allow(non_snake_case)]
+    /// Static method that creates values for SA over the given public bases u, v, h
+    fn gen_SA_with_bases<R: RngCore + CryptoRng>(params:&PublicParams, (u, v, h):(G1, G1, G1), rng:&mut R) -> (VerificationKey, Fr) {
 
         // Generate secret y as element of cyclic group with order r (q, in ANONIZE's notation???)
         let y:Fr = Fr::random(rng);
 
         // Compute e(g, g2)^y
-        let pair:Gt = pairing(g, g2).pow(y);
+        let pair:Gt = params.gt.pow(y);
 
         // Construct public verification key
-        let vk = VerificationKey { u, v, h, pk: pair };
+        let vk = VerificationKey { u, v, h, pk: pair, params: params.fingerprint };
         
         // Return the public and private keys
         (vk, y)
     }
 
-    // Instance method that generate survey with signature for each provided user id
-    fn gen_survey(&mut self, L:&Vec<Fr>, g:G1, g2:G2, vk_ra: &VerificationKey) -> Option<(Fr, Vec<(Fr, G1, G2)>)>;
+    /// Instance method that generate survey with signature for each provided user id. Fails with
+    /// InvalidParams if the SA's or RA's key was generated under other public parameters.
+    #[allow(non_snake_case)]
+    fn gen_survey(&mut self, L:&[Fr], params:&PublicParams, vk_ra: &VerificationKey) -> Result<(Fr, ParticipantList)> {
+        self.gen_survey_with_rng(L, params, vk_ra, &mut rand::thread_rng())
+    }
+
+    /// gen_survey, drawing the survey id and signature randomness from the given rng
+    #[allow(non_snake_case)]
+    fn gen_survey_with_rng<R: RngCore + CryptoRng>(&mut self, L:&[Fr], params:&PublicParams, vk_ra: &VerificationKey, rng:&mut R) -> Result<(Fr, ParticipantList)> {
+
+        // Choose random survey label
+        let label = Fr::random(rng);
+        self.gen_survey_for_vid_with_rng(label, L, params, vk_ra, rng)
+    }
+
+    /// Instance method that generates a survey whose id is derived from a label chosen by the
+    /// caller and the SA's key (see VerificationKey::survey_id), so the caller can fix the id in
+    /// advance but not pick one that belongs to another SA. Fails with DuplicateSurvey if the SA
+    /// already owns a survey with that id.
+    #[allow(non_snake_case)]
+    fn gen_survey_for_vid(&mut self, label:Fr, L:&[Fr], params:&PublicParams, vk_ra: &VerificationKey) -> Result<(Fr, ParticipantList)> {
+        self.gen_survey_for_vid_with_rng(label, L, params, vk_ra, &mut rand::thread_rng())
+    }
+
+    /// gen_survey_for_vid, drawing the signature randomness from the given rng
+    #[allow(non_snake_case)]
+    fn gen_survey_for_vid_with_rng<R: RngCore + CryptoRng>(&mut self, label:Fr, L:&[Fr], params:&PublicParams, vk_ra: &VerificationKey, rng:&mut R) -> Result<(Fr, ParticipantList)>;
+
+    /// Instance method that generates a survey whose id is derived from its descriptor, so that
+    /// the id commits to the survey's content. Fails with InvalidDescriptor if the descriptor
+    /// names another SA, closes before it opens, or asks for more responses than there are
+    /// participants.
+    #[allow(non_snake_case)]
+    fn gen_survey_from_descriptor(&mut self, descriptor:&SurveyDescriptor, L:&[Fr], params:&PublicParams, vk_ra: &VerificationKey) -> Result<(Fr, ParticipantList)> {
+        self.gen_survey_from_descriptor_with_rng(descriptor, L, params, vk_ra, &mut rand::thread_rng())
+    }
+
+    /// gen_survey_from_descriptor, drawing the signature randomness from the given rng
+    #[allow(non_snake_case)]
+    fn gen_survey_from_descriptor_with_rng<R: RngCore + CryptoRng>(&mut self, descriptor:&SurveyDescriptor, L:&[Fr], params:&PublicParams,
+                                                                   vk_ra: &VerificationKey, rng:&mut R) -> Result<(Fr, ParticipantList)>;
+
+    /// Instance method that signs a survey descriptor with the SA's key, so that participants can
+    /// check who set its questions. Fails with InvalidDescriptor if the descriptor names another
+    /// SA or is malformed.
+    fn sign_descriptor(&self, descriptor:&SurveyDescriptor, params:&PublicParams) -> Result<SignedDescriptor> {
+        self.sign_descriptor_with_rng(descriptor, params, &mut rand::thread_rng())
+    }
+
+    /// sign_descriptor, drawing the signature randomness from the given rng
+    fn sign_descriptor_with_rng<R: RngCore + CryptoRng>(&self, descriptor:&SurveyDescriptor, params:&PublicParams, rng:&mut R) -> Result<SignedDescriptor>;
+
+    /// Instance method that signs additional user ids into survey vid after it was published, and
+    /// returns the amended list with its version bumped. Ids already on the list are re-signed.
+    /// Fails with UnknownSurvey if the SA does not own vid.
+    #[allow(non_snake_case)]
+    fn add_participants(&mut self, vid:Fr, L:&[Fr], params:&PublicParams, vk_ra: &VerificationKey) -> Result<ParticipantList> {
+        self.add_participants_with_rng(vid, L, params, vk_ra, &mut rand::thread_rng())
+    }
+
+    /// add_participants, drawing the signature randomness from the given rng
+    #[allow(non_snake_case)]
+    fn add_participants_with_rng<R: RngCore + CryptoRng>(&mut self, vid:Fr, L:&[Fr], params:&PublicParams, vk_ra: &VerificationKey, rng:&mut R) -> Result<ParticipantList>;
+
+    /// Instance method that revokes user ids from survey vid and returns the amended list. This
+    /// starts a new epoch: everyone who remains is re-signed, and must use the new list to submit,
+    /// while the revoked ids' signatures stop being accepted. Fails with UnknownSurvey if the SA
+    /// does not own vid, and NotAuthorized (revoking nobody) if an id is not on the list.
+    fn revoke_participants(&mut self, vid:Fr, ids:&[Fr], params:&PublicParams, vk_ra: &VerificationKey) -> Result<ParticipantList> {
+        self.revoke_participants_with_rng(vid, ids, params, vk_ra, &mut rand::thread_rng())
+    }
+
+    /// revoke_participants, drawing the signature randomness from the given rng
+    fn revoke_participants_with_rng<R: RngCore + CryptoRng>(&mut self, vid:Fr, ids:&[Fr], params:&PublicParams, vk_ra: &VerificationKey, rng:&mut R) -> Result<ParticipantList>;
+
+    /// Instance method that closes the survey whose submissions store collects: the store accepts
+    /// no further submissions, so its result set is final, and the survey's list can no longer be
+    /// amended. Fails with UnknownSurvey if the SA does not own the store's survey.
+    fn close_survey(&mut self, store:&mut SubmissionStore) -> Result<()>;
 }
 
 impl SurveyAuthority for User {
     
     /* Create Survey Authority */
-    fn new(g:G1, g2:G2) -> User {
+    fn new_with_rng<R: RngCore + CryptoRng>(params:&PublicParams, rng:&mut R) -> User {
  
-        let mut sa = User::new();
+        let mut sa = User::new_with_rng(rng);
         
         // Return user with verification and signing key for creating surveys
-        let (vk, y) = Self::gen_SA(g, g2);
+        let (vk, y) = Self::gen_SA_with_rng(params, rng);
         sa.vk = vk;
         sa.sk = y;
-        return sa;
+        sa
     }
 
+    fn from_seed_with_rng<R: RngCore + CryptoRng>(params:&PublicParams, seed:&str, rng:&mut R) -> User {
 
-        
-    fn gen_survey(&mut self, L:&Vec<Fr>, g:G1, g2:G2, vk_ra: &VerificationKey) -> Option<(Fr, Vec<(Fr, G1, G2)>)> {
-        // crytpographiclaly secure thread-local rng
-        let rng = &mut rand::thread_rng();
+        let mut sa = User::new_with_rng(rng);
+        let (vk, y) = Self::gen_SA_from_seed_with_rng(params, seed, rng);
+        sa.vk = vk;
+        sa.sk = y;
+        sa
+    }
 
-        // Choose random survey id as well
-        let vid = Fr::random(rng);
-        // Add vid to the list of owned surveys (by ID)
-        (*self).owned_surveys.push(vid);
+    #[cfg(feature = "keystore")]
+    fn save_keys_with_rng<P: AsRef<Path>, R: RngCore + CryptoRng>(&self, path:P, passphrase:&str, rng:&mut R) -> Result<()> {
+        KeyFile::seal(&self.vk, self.sk, passphrase, KdfParams::default(), rng)?.save(path)
+    }
+
+    #[cfg(feature = "keystore")]
+    fn load_keys<P: AsRef<Path>>(params:&PublicParams, path:P, passphrase:&str) -> Result<User> {
+        let file:KeyFile = KeyFile::load(path)?;
+        let mut sa = User::new();
+        sa.sk = file.open(passphrase, params)?;
+        sa.vk = file.vk;
+        Ok(sa)
+    }
 
-        /* --------------------------------------------------------------------------
-         *          Variation of Boneh-Boyen (BB) ID-based Signature Scheme
-         * --------------------------------------------------------------------------
-         */
 
-        /* Hoist invariant code to loop pre-header for efficiency */
-        // Sign with secret key
-        let sign_val:G1 = g * (*self).sk;
-        // Sign with vid
-        let vid_val:G1 = (*self).vk.u * vid;
         
-        // Authorize all users in L (even if they're not registered -- this would be caught later)
-        // to submit a survey by constructing a signature with their id
-        for id in L {
-            
-            // Choose random r in Z_q (TODO: Move this and sigma_2 outside of loop???)
-            let r = Fr::random(rng);
-            // Sign with participant ID
-            let user_val:G1 = (*self).vk.v * *id;
-            // Put it all together to get the first signature
-            let sigma_1:G1 = sign_val + (vid_val + user_val + (*vk_ra).h) * r;
-            // Also sign 2nd group generator with random to get second signature
-            let sigma_2:G2 = g2 * r;
-            let user_signature:(Fr, G1, G2) = (*id, sigma_1, sigma_2);
-            
-            let mut found:bool = false;
-            // Loop through the various survey(s) the SA owns
-            for (owned_vid, id_list) in &mut (*self).verid_list {
-                // Found entry to add (id, signature) to
-                if *owned_vid == vid {
-                    found = true;
-                    id_list.push(user_signature);
-                    break;
-                }
-            }
-            if !found {   
-                // Add (sigma_1, sigma_2) to the list assoc with this vid and participant id
-                let mut user_list:Vec<(Fr, G1, G2)> = Vec::new();
-                user_list.push(user_signature);
-                let survey_entry:(Fr, Vec<(Fr, G1, G2)>)  = (vid, user_list);
-                // Must create new entry for (vid, {(ids, signatures)})
-                (*self).verid_list.push(survey_entry);
-            }
+    #[allow(non_snake_case)]
+    fn gen_survey_for_vid_with_rng<R: RngCore + CryptoRng>(&mut self, label:Fr, L:&[Fr], params:&PublicParams, vk_ra: &VerificationKey, rng:&mut R) -> Result<(Fr, ParticipantList)> {
+        let vid:Fr = self.vk.survey_id(label);
+        self.publish_survey_with_rng(vid, L, params, vk_ra, rng)
+    }
+
+    #[allow(non_snake_case)]
+    fn gen_survey_from_descriptor_with_rng<R: RngCore + CryptoRng>(&mut self, descriptor:&SurveyDescriptor, L:&[Fr], params:&PublicParams,
+                                                                   vk_ra: &VerificationKey, rng:&mut R) -> Result<(Fr, ParticipantList)> {
+        let vid:Fr = descriptor.vid();
+        descriptor.check(vid, &self.vk)?;
+        // Results could never be released
+        let participants:HashSet<FrKey> = L.iter().map(|id| FrKey::from(*id)).collect();
+        if descriptor.min_responses as usize > participants.len() {
+            return Err(Error::InvalidDescriptor);
         }
-        // "Publish" list of signatures for each participant of survey vid
-        for (owned_vid, id_list) in &(*self).verid_list {
-            // NOTE: unless something were to remove it during a race condition, should always return
-            if *owned_vid == vid {
-                let vid_list:(Fr, Vec<(Fr, G1, G2)>) = (vid, (*id_list).clone());
-                return Some(vid_list);
-            }
+        self.publish_survey_with_rng(vid, L, params, vk_ra, rng)
+    }
+
+    fn sign_descriptor_with_rng<R: RngCore + CryptoRng>(&self, descriptor:&SurveyDescriptor, params:&PublicParams, rng:&mut R) -> Result<SignedDescriptor> {
+        params.check_key(&self.vk)?;
+        descriptor.check(descriptor.vid(), &self.vk)?;
+
+        let signature = zkp::sign_with_key(params, &self.vk, self.sk, &descriptor.to_bytes(), rng);
+        Ok(SignedDescriptor { descriptor: descriptor.clone(), signature })
+    }
+
+    #[allow(non_snake_case)]
+    fn add_participants_with_rng<R: RngCore + CryptoRng>(&mut self, vid:Fr, L:&[Fr], params:&PublicParams, vk_ra: &VerificationKey, rng:&mut R) -> Result<ParticipantList> {
+        params.check_key(&self.vk)?;
+        params.check_key(vk_ra)?;
+
+        // New participants are signed under the list's current epoch, so nobody else is affected
+        let epoch:u64 = self.verid_list.get(vid).ok_or(Error::UnknownSurvey)?.epoch;
+        if self.closed_surveys.contains(&FrKey::from(vid)) {
+            return Err(Error::SurveyClosed);
+        }
+        let signatures:Vec<(Fr, G1, G2)> = self.sign_participants(vid, epoch, L, params, vk_ra, rng);
+
+        let id_list:&mut ParticipantList = self.verid_list.get_mut(vid).expect("Survey is owned");
+        for user_signature in signatures {
+            id_list.insert(user_signature);
+        }
+        id_list.version += 1;
+        Ok(id_list.clone())
+    }
+
+    fn revoke_participants_with_rng<R: RngCore + CryptoRng>(&mut self, vid:Fr, ids:&[Fr], params:&PublicParams, vk_ra: &VerificationKey, rng:&mut R) -> Result<ParticipantList> {
+        params.check_key(&self.vk)?;
+        params.check_key(vk_ra)?;
+
+        let id_list:&ParticipantList = self.verid_list.get(vid).ok_or(Error::UnknownSurvey)?;
+        if self.closed_surveys.contains(&FrKey::from(vid)) {
+            return Err(Error::SurveyClosed);
+        }
+        if !ids.iter().all(|id| id_list.contains(*id)) {
+            return Err(Error::NotAuthorized);
         }
-        return None;
+        let revoked:HashSet<FrKey> = ids.iter().map(|id| FrKey::from(*id)).collect();
+        let remaining:Vec<Fr> = id_list.iter().map(|(id, _, _)| *id).filter(|id| !revoked.contains(&FrKey::from(*id))).collect();
+        let (version, epoch):(u64, u64) = (id_list.version + 1, id_list.epoch + 1);
+
+        // Re-sign everyone who remains under the new epoch, in the same order
+        let mut amended = ParticipantList::from(self.sign_participants(vid, epoch, &remaining, params, vk_ra, rng));
+        amended.version = version;
+        amended.epoch = epoch;
+        self.verid_list.insert(vid, amended.clone());
+        Ok(amended)
+    }
+
+    fn close_survey(&mut self, store:&mut SubmissionStore) -> Result<()> {
+        if !self.verid_list.contains(store.vid) {
+            return Err(Error::UnknownSurvey);
+        }
+        store.close()?;
+        self.closed_surveys.insert(FrKey::from(store.vid));
+        Ok(())
     }
 }
 
@@ -230,14 +636,183 @@ impl SurveyAuthority for User {
  * Unit tests
  */
 
+#[cfg(test)]
+use crate::verifier::CheckOutcome;
+
 #[test]
 #[allow(non_snake_case)]
 // Test to ensure that e(g, g2)^(sk_SA) = vk_SA
 fn test_SA_keys() {
+    let params = PublicParams::generate();
+    let sa:User = SurveyAuthority::new(&params);
+    assert!( pairing(params.g, params.g2).pow(sa.sk) == sa.vk.pk );
+}
+
+#[test]
+// Test that RegUser yields a master credential that verifies under the RA's key
+fn test_reg_user() {
+    let params = PublicParams::generate();
+    let mut ra = RegistrationAuthority::new(&params);
+    let mut user = User::new();
+
+    assert!( user.reg_user(&mut ra).is_ok() );
+    assert!( ra.registry.contains(user.id) );
+    let cred = user.credential.expect("No credential issued");
+    assert!( verify_credential(&cred, user.id, user.seed, &ra.vk, params.g2) );
+    // Credential is bound to the seed
+    assert!( !verify_credential(&cred, user.id, user.seed + Fr::one(), &ra.vk, params.g2) );
+
+    // Re-registering the same id is refused
+    assert_eq!( user.reg_user(&mut ra), Err(Error::DuplicateId) );
+}
+
+#[test]
+// Test that users cannot submit to a survey they were not authorized for, or before registering
+fn test_submit_survey_unauthorized() {
+    let params = PublicParams::generate();
+    let mut ra = RegistrationAuthority::new(&params);
+    let mut sa:User = SurveyAuthority::new(&params);
+    let mut user = User::new();
+    let mut outsider = User::new();
+
+    // Authorized but not yet registered
+    let (vid, signatures) = sa.gen_survey(&[user.id], &params, &ra.vk).expect("SA survey creation failed!");
+    assert_eq!( user.submit_survey(vid, "yes", &signatures, &sa.vk, &ra.vk, &params), Err(Error::UnregisteredId) );
+
+    // Registered but not authorized
+    assert!( user.reg_user(&mut ra).is_ok() && outsider.reg_user(&mut ra).is_ok() );
+    assert!( user.submit_survey(vid, "yes", &signatures, &sa.vk, &ra.vk, &params).is_ok() );
+    assert_eq!( outsider.submit_survey(vid, "yes", &signatures, &sa.vk, &ra.vk, &params), Err(Error::NotAuthorized) );
+}
+
+#[test]
+// Test that a survey lists every participant once, in order, with valid signatures
+fn test_gen_survey_list() {
+    let params = PublicParams::generate();
     let rng = &mut rand::thread_rng();
-    let (g, g2):(G1, G2) = (G1::random(rng), G2::random(rng));
-    let sa:User = SurveyAuthority::new(g, g2);
-    assert!( pairing(g, g2).pow(sa.sk) == sa.vk.pk ); 
+    let ra = RegistrationAuthority::new(&params);
+    let mut sa:User = SurveyAuthority::new(&params);
+    let mut ids:Vec<Fr> = (0..64).map(|_| Fr::random(rng)).collect();
+    ids.push(ids[0]);
+
+    let (vid, signatures) = sa.gen_survey(&ids, &params, &ra.vk).expect("SA survey creation failed!");
+    assert_eq!( signatures.len(), 64 );
+    assert!( signatures.iter().map(|entry| entry.0).eq(ids[..64].iter().cloned()) );
+    assert_eq!( crate::verify_survey_list(vid, &signatures, &sa.vk, &ra.vk, &params), Ok(()) );
+    assert_eq!( sa.verid_list.get(vid), Some(&signatures) );
+}
+
+#[test]
+// Test that participants can be added to and revoked from a published survey
+fn test_amend_survey() {
+    let params = PublicParams::generate();
+    let mut ra = RegistrationAuthority::new(&params);
+    let mut sa:User = SurveyAuthority::new(&params);
+    let mut users:Vec<User> = (0..3).map(|_| User::new()).collect();
+    for user in users.iter_mut() {
+        assert!( user.reg_user(&mut ra).is_ok() );
+    }
+    let (a, b, c):(Fr, Fr, Fr) = (users[0].id, users[1].id, users[2].id);
+
+    let (vid, first) = sa.gen_survey(&[a, b], &params, &ra.vk).expect("SA survey creation failed!");
+    let added = sa.add_participants(vid, &[c], &params, &ra.vk).expect("Adding participants failed");
+    assert_eq!( (added.version, added.epoch, added.len()), (1, 0, 3) );
+    // Earlier signatures are untouched
+    assert_eq!( added[..2], first[..] );
+    assert_eq!( crate::authorized(c, vid, &added, &sa.vk, &ra.vk, &params), Ok(()) );
+    let old_submission = users[1].submit_survey(vid, "yes", &added, &sa.vk, &ra.vk, &params).expect("Submission failed");
+    let mut store = SubmissionStore::new(vid);
+    assert_eq!( store.update_list(&added), Ok(()) );
+
+    let revoked = sa.revoke_participants(vid, &[b], &params, &ra.vk).expect("Revoking participants failed");
+    assert_eq!( (revoked.version, revoked.epoch), (2, 1) );
+    assert!( revoked.iter().map(|entry| entry.0).eq(vec![a, c]) );
+    assert_eq!( crate::verify_survey_list(vid, &revoked, &sa.vk, &ra.vk, &params), Ok(()) );
+    assert_eq!( sa.verid_list.get(vid), Some(&revoked) );
+
+    // The revoked participant is off the list, and their old signature is no longer accepted
+    assert_eq!( users[1].submit_survey(vid, "yes", &revoked, &sa.vk, &ra.vk, &params), Err(Error::NotAuthorized) );
+    assert_eq!( crate::verifier::check(&old_submission, &sa.vk, &ra.vk, vid, revoked.epoch, &params), Err(Error::StaleSurveyList) );
+    let mut forged = old_submission.clone();
+    forged.epoch = revoked.epoch;
+    assert_eq!( crate::verifier::check(&forged, &sa.vk, &ra.vk, vid, revoked.epoch, &params), Err(Error::InvalidProof) );
+    let submission = users[0].submit_survey(vid, "no", &revoked, &sa.vk, &ra.vk, &params).expect("Submission failed");
+    assert!( crate::verifier::check(&submission, &sa.vk, &ra.vk, vid, revoked.epoch, &params).is_ok() );
+
+    // A store moves to the new epoch with the amended list, and won't go back to an older one
+    assert_eq!( store.update_list(&revoked), Ok(()) );
+    assert_eq!( store.epoch(), 1 );
+    assert_eq!( store.check(old_submission, &sa.vk, &ra.vk, &params), CheckOutcome::Rejected(Error::StaleSurveyList) );
+    assert_eq!( store.check(submission, &sa.vk, &ra.vk, &params), CheckOutcome::Accepted );
+    assert_eq!( store.update_list(&first), Err(Error::StaleSurveyList) );
+
+    assert_eq!( sa.revoke_participants(vid, &[b], &params, &ra.vk), Err(Error::NotAuthorized) );
+    assert_eq!( sa.add_participants(vid + Fr::one(), &[b], &params, &ra.vk), Err(Error::UnknownSurvey) );
+}
+
+#[test]
+// Test that survey ids can be chosen by the SA or derived from the survey's descriptor
+fn test_gen_survey_from_descriptor() {
+    let params = PublicParams::generate();
+    let ra = RegistrationAuthority::new(&params);
+    let mut sa:User = SurveyAuthority::new(&params);
+    let other:User = SurveyAuthority::new(&params);
+    let id = Fr::one();
+
+    let descriptor = SurveyDescriptor::new("Lunch", crate::schema::sample_questions(), 1_700_000_000, 1_700_086_400, &sa.vk);
+    let (vid, signatures) = sa.gen_survey_from_descriptor(&descriptor, &[id], &params, &ra.vk).expect("SA survey creation failed!");
+    assert!( vid == descriptor.vid() );
+    assert_eq!( crate::authorized(id, vid, &signatures, &sa.vk, &ra.vk, &params), Ok(()) );
+
+    // Participants can check what they are answering and who runs it
+    assert_eq!( descriptor.check(vid, &sa.vk), Ok(()) );
+    assert_eq!( descriptor.check(vid, &other.vk), Err(Error::InvalidDescriptor) );
+    let mut changed = descriptor.clone();
+    changed.questions[1].required = false;
+    assert_eq!( changed.check(vid, &sa.vk), Err(Error::InvalidDescriptor) );
+
+    // The SA signs the descriptor, and the signature only holds for that descriptor and key
+    let signed = sa.sign_descriptor(&descriptor, &params).expect("Signing failed");
+    assert_eq!( signed.verify(vid, &sa.vk, &params), Ok(()) );
+    assert_eq!( other.sign_descriptor(&descriptor, &params), Err(Error::InvalidDescriptor) );
+    let forged = SignedDescriptor { descriptor: changed.clone(), signature: signed.signature };
+    assert_eq!( forged.verify(changed.vid(), &sa.vk, &params), Err(Error::InvalidSignature) );
+    let mut no_questions = descriptor.clone();
+    no_questions.questions.clear();
+    assert_eq!( sa.sign_descriptor(&no_questions, &params), Err(Error::InvalidDescriptor) );
+
+    assert_eq!( sa.gen_survey_from_descriptor(&descriptor, &[id], &params, &ra.vk), Err(Error::DuplicateSurvey) );
+    let foreign = SurveyDescriptor::new("Lunch", crate::schema::sample_questions(), 0, 0, &other.vk);
+    assert_eq!( sa.gen_survey_from_descriptor(&foreign, &[id], &params, &ra.vk), Err(Error::InvalidDescriptor) );
+    let unreachable = SurveyDescriptor::new("Lunch", crate::schema::sample_questions(), 0, 0, &sa.vk).with_min_responses(2);
+    assert_eq!( sa.gen_survey_from_descriptor(&unreachable, &[id, id], &params, &ra.vk), Err(Error::InvalidDescriptor) );
+
+    // Labels are bound to the SA's key, so another SA reusing one gets a different survey id
+    let label:Fr = Fr::from_str("42").unwrap();
+    assert!( sa.gen_survey_for_vid(label, &[id], &params, &ra.vk).map(|(vid, _)| vid == sa.vk.survey_id(label)) == Ok(true) );
+    assert_eq!( sa.gen_survey_for_vid(label, &[id], &params, &ra.vk), Err(Error::DuplicateSurvey) );
+    assert!( sa.vk.survey_id(label) != other.vk.survey_id(label) );
+    assert!( sa.vk.survey_id(label) != label );
+}
+
+#[test]
+// Test that every randomized step is reproducible when driven by a seeded rng
+fn test_seeded_rng() {
+    use rand::{SeedableRng, rngs::StdRng};
+
+    let run = || {
+        let rng = &mut StdRng::from_seed([7; 32]);
+        let params = PublicParams::generate_with_rng(rng);
+        let mut ra = RegistrationAuthority::new_with_rng(&params, rng);
+        let mut sa:User = SurveyAuthority::new_with_rng(&params, rng);
+        let mut user = User::new_with_rng(rng);
+        user.reg_user_with_rng(&mut ra, rng).expect("Registration failed");
+        let (vid, signatures) = sa.gen_survey_with_rng(&[user.id], &params, &ra.vk, rng).expect("SA survey creation failed!");
+        let submission = user.submit_survey_with_rng(vid, "yes", &signatures, &sa.vk, &ra.vk, &params, rng).expect("Submission failed");
+        assert!( crate::verifier::check(&submission, &sa.vk, &ra.vk, vid, signatures.epoch, &params).is_ok() );
+        (params.fingerprint, user.credential, signatures, submission)
+    };
+    assert_eq!( run(), run() );
 }
 
 
@@ -245,80 +820,63 @@ fn test_SA_keys() {
  * Benchmark tests
  */
 
+#[cfg(test)]
+#[allow(non_snake_case)]
+// GenSA num_trials times
+fn bench_gen_SA(num_trials: usize) {
+
+    // Setup
+    let params = PublicParams::generate();
+
+    println!("GenSA Benchmark Test ({} trials)", num_trials);
+    crate::bench_trials("Trial", num_trials, |_| {
+        let _sa:User = SurveyAuthority::new(&params);
+    });
+}
+
 #[test]
 #[ignore]
 #[allow(non_snake_case)]
 // Test 100 iterations of GenSA to get mean and standard deviation
 fn bench_100_gen_SA() {
-
-    use std::time::{Duration, Instant};
-
-    // Setup 
-    let rng = &mut rand::thread_rng();
-    let (g, g2):(G1, G2) = (G1::random(rng), G2::random(rng));
-    
-    // 100 irerations of GenSA
-    const NUM_TRIALS:usize = 100;
-    assert!(NUM_TRIALS > 1);
-    println!("GenSA Benchmark Test ({} trials)", NUM_TRIALS);
-    let mut sum:Duration = Duration::new(0,0);
-    let mut durs:[Duration;NUM_TRIALS] = [Duration::new(0,0);NUM_TRIALS];
-    for i in 0..NUM_TRIALS {
-        let start = Instant::now(); 
-        let _sa:User = SurveyAuthority::new(g, g2);
-        durs[i] = start.elapsed();
-        sum += durs[i];
-        println!("Trial {}:\t{:?}", i+1, durs[i]);
-    }
-    println!();
-    // Calculate mean
-    let mean = sum / (NUM_TRIALS as u32);
-    // Calculate standard deviation
-    let mut sum_of_diff:f32 = 0.0;
-    for i in 0..NUM_TRIALS {
-        sum_of_diff += f32::powf((((durs[i].as_millis() as i128) - (mean.as_millis() as i128)) as f32)/1000.0, 2.0);
-    }
-    let sd = ( sum_of_diff / ((NUM_TRIALS as f32)- 1.0)).sqrt();
- 
-    println!("Mean:\t\t{:?}", mean);
-    println!("Std Dev:\t{:?}s", sd);
+    bench_gen_SA(100);
 }
 
-
 #[test]
+#[ignore]
 #[allow(non_snake_case)]
 // Test 5 iterations of GenSA to get mean and standard deviation
 fn bench_5_gen_SA() {
+    bench_gen_SA(5);
+}
+
+
+#[test]
+#[ignore]
+#[allow(clippy::assertions_on_constants)]
+// Test 100 multiplications of g and g2 by random scalars, with the fixed-base tables and without
+fn bench_100_fixed_base_mul() {
 
     use std::time::{Duration, Instant};
 
-    // Setup 
+    // Setup
+    let params = PublicParams::generate();
     let rng = &mut rand::thread_rng();
-    let (g, g2):(G1, G2) = (G1::random(rng), G2::random(rng));
-    
-    // 5 irerations of GenSA
-    const NUM_TRIALS:usize = 5;
+
+    const NUM_TRIALS:usize = 100;
     assert!(NUM_TRIALS > 1);
-    println!("GenSA Benchmark Test ({} trials)", NUM_TRIALS);
-    let mut sum:Duration = Duration::new(0,0);
-    let mut durs:[Duration;NUM_TRIALS] = [Duration::new(0,0);NUM_TRIALS];
-    for i in 0..NUM_TRIALS {
-        let start = Instant::now(); 
-        let _sa:User = SurveyAuthority::new(g, g2);
-        durs[i] = start.elapsed();
-        sum += durs[i];
-        println!("Trial {}:\t{:?}", i+1, durs[i]);
-    }
-    println!();
-    // Calculate mean
-    let mean = sum / (NUM_TRIALS as u32);
-    // Calculate standard deviation
-    let mut sum_of_diff:f32 = 0.0;
-    for i in 0..NUM_TRIALS {
-        sum_of_diff += f32::powf((((durs[i].as_millis() as i128) - (mean.as_millis() as i128)) as f32)/1000.0, 2.0);
-    }
-    let sd = ( sum_of_diff / ((NUM_TRIALS as f32)- 1.0)).sqrt();
- 
-    println!("Mean:\t\t{:?}", mean);
-    println!("Std Dev:\t{:?}s", sd);
+    println!("Fixed-base Multiplication Benchmark Test ({} trials)", NUM_TRIALS);
+    let xs:Vec<Fr> = (0..NUM_TRIALS).map(|_| Fr::random(rng)).collect();
+    let time = |mul: &dyn Fn(Fr)| -> Duration {
+        let start = Instant::now();
+        for x in &xs {
+            mul(*x);
+        }
+        start.elapsed() / (NUM_TRIALS as u32)
+    };
+
+    println!("G1 table:\t{:?}", time(&|x| { params.mul_g(x); }));
+    println!("G1 g * x:\t{:?}", time(&|x| { let _ = params.g * x; }));
+    println!("G2 table:\t{:?}", time(&|x| { params.mul_g2(x); }));
+    println!("G2 g2 * x:\t{:?}", time(&|x| { let _ = params.g2 * x; }));
 }
@@ -0,0 +1,399 @@
+extern crate tbn;
+
+use std::cmp::Ordering;
+use std::collections::VecDeque;
+use tbn::{Fr, G1};
+use crate::users::{VerificationKey, Submission};
+use crate::params::PublicParams;
+use crate::survey::{ParticipantList, SurveyDescriptor};
+use crate::clock::{Clock, SystemClock};
+use crate::store::{SubmissionBackend, MemoryStore};
+use crate::zkp;
+use crate::tally;
+use crate::error::{Error, Result};
+
+/*
+ * ----------------------------------------------
+ * |    CHECK                                   |
+ * ----------------------------------------------
+ *
+ * Anyone holding the SA and RA verification keys can check a submission. The SA additionally
+ * keeps the accepted submissions for each of its surveys in a SubmissionStore, storing at most one
+ * response per survey token: a later valid submission with the same token replaces the earlier
+ * response. It keeps them in a SubmissionBackend, in memory unless given a durable one,
+ * together with the survey's epoch and close, so that a restarted store picks up where it was.
+ *
+ * Submissions are checked against the epoch of the survey list the verifier holds. Revoking a
+ * participant starts a new epoch, so submissions made afterwards under their old signature are
+ * refused; responses accepted before the revocation stay, since they cannot be traced to anyone.
+ *
+ * A store for a survey with a descriptor also refuses responses that don't answer its questions,
+ * and submissions outside of the survey's window according to the store's clock. Once the SA
+ * closes the survey, the store refuses everything and its submissions are the final results. They
+ * are only read out under the same conditions as the tally: once the survey has ended, and at least
+ * the descriptor's minimum number of responses answering its questions are stored.
+*/
+
+// Entries kept in a store's audit log unless it is given another length
+const AUDIT_LOG_LEN: usize = 1024;
+
+/// What happened to a submission handed to a SubmissionStore
+#[derive(Clone, Debug, PartialEq)]
+pub enum CheckOutcome {
+    /// First valid response for this token
+    Accepted,
+    /// Valid response that overwrote an earlier response for this token
+    Replaced,
+    /// Invalid submission, and why it was rejected
+    Rejected(Error)
+}
+
+/// Verify that a submission was correctly computed for survey vid by a registered, authorized user
+/// on the epoch of its participant list. Fails with UnknownSurvey if it was made for another
+/// survey, StaleSurveyList if it was made under another epoch, InvalidParams if either key belongs
+/// to other public parameters, and InvalidProof if the proof does not verify under the SA's and RA's keys.
+pub fn check(submission: &Submission, vk_sa: &VerificationKey, vk_ra: &VerificationKey, vid: Fr, epoch: u64, params: &PublicParams) -> Result<()> {
+
+    params.check_key(vk_sa)?;
+    params.check_key(vk_ra)?;
+    if submission.vid != vid {
+        return Err(Error::UnknownSurvey);
+    }
+    if submission.epoch != epoch {
+        return Err(Error::StaleSurveyList);
+    }
+    match zkp::verify_submission(params, vk_ra, vk_sa, vid, epoch, submission.token, &submission.response, &submission.proof) {
+        true    => Ok(()),
+        false   => Err(Error::InvalidProof)
+    }
+}
+
+/// Checks submissions to a single survey, keeping the accepted ones (one per survey token)
+pub struct SubmissionStore {
+    pub vid: Fr,
+    /// Questions responses must answer and when, if the survey has a descriptor
+    pub descriptor: Option<SurveyDescriptor>,
+    // Time submissions are checked against the descriptor's window at
+    clock: Box<dyn Clock>,
+    // Accepted submissions, and the epoch of the survey's list and when it was closed
+    backend: Box<dyn SubmissionBackend>,
+    /// Token and outcome of the latest submissions checked, in the order they were received
+    pub audit_log: VecDeque<(G1, CheckOutcome)>,
+    // Entries kept in audit_log, the oldest being dropped first
+    audit_log_len: usize
+}
+
+impl SubmissionStore {
+
+    pub fn new(vid: Fr) -> Self {
+        SubmissionStore { vid, descriptor: None, clock: Box::new(SystemClock), backend: Box::new(MemoryStore::new()),
+                          audit_log: VecDeque::new(), audit_log_len: AUDIT_LOG_LEN }
+    }
+
+    /// Read the time from clock instead of the system clock
+    pub fn with_clock<C: Clock + 'static>(mut self, clock: C) -> Self {
+        self.clock = Box::new(clock);
+        self
+    }
+
+    /// Keep the outcome of the last len submissions checked in the audit log, instead of 1024
+    pub fn with_audit_log_len(mut self, len: usize) -> Self {
+        self.audit_log_len = len;
+        self.audit_log.truncate(len);
+        self
+    }
+
+    /// Keep accepted submissions in backend (e.g. a FileStore, to keep them across restarts)
+    /// instead of in memory. Submissions to this survey already in the backend count as accepted,
+    /// and the epoch and close the backend recorded for it are in force.
+    pub fn with_backend<B: SubmissionBackend + 'static>(mut self, backend: B) -> Self {
+        self.backend = Box::new(backend);
+        self
+    }
+
+    /// Check further submissions against list, the survey's participant list as amended by the SA.
+    /// A list revoking participants starts a new epoch, after which submissions made under an
+    /// earlier one are rejected with StaleSurveyList. Fails with StaleSurveyList if list is from an
+    /// older epoch than the current one.
+    pub fn update_list(&mut self, list: &ParticipantList) -> Result<()> {
+        match list.epoch.cmp(&self.epoch()) {
+            Ordering::Greater   => self.backend.set_epoch(self.vid, list.epoch),
+            Ordering::Equal     => Ok(()),
+            Ordering::Less      => Err(Error::StaleSurveyList)
+        }
+    }
+
+    /// Epoch of the participant list submissions are checked against
+    pub fn epoch(&self) -> u64 {
+        self.backend.epoch(self.vid)
+    }
+
+    /// Stop accepting submissions, freezing the stored ones. Closing again keeps the first time.
+    /// Fails with the backend's error if it can't record the close.
+    pub fn close(&mut self) -> Result<()> {
+        match self.is_closed() {
+            true    => Ok(()),
+            false   => self.backend.close(self.vid, self.clock.now())
+        }
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed_at().is_some()
+    }
+
+    /// Time the survey was closed at, if the SA closed it
+    pub fn closed_at(&self) -> Option<u64> {
+        self.backend.closed_at(self.vid)
+    }
+
+    /// Whether the survey takes no more submissions: the SA closed it, or its descriptor's closing
+    /// time has passed
+    pub fn has_ended(&self) -> bool {
+        self.is_closed() || self.descriptor.as_ref().is_some_and(|descriptor| descriptor.closes_at <= self.clock.now())
+    }
+
+    /// Store for the survey derived from descriptor, only accepting responses to its questions
+    pub fn for_descriptor(descriptor: SurveyDescriptor) -> Self {
+        SubmissionStore { descriptor: Some(descriptor.clone()), ..SubmissionStore::new(descriptor.vid()) }
+    }
+
+    /// Check a submission and store it if valid, replacing any earlier response with the same token.
+    /// Submissions are rejected with SurveyClosed once the store is closed, and with SurveyNotOpen
+    /// or SurveyClosed outside of the descriptor's window. Responses that don't answer the
+    /// descriptor's questions are rejected with InvalidResponse, and valid submissions the store
+    /// fails to keep with its error.
+    pub fn check(&mut self, submission: Submission, vk_sa: &VerificationKey, vk_ra: &VerificationKey, params: &PublicParams) -> CheckOutcome {
+
+        let token:G1 = submission.token;
+        let outcome:CheckOutcome = match self.validate(&submission, vk_sa, vk_ra, params) {
+            Err(reason) => CheckOutcome::Rejected(reason),
+            Ok(()) => match self.backend.put(submission, self.clock.now()) {
+                Ok(Some(_)) => CheckOutcome::Replaced,
+                Ok(None)    => CheckOutcome::Accepted,
+                Err(reason) => CheckOutcome::Rejected(reason)
+            }
+        };
+        if self.audit_log_len > 0 {
+            if self.audit_log.len() == self.audit_log_len {
+                self.audit_log.pop_front();
+            }
+            self.audit_log.push_back((token, outcome.clone()));
+        }
+        outcome
+    }
+
+    // Everything a submission must pass to be stored, cheapest first
+    fn validate(&self, submission: &Submission, vk_sa: &VerificationKey, vk_ra: &VerificationKey, params: &PublicParams) -> Result<()> {
+        if self.is_closed() {
+            return Err(Error::SurveyClosed);
+        }
+        if let Some(descriptor) = &self.descriptor {
+            descriptor.check_open(self.clock.now())?;
+        }
+        check(submission, vk_sa, vk_ra, self.vid, self.epoch(), params)?;
+        match &self.descriptor {
+            Some(descriptor)    => descriptor.check_response(&submission.response),
+            None                => Ok(())
+        }
+    }
+
+    /// Number of stored submissions (at most one per token)
+    pub fn count(&self) -> usize {
+        self.stored().len()
+    }
+
+    /// Stored submission for a survey token, if any. Released like the results in tally, for the
+    /// survey with published participant list Lvid: fails with SurveyStillOpen until the survey has
+    /// ended, and TooFewResponses until its minimum number of responses answering its questions
+    /// are stored.
+    #[allow(non_snake_case)]
+    pub fn get(&self, token: G1, Lvid: &ParticipantList) -> Result<Option<&Submission>> {
+        self.released(Lvid)?;
+        Ok(self.backend.get(self.vid, token))
+    }
+
+    /// All stored submissions (at most one per token), released like get
+    #[allow(non_snake_case)]
+    pub fn submissions(&self, Lvid: &ParticipantList) -> Result<Vec<&Submission>> {
+        self.released(Lvid)?;
+        Ok(self.stored())
+    }
+
+    // Stored submissions, whether or not they can be released
+    pub(crate) fn stored(&self) -> Vec<&Submission> {
+        self.backend.submissions(self.vid)
+    }
+
+    // Whether the stored submissions can be released, under the same conditions as the tally:
+    // only responses that answer the descriptor's questions count towards its threshold
+    #[allow(non_snake_case)]
+    fn released(&self, Lvid: &ParticipantList) -> Result<()> {
+        let descriptor:&SurveyDescriptor = tally::ended(self)?;
+        tally::threshold(descriptor, Lvid, tally::responses(self, descriptor).0.len()).map(|_| ())
+    }
+}
+
+
+/*
+ * Unit tests
+ */
+
+#[cfg(test)]
+use crate::users::{User, SurveyAuthority, RegistrationAuthority};
+#[cfg(test)]
+use crate::schema::{self, Answer, Response};
+#[cfg(test)]
+use crate::clock::FixedClock;
+#[cfg(test)]
+use std::{rc::Rc, cell::Cell};
+
+#[test]
+// Test that a resubmission replaces the stored response and invalid submissions are rejected
+fn test_submission_store() {
+    let (params, ra, sa, user, vid, signatures) = zkp::setup_submission();
+    let mut store = SubmissionStore::new(vid);
+
+    let first = user.submit_survey(vid, "yes", &signatures, &sa.vk, &ra.vk, &params).expect("Submission failed");
+    let token:G1 = first.token;
+    assert_eq!( store.check(first, &sa.vk, &ra.vk, &params), CheckOutcome::Accepted );
+
+    let second = user.submit_survey(vid, "no", &signatures, &sa.vk, &ra.vk, &params).expect("Submission failed");
+    assert_eq!( store.check(second, &sa.vk, &ra.vk, &params), CheckOutcome::Replaced );
+    assert_eq!( store.count(), 1 );
+    assert_eq!( store.stored()[0].response.as_str(), "no" );
+
+    // Response swapped after the proof was made
+    let mut tampered = user.submit_survey(vid, "maybe", &signatures, &sa.vk, &ra.vk, &params).expect("Submission failed");
+    tampered.response = String::from("yes");
+    assert_eq!( store.check(tampered, &sa.vk, &ra.vk, &params), CheckOutcome::Rejected(Error::InvalidProof) );
+
+    // Submission for another survey
+    let mut other = user.submit_survey(vid, "yes", &signatures, &sa.vk, &ra.vk, &params).expect("Submission failed");
+    other.vid = vid + Fr::one();
+    assert_eq!( store.check(other, &sa.vk, &ra.vk, &params), CheckOutcome::Rejected(Error::UnknownSurvey) );
+
+    assert_eq!( store.backend.get(vid, token).map(|stored| stored.response.as_str()), Some("no") );
+    assert_eq!( store.audit_log.len(), 4 );
+
+    // Only the latest outcomes are kept in the audit log
+    let mut short = SubmissionStore::new(vid).with_audit_log_len(2);
+    let submission = user.submit_survey(vid, "yes", &signatures, &sa.vk, &ra.vk, &params).expect("Submission failed");
+    assert_eq!( short.check(submission.clone(), &sa.vk, &ra.vk, &params), CheckOutcome::Accepted );
+    assert_eq!( short.close(), Ok(()) );
+    for _ in 0..2 {
+        assert_eq!( short.check(submission.clone(), &sa.vk, &ra.vk, &params), CheckOutcome::Rejected(Error::SurveyClosed) );
+    }
+    assert!( short.audit_log.iter().eq(&[(token, CheckOutcome::Rejected(Error::SurveyClosed)), (token, CheckOutcome::Rejected(Error::SurveyClosed))]) );
+}
+
+#[test]
+// Test that a store with a descriptor only accepts responses that answer its questions
+fn test_submission_schema() {
+    let params = PublicParams::generate();
+    let mut ra = RegistrationAuthority::new(&params);
+    let mut sa:User = SurveyAuthority::new(&params);
+    let mut user = User::new();
+    assert!( user.reg_user(&mut ra).is_ok() );
+    let descriptor = SurveyDescriptor::new("Lunch", schema::sample_questions(), 0, 3600, &sa.vk);
+    let (vid, signatures) = sa.gen_survey_from_descriptor(&descriptor, &ra.registry.ids(), &params, &ra.vk).expect("SA survey creation failed!");
+    let mut store = SubmissionStore::for_descriptor(descriptor).with_clock(FixedClock(1800));
+    assert!( store.vid == vid );
+
+    let response = Response::new(vec![Some(Answer::Choice(0)), Some(Answer::Choices(vec![1])), Some(Answer::Scale(3)), Some(Answer::Number(2)), None]);
+    let valid = user.submit_survey(vid, &response.to_string(), &signatures, &sa.vk, &ra.vk, &params).expect("Submission failed");
+    assert_eq!( store.check(valid, &sa.vk, &ra.vk, &params), CheckOutcome::Accepted );
+
+    // Correctly proven, but not an answer to the questions
+    let invalid = user.submit_survey(vid, "yes", &signatures, &sa.vk, &ra.vk, &params).expect("Submission failed");
+    assert_eq!( store.check(invalid, &sa.vk, &ra.vk, &params), CheckOutcome::Rejected(Error::InvalidResponse) );
+    assert_eq!( store.count(), 1 );
+}
+
+#[test]
+// Test that submissions are only accepted while the survey is open, and not after the SA closes it
+fn test_survey_window() {
+    let params = PublicParams::generate();
+    let mut ra = RegistrationAuthority::new(&params);
+    let mut sa:User = SurveyAuthority::new(&params);
+    let mut user = User::new();
+    assert!( user.reg_user(&mut ra).is_ok() );
+    let descriptor = SurveyDescriptor::new("Lunch", schema::sample_questions(), 1000, 2000, &sa.vk);
+    let (vid, signatures) = sa.gen_survey_from_descriptor(&descriptor, &ra.registry.ids(), &params, &ra.vk).expect("SA survey creation failed!");
+
+    let time = Rc::new(Cell::new(999));
+    let clock = { let time = time.clone(); move || time.get() };
+    let mut store = SubmissionStore::for_descriptor(descriptor.clone()).with_clock(clock);
+    let response:String = Response::new(vec![Some(Answer::Choice(0)), Some(Answer::Choices(vec![1])), Some(Answer::Scale(3)), Some(Answer::Number(2)), None]).to_string();
+    let vk_sa:VerificationKey = sa.vk;
+    let submit = |clock| user.submit_described_survey(&descriptor, &response, &signatures, &vk_sa, &ra.vk, &params, &clock);
+
+    // Users refuse to submit outside of the window, and so does the store
+    assert_eq!( submit(FixedClock(999)), Err(Error::SurveyNotOpen) );
+    assert_eq!( submit(FixedClock(2000)), Err(Error::SurveyClosed) );
+    assert_eq!( user.submit_described_survey(&descriptor, "yes", &signatures, &sa.vk, &ra.vk, &params, &FixedClock(1500)), Err(Error::InvalidResponse) );
+    let early = user.submit_survey(vid, &response, &signatures, &sa.vk, &ra.vk, &params).expect("Submission failed");
+    assert_eq!( store.check(early.clone(), &sa.vk, &ra.vk, &params), CheckOutcome::Rejected(Error::SurveyNotOpen) );
+    time.set(1000);
+    assert_eq!( store.check(early.clone(), &sa.vk, &ra.vk, &params), CheckOutcome::Accepted );
+    time.set(2000);
+    assert_eq!( store.check(early.clone(), &sa.vk, &ra.vk, &params), CheckOutcome::Rejected(Error::SurveyClosed) );
+
+    // Closing early freezes the results, and the list can't be amended anymore
+    time.set(1500);
+    let mut other:User = SurveyAuthority::new(&params);
+    assert_eq!( other.close_survey(&mut SubmissionStore::new(vid)), Err(Error::UnknownSurvey) );
+    assert_eq!( sa.close_survey(&mut store), Ok(()) );
+    assert_eq!( store.closed_at(), Some(1500) );
+    let on_time = submit(FixedClock(1500)).expect("Submission failed");
+    assert_eq!( store.check(on_time, &sa.vk, &ra.vk, &params), CheckOutcome::Rejected(Error::SurveyClosed) );
+    assert_eq!( store.count(), 1 );
+    assert_eq!( sa.add_participants(vid, &[Fr::one()], &params, &ra.vk), Err(Error::SurveyClosed) );
+}
+
+#[test]
+// Test that stored submissions are only read out once the survey has ended with enough responses
+fn test_submission_export() {
+    let params = PublicParams::generate();
+    let mut ra = RegistrationAuthority::new(&params);
+    let mut sa:User = SurveyAuthority::new(&params);
+    let mut users:Vec<User> = (0..2).map(|_| User::new()).collect();
+    for user in users.iter_mut() {
+        assert!( user.reg_user(&mut ra).is_ok() );
+    }
+    let descriptor = SurveyDescriptor::new("Lunch", schema::sample_questions(), 0, 3600, &sa.vk).with_min_responses(2);
+    let (vid, signatures) = sa.gen_survey_from_descriptor(&descriptor, &ra.registry.ids(), &params, &ra.vk).expect("SA survey creation failed!");
+    let mut store = SubmissionStore::for_descriptor(descriptor).with_clock(FixedClock(0));
+    let response:String = Response::new(vec![Some(Answer::Choice(0)), Some(Answer::Choices(vec![1])), Some(Answer::Scale(3)), Some(Answer::Number(2)), None]).to_string();
+
+    let first = users[0].submit_survey(vid, &response, &signatures, &sa.vk, &ra.vk, &params).expect("Submission failed");
+    let token:G1 = first.token;
+    assert_eq!( store.check(first, &sa.vk, &ra.vk, &params), CheckOutcome::Accepted );
+    assert_eq!( store.submissions(&signatures).map(|submissions| submissions.len()), Err(Error::SurveyStillOpen) );
+
+    // Closed with a single response: below the threshold, nothing is read out
+    let mut closed = SubmissionStore::for_descriptor(store.descriptor.clone().unwrap()).with_clock(FixedClock(0));
+    assert_eq!( closed.check(store.stored()[0].clone(), &sa.vk, &ra.vk, &params), CheckOutcome::Accepted );
+    assert_eq!( closed.close(), Ok(()) );
+    assert_eq!( closed.count(), 1 );
+    assert_eq!( closed.submissions(&signatures).map(|submissions| submissions.len()), Err(Error::TooFewResponses { responses: 1, required: 2 }) );
+    assert_eq!( closed.get(token, &signatures).map(|stored| stored.is_some()), Err(Error::TooFewResponses { responses: 1, required: 2 }) );
+
+    // Submissions stored before the store had a descriptor only count if they answer its questions
+    let mut undescribed = SubmissionStore::new(vid).with_clock(FixedClock(0));
+    let unanswered = users[1].submit_survey(vid, "yes", &signatures, &sa.vk, &ra.vk, &params).expect("Submission failed");
+    assert_eq!( undescribed.check(store.stored()[0].clone(), &sa.vk, &ra.vk, &params), CheckOutcome::Accepted );
+    assert_eq!( undescribed.check(unanswered, &sa.vk, &ra.vk, &params), CheckOutcome::Accepted );
+    undescribed.descriptor = store.descriptor.clone();
+    assert_eq!( undescribed.close(), Ok(()) );
+    assert_eq!( undescribed.count(), 2 );
+    assert_eq!( undescribed.submissions(&signatures).map(|submissions| submissions.len()), Err(Error::TooFewResponses { responses: 1, required: 2 }) );
+    assert_eq!( tally::tally(&undescribed, &signatures).map(|report| report.responses), Err(Error::TooFewResponses { responses: 1, required: 2 }) );
+
+    let second = users[1].submit_survey(vid, &response, &signatures, &sa.vk, &ra.vk, &params).expect("Submission failed");
+    assert_eq!( store.check(second, &sa.vk, &ra.vk, &params), CheckOutcome::Accepted );
+    assert_eq!( store.close(), Ok(()) );
+    assert_eq!( store.submissions(&signatures).map(|submissions| submissions.len()), Ok(2) );
+    assert_eq!( store.get(token, &signatures).map(|stored| stored.map(|stored| stored.response.clone())), Ok(Some(response)) );
+    #[cfg(feature = "serde")]
+    assert_eq!( serde_json::to_value(store.submissions(&signatures).unwrap()).unwrap().as_array().map(|submissions| submissions.len()), Some(2) );
+}
@@ -0,0 +1,230 @@
+extern crate tbn;
+extern crate rand;
+extern crate sha2;
+
+use tbn::{Group, Fr, G1, G2, Gt, pairing};
+use sha2::{Sha512, Digest};
+use crate::users::{VerificationKey, MasterCredential};
+use rand::{RngCore, CryptoRng};
+use crate::params::PublicParams;
+use crate::encoding::Encode;
+use crate::survey;
+use crate::error::{Error, Result};
+
+/*
+ * ----------------------------------------------
+ * |    SURVEY SUBMISSION PROOF                 |
+ * ----------------------------------------------
+ *
+ * Non-interactive (Fiat-Shamir) zero-knowledge proof that a survey token
+ *      T = g^(1/(s + vid))
+ * was derived from the seed s of a master credential signed by the RA on (id, s), and that the
+ * SA signed (m, id) for the same hidden id, where m = signed_vid(vid, epoch) for the epoch of the
+ * survey list. The prover re-randomizes and blinds both signatures, then proves knowledge of
+ * (id, s, a, b) such that:
+ *      e(S1, g2) / (pk_RA * e(h_RA, sigma_2'))        = e(v_RA^id * u_RA^s, sigma_2') * e(g, g2)^a
+ *      e(S2, g2) / (pk_SA * e(u_SA^m * h_RA, tau_2')) = e(v_SA^id, tau_2') * e(g, g2)^b
+ *      T^s                                            = g * T^(-vid)
+ * The survey response is hashed into the challenge, so the proof also authenticates it.
+ *
+ * Authorities also sign public statements (such as survey descriptors) with a Schnorr signature
+ * in Gt, proving knowledge of their secret key sk for pk = e(g, g2)^sk:
+ *      c = H(vk, e(g, g2)^k, message),  z = k + c * sk
+*/
+
+/// Proof attached to a survey submission
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SurveyProof {
+    /// Blinded and re-randomized master credential (S1, sigma_2')
+    pub cred_1: G1,
+    pub cred_2: G2,
+    /// Blinded and re-randomized SA signature for the hidden id (S2, tau_2')
+    pub sig_1: G1,
+    pub sig_2: G2,
+    /// Fiat-Shamir challenge
+    pub c: Fr,
+    /// Responses for id, s and the two blinding factors
+    pub z_id: Fr,
+    pub z_seed: Fr,
+    pub z_a: Fr,
+    pub z_b: Fr
+}
+
+/// Deterministic survey token for a seed and survey (Dodis-Yampolskiy PRF)
+pub fn survey_token(g: G1, seed: Fr, vid: Fr) -> Option<G1> {
+    (seed + vid).inverse().map(|exp| g * exp)
+}
+
+/// Produce the survey token and a proof of credential possession for a survey response
+#[allow(clippy::too_many_arguments)]
+pub fn prove_submission<R: RngCore + CryptoRng>(params: &PublicParams, vk_ra: &VerificationKey, vk_sa: &VerificationKey, vid: Fr, epoch: u64, id: Fr, seed: Fr,
+                                                cred: &MasterCredential, sig: (G1, G2), response: &str, rng: &mut R) -> Result<(G1, SurveyProof)> {
+
+    let (g, g2):(G1, G2) = (params.g, params.g2);
+
+    let token:G1 = survey_token(g, seed, vid).ok_or(Error::InvalidProof)?;
+    let m:Fr = survey::signed_vid(vid, epoch);
+
+    // Re-randomize the master credential and blind its first component with g^a
+    let (r, a):(Fr, Fr) = (Fr::random(rng), Fr::random(rng));
+    let cred_2:G2 = cred.sigma_2 + g2 * r;
+    let cred_1:G1 = cred.sigma_1 + (vk_ra.v * id + vk_ra.u * seed + vk_ra.h) * r + g * a;
+
+    // Same for the SA's signature on (m, id), blinded with g^b
+    let (rho, b):(Fr, Fr) = (Fr::random(rng), Fr::random(rng));
+    let sig_2:G2 = sig.1 + g2 * rho;
+    let sig_1:G1 = sig.0 + (vk_sa.u * m + vk_sa.v * id + vk_ra.h) * rho + g * b;
+
+    // Commit to nonces for each witness
+    let (k_id, k_seed, k_a, k_b):(Fr, Fr, Fr, Fr) = (Fr::random(rng), Fr::random(rng), Fr::random(rng), Fr::random(rng));
+    let r_cred:Gt = pairing(vk_ra.v * k_id + vk_ra.u * k_seed, cred_2) * params.gt.pow(k_a);
+    let r_sig:Gt = pairing(vk_sa.v * k_id, sig_2) * params.gt.pow(k_b);
+    let r_token:G1 = token * k_seed;
+
+    let c:Fr = challenge(params, vk_ra, vk_sa, (vid, epoch), token, (cred_1, cred_2, sig_1, sig_2), (r_cred, r_sig, r_token), response);
+
+    let proof = SurveyProof {
+        cred_1, cred_2, sig_1, sig_2, c,
+        z_id: k_id + c * id,
+        z_seed: k_seed + c * seed,
+        z_a: k_a + c * a,
+        z_b: k_b + c * b
+    };
+    Ok((token, proof))
+}
+
+/// Verify a proof produced by prove_submission for the given token and response
+#[allow(clippy::too_many_arguments)]
+pub fn verify_submission(params: &PublicParams, vk_ra: &VerificationKey, vk_sa: &VerificationKey, vid: Fr, epoch: u64, token: G1,
+                         response: &str, proof: &SurveyProof) -> bool {
+
+    let (g, g2):(G1, G2) = (params.g, params.g2);
+
+    if token.is_zero() || proof.cred_2.is_zero() || proof.sig_2.is_zero() {
+        return false;
+    }
+    let c:Fr = proof.c;
+    let m:Fr = survey::signed_vid(vid, epoch);
+
+    // Recompute the prover's commitments from the responses and the challenge
+    let r_cred:Gt = pairing(vk_ra.v * proof.z_id + vk_ra.u * proof.z_seed + vk_ra.h * c, proof.cred_2)
+        * pairing(g * proof.z_a - proof.cred_1 * c, g2)
+        * vk_ra.pk.pow(c);
+    let r_sig:Gt = pairing(vk_sa.v * proof.z_id + (vk_sa.u * m + vk_ra.h) * c, proof.sig_2)
+        * pairing(g * proof.z_b - proof.sig_1 * c, g2)
+        * vk_sa.pk.pow(c);
+    let r_token:G1 = token * proof.z_seed - (g - token * vid) * c;
+
+    c == challenge(params, vk_ra, vk_sa, (vid, epoch), token, (proof.cred_1, proof.cred_2, proof.sig_1, proof.sig_2), (r_cred, r_sig, r_token), response)
+}
+
+// Fiat-Shamir challenge over the full statement, the prover's commitments and the response
+#[allow(clippy::too_many_arguments)]
+fn challenge(params: &PublicParams, vk_ra: &VerificationKey, vk_sa: &VerificationKey, (vid, epoch): (Fr, u64), token: G1,
+             blinded: (G1, G2, G1, G2), commitments: (Gt, Gt, G1), response: &str) -> Fr {
+
+    let mut transcript:Vec<u8> = b"anon_survey/submit_survey".to_vec();
+    params.fingerprint.encode_to(&mut transcript);
+    vk_ra.encode_to(&mut transcript);
+    vk_sa.encode_to(&mut transcript);
+    vid.encode_to(&mut transcript);
+    epoch.encode_to(&mut transcript);
+    token.encode_to(&mut transcript);
+    blinded.0.encode_to(&mut transcript);
+    blinded.1.encode_to(&mut transcript);
+    blinded.2.encode_to(&mut transcript);
+    blinded.3.encode_to(&mut transcript);
+    commitments.0.encode_to(&mut transcript);
+    commitments.1.encode_to(&mut transcript);
+    commitments.2.encode_to(&mut transcript);
+    response.encode_to(&mut transcript);
+
+    let mut hasher = Sha512::new();
+    hasher.update(&transcript);
+
+    let mut digest:[u8; 64] = [0; 64];
+    digest.copy_from_slice(&hasher.finalize());
+    Fr::interpret(&digest)
+}
+
+
+/// Schnorr signature on a message under the key pk = e(g, g2)^sk of an authority
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct KeySignature {
+    pub c: Fr,
+    pub z: Fr
+}
+
+/// Sign message with the secret key sk of the authority with key vk
+pub fn sign_with_key<R: RngCore + CryptoRng>(params: &PublicParams, vk: &VerificationKey, sk: Fr, message: &[u8], rng: &mut R) -> KeySignature {
+    let k:Fr = Fr::random(rng);
+    let c:Fr = key_challenge(params, vk, params.gt.pow(k), message);
+    KeySignature { c, z: k + c * sk }
+}
+
+/// Verify a signature produced by sign_with_key
+pub fn verify_with_key(params: &PublicParams, vk: &VerificationKey, message: &[u8], sig: &KeySignature) -> bool {
+    let commitment:Gt = params.gt.pow(sig.z) * vk.pk.pow(-sig.c);
+    sig.c == key_challenge(params, vk, commitment, message)
+}
+
+// Fiat-Shamir challenge of a key signature
+fn key_challenge(params: &PublicParams, vk: &VerificationKey, commitment: Gt, message: &[u8]) -> Fr {
+    let mut transcript:Vec<u8> = b"anon_survey/key_signature".to_vec();
+    params.fingerprint.encode_to(&mut transcript);
+    vk.encode_to(&mut transcript);
+    commitment.encode_to(&mut transcript);
+    message.encode_to(&mut transcript);
+
+    let mut digest:[u8; 64] = [0; 64];
+    digest.copy_from_slice(&Sha512::digest(&transcript));
+    Fr::interpret(&digest)
+}
+
+
+/*
+ * Unit tests
+ */
+
+#[cfg(test)]
+use crate::users::{User, SurveyAuthority, RegistrationAuthority};
+#[cfg(test)]
+use crate::survey::ParticipantList;
+
+#[cfg(test)]
+// Register a user, create a survey for them and return everything needed to submit
+pub(crate) fn setup_submission() -> (PublicParams, RegistrationAuthority, User, User, Fr, ParticipantList) {
+    let params = PublicParams::generate();
+    let mut ra = RegistrationAuthority::new(&params);
+    let mut sa:User = SurveyAuthority::new(&params);
+    let mut user = User::new();
+    assert!( user.reg_user(&mut ra).is_ok() );
+    let (vid, signatures) = sa.gen_survey(&ra.registry.ids(), &params, &ra.vk).expect("SA survey creation failed!");
+    (params, ra, sa, user, vid, signatures)
+}
+
+#[test]
+// Test that an honestly generated submission verifies, and only for its own response and survey
+fn test_submission_proof() {
+    let (params, ra, sa, user, vid, signatures) = setup_submission();
+    let submission = user.submit_survey(vid, "yes", &signatures, &sa.vk, &ra.vk, &params).expect("Submission failed");
+
+    assert!( verify_submission(&params, &ra.vk, &sa.vk, vid, 0, submission.token, "yes", &submission.proof) );
+    assert!( !verify_submission(&params, &ra.vk, &sa.vk, vid, 0, submission.token, "no", &submission.proof) );
+    assert!( !verify_submission(&params, &ra.vk, &sa.vk, vid, 1, submission.token, "yes", &submission.proof) );
+    assert!( !verify_submission(&params, &ra.vk, &sa.vk, vid + Fr::one(), 0, submission.token, "yes", &submission.proof) );
+    assert!( !verify_submission(&params, &ra.vk, &sa.vk, vid, 0, submission.token * Fr::from_str("2").unwrap(), "yes", &submission.proof) );
+    // Keys swapped between RA and SA
+    assert!( !verify_submission(&params, &sa.vk, &ra.vk, vid, 0, submission.token, "yes", &submission.proof) );
+}
+
+#[test]
+// Test that the same user always gets the same token for a survey, but fresh proofs
+fn test_token_deterministic() {
+    let (params, ra, sa, user, vid, signatures) = setup_submission();
+    let first = user.submit_survey(vid, "yes", &signatures, &sa.vk, &ra.vk, &params).expect("Submission failed");
+    let second = user.submit_survey(vid, "no", &signatures, &sa.vk, &ra.vk, &params).expect("Submission failed");
+
+    assert!( first.token == second.token );
+    assert!( first.proof.cred_1 != second.proof.cred_1 );
+}